@@ -18,6 +18,14 @@ pub enum Command {
     Hls {
         #[structopt(help = "HLS 'Master Manifest' URL")]
         manifest: Url,
+        #[structopt(long = "delta-updates", help = "Request Playlist Delta Updates from servers that support them")]
+        delta_updates: bool,
+        #[structopt(long = "vod", help = "Check each media playlist once, as a complete VOD playlist")]
+        vod: bool,
+        #[structopt(long = "main-reload-interval", help = "Reload the main manifest every given number of seconds, checking for changes")]
+        main_reload_interval: Option<u64>,
+        #[structopt(long = "fetch-segments", help = "Download media segments as they appear in media playlists")]
+        fetch_segments: bool,
     },
     #[structopt(name = "dash", about = "Check a 'Dynamic Adaptive Streaming over HTTP' manifest")]
     Dash {
@@ -3,9 +3,8 @@ use crate::{http_snoop, NullSnoop};
 use futures::prelude::*;
 use mp4parse::{read_mp4, MediaContext};
 use std::io;
-use std::convert::TryFrom;
-use h264_reader::nal::pps::ParamSetId;
 use reqwest::Error;
+use serde_derive::Serialize;
 use strck::http_snoop::BodyError;
 
 mod mpd {
@@ -207,12 +206,35 @@ impl From<http_snoop::Error> for DashManifestError {
         DashManifestError::NHttp(e)
     }
 }
-pub struct DashCheck<L: EventSink> {
+/// Problems found in the media of a DASH presentation
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "event_name")]
+pub enum DashEvent {
+    /// The resolution, profile or level of the video changed from one segment to the next
+    VideoParametersChanged {
+        url: String,
+        prev_url: String,
+        last_codec: String,
+        this_codec: String,
+        last_resolution: String,
+        this_resolution: String,
+    },
+    /// The content of a picture parameter set changed from one segment to the next, while keeping
+    /// the same `pic_parameter_set_id`
+    VideoPictureParametersChanged {
+        url: String,
+        prev_url: String,
+        pps_id: u32,
+    },
+}
+
+pub struct DashCheck<L: EventSink<Extra = DashEvent>> {
     client: http_snoop::Client<NullSnoop>,
     url: reqwest::Url,
     log: L,
 }
-impl<L: EventSink> DashCheck<L> {
+impl<L: EventSink<Extra = DashEvent>> DashCheck<L> {
     pub fn new(client: http_snoop::Client<NullSnoop>, url: reqwest::Url, log: L) -> DashCheck<L> {
         DashCheck {
             client,
@@ -241,12 +263,11 @@ impl<L: EventSink> DashCheck<L> {
                         })
                         .collect();
                     let segs = segs?;
-                    println!("Will check {:?}", r.id);
                     repr_checks.push(RepresentataionCheck { id: r.id.clone(), init, segs });
                 }
             }
         }
-        check_all(client, repr_checks).map_err(|e| panic!("{:?}", e) ).await
+        check_all(client, self.log.clone(), repr_checks).map_err(|e| panic!("{:?}", e) ).await
     }
     pub async fn load_mpd(&self) -> Result<mpd::Mpd, DashManifestError> {
         let resp = self.client.get(self.url.clone()).send().await?;
@@ -285,19 +306,19 @@ impl From<http_snoop::Error> for DashMediaError {
     }
 }
 
-async fn check_all(client: http_snoop::Client<NullSnoop>, repr_checks: Vec<RepresentataionCheck>) -> Result<(), DashMediaError> {
+async fn check_all<L: EventSink<Extra = DashEvent>>(client: http_snoop::Client<NullSnoop>, log: L, repr_checks: Vec<RepresentataionCheck>) -> Result<(), DashMediaError> {
     futures::stream::iter(repr_checks)
         .map(Ok)
-        .try_for_each(move |check| check_repr(client.clone(), check)).await
+        .try_for_each(move |check| check_repr(client.clone(), log.clone(), check)).await
 }
 
-async fn check_repr(client: http_snoop::Client<NullSnoop>, check: RepresentataionCheck) -> Result<(), DashMediaError> {
+async fn check_repr<L: EventSink<Extra = DashEvent>>(client: http_snoop::Client<NullSnoop>, log: L, check: RepresentataionCheck) -> Result<(), DashMediaError> {
     let cl = client.clone();
     let (init_data, init) = get_init(client, check.init.clone()).await?;
     // TODO: support multiplexed media (multiple tracks) later
     if init.tracks.len() == 1 {
         match init.tracks[0].track_type {
-            mp4parse::TrackType::Audio | mp4parse::TrackType::Video => check_track(cl, init_data, init, check).await,
+            mp4parse::TrackType::Audio | mp4parse::TrackType::Video => check_track(cl, log, init_data, init, check).await,
             _ => unimplemented!("track type {:?}", init.tracks[0].track_type),
         }
     } else {
@@ -331,31 +352,15 @@ async fn get_seg(client: http_snoop::Client<NullSnoop>, init_data: &[u8], url: r
         })
 }
 
-async fn check_track(client: http_snoop::Client<NullSnoop>, init_data: bytes::Bytes, init: MediaContext, check: RepresentataionCheck) -> Result<(), DashMediaError> {
-    let track = &init.tracks[0];
-    match &track.stsd.as_ref().unwrap().descriptions[0] {
-        mp4parse::SampleEntry::Audio(aud) => {
-            println!("Audio: {:?}", aud);
-        },
-        mp4parse::SampleEntry::Video(vid) => {
-            match &vid.codec_specific {
-                mp4parse::VideoCodecSpecific::AVCConfig(avc) => {
-                    let avcc = h264_reader::avcc::AvcDecoderConfigurationRecord::try_from(&avc[..]).unwrap(/*TODO*/);
-                    let ctx = avcc.create_context(()).unwrap(/*TODO*/);
-                    println!("  Profile indication {:?}", avcc.avc_profile_indication());
-                    let sps = ctx.sps_by_id(ParamSetId::from_u32(0).unwrap());
-                    println!("  {:#?}", sps);
-                    let pps = ctx.pps_by_id(ParamSetId::from_u32(0).unwrap());
-                    println!("  {:#?}", pps);
-                },
-                _ => println!("Unhandled VideoCodecSpecific")
-            }
-        },
-        mp4parse::SampleEntry::Unknown => println!("SampleEntry::Unknown"),
-    }
+async fn check_track<L: EventSink<Extra = DashEvent>>(client: http_snoop::Client<NullSnoop>, mut log: L, init_data: bytes::Bytes, init: MediaContext, check: RepresentataionCheck) -> Result<(), DashMediaError> {
+    let params_init = strck::mp4::parse_init(&init_data).ok();
+    let mut last_params: Option<(reqwest::Url, strck::video::ParameterSets)> = None;
     for seg_url in check.segs.into_iter().take(5) {
-        println!("seg {}", seg_url);
-        let (seg_ctx, seg_data) = get_seg(client.clone(), &init_data[..], seg_url).await?;
+        let (seg_ctx, seg_data) = get_seg(client.clone(), &init_data[..], seg_url.clone()).await?;
+        if let Some(params) = params_init.as_ref().and_then(|init| segment_parameter_sets(init, &seg_data) ) {
+            check_parameter_sets(&mut log, last_params.as_ref(), &seg_url, &params);
+            last_params = Some((seg_url, params));
+        }
         if seg_ctx.tracks.len() == 1 {
             let track_idx = 0;
             match seg_ctx.tracks[track_idx].track_type {
@@ -369,6 +374,38 @@ async fn check_track(client: http_snoop::Client<NullSnoop>, init_data: bytes::By
     Ok(())
 }
 
+/// The video parameter sets in effect for a segment, given the segment data prefixed with the init
+/// segment (as returned by `get_seg()`)
+fn segment_parameter_sets(init: &strck::mp4::InitSegment, data: &[u8]) -> Option<strck::video::ParameterSets> {
+    let media = strck::mp4::parse_media(data, init).ok()?;
+    strck::mp4::video_parameter_sets(init, &media, data)
+}
+
+/// Reports changes to the video parameter sets from one segment to the next
+fn check_parameter_sets<L: EventSink<Extra = DashEvent>>(log: &mut L, last: Option<&(reqwest::Url, strck::video::ParameterSets)>, url: &reqwest::Url, params: &strck::video::ParameterSets) {
+    let (last_url, last) = match last {
+        Some((u, p)) => (u, p),
+        None => return,
+    };
+    if params.sequence_changed(last) {
+        log.error(DashEvent::VideoParametersChanged {
+            url: url.to_string(),
+            prev_url: last_url.to_string(),
+            last_codec: last.sps.codecs.clone(),
+            this_codec: params.sps.codecs.clone(),
+            last_resolution: last.sps.resolution(),
+            this_resolution: params.sps.resolution(),
+        })
+    }
+    for pps_id in params.changed_pictures(last) {
+        log.warning(DashEvent::VideoPictureParametersChanged {
+            url: url.to_string(),
+            prev_url: last_url.to_string(),
+            pps_id,
+        })
+    }
+}
+
 struct SampleToChunk {
     first_sample: u32,
     first_chunk: u32,
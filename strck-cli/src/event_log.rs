@@ -1,6 +1,7 @@
 use serde::Serialize;
 use strck::event_log::EventSink;
-use strck::hls::HlsEvent;
+use strck::hls::{HlsEvent, Delta, ManifestRef};
+use strck::http_snoop::HttpRef;
 
 pub struct StderrLog {
 }
@@ -31,28 +32,150 @@ impl EventSink for StderrLog {
 }
 
 impl StderrLog {
+    /// print a diagnostic showing the given byte range of the response body
+    fn emit_snippet(req: &HttpRef, start: usize, end: usize, level: codemap_diagnostic::Level, message: &str, code: &str, label: &str) {
+        let mut map = codemap::CodeMap::new();
+        let file = map.add_file(req.info().url.to_string(), String::from_utf8_lossy(req.info().response.as_ref().unwrap().body.as_ref().unwrap().data.as_ref()).into_owned()).span;
+        let whence = file.subspan(start as u64, end as u64);
+        let label = codemap_diagnostic::SpanLabel {
+            span: whence,
+            style: codemap_diagnostic::SpanStyle::Primary,
+            label: Some(label.to_owned())
+        };
+        let d = codemap_diagnostic::Diagnostic {
+            level,
+            message: message.to_owned(),
+            code: Some(code.to_owned()),
+            spans: vec![label]
+        };
+        let mut emitter = codemap_diagnostic::Emitter::stderr(codemap_diagnostic::ColorConfig::Auto, Some(&map));
+        emitter.emit(&[d]);
+    }
+
+    /// print a diagnostic showing the lines of the before and after playlists that an event
+    /// relates to
+    fn emit_delta(delta: &Delta, level: codemap_diagnostic::Level, message: &str, code: &str, before_label: &str, after_label: &str) {
+        let mut map = codemap::CodeMap::new();
+        let mut spans = vec![];
+        for (manifest_ref, label, style) in &[
+            (delta.before(), before_label, codemap_diagnostic::SpanStyle::Secondary),
+            (delta.after(), after_label, codemap_diagnostic::SpanStyle::Primary),
+        ] {
+            let req = manifest_ref.req_id();
+            let body = req.info().response.as_ref().ok().and_then(|r| r.body.as_ref().ok() ).map(|b| String::from_utf8_lossy(b.data.as_ref()).into_owned() );
+            if let (Some(line), Some(body)) = (manifest_ref.line(), body) {
+                let file = map.add_file(req.info().url.to_string(), body);
+                if line > 0 && line <= file.num_lines() {
+                    spans.push(codemap_diagnostic::SpanLabel {
+                        span: file.line_span(line - 1),
+                        style: *style,
+                        label: Some(label.to_string()),
+                    });
+                }
+            }
+        }
+        if spans.is_empty() {
+            eprintln!("{}[{}]: {}", level, code, message);
+            eprintln!(" --> {}", delta.after().req_id().info().url);
+            return;
+        }
+        let d = codemap_diagnostic::Diagnostic {
+            level,
+            message: message.to_owned(),
+            code: Some(code.to_owned()),
+            spans,
+        };
+        let mut emitter = codemap_diagnostic::Emitter::stderr(codemap_diagnostic::ColorConfig::Auto, Some(&map));
+        emitter.emit(&[d]);
+    }
+
+    /// print a diagnostic showing the line of a playlist that an event relates to
+    fn emit_at(at: &ManifestRef, level: codemap_diagnostic::Level, message: &str, code: &str, label: &str) {
+        let req = at.req_id();
+        let body = req.info().response.as_ref().ok().and_then(|r| r.body.as_ref().ok() ).map(|b| String::from_utf8_lossy(b.data.as_ref()).into_owned() );
+        let mut map = codemap::CodeMap::new();
+        let mut spans = vec![];
+        if let (Some(line), Some(body)) = (at.line(), body) {
+            let file = map.add_file(req.info().url.to_string(), body);
+            if line > 0 && line <= file.num_lines() {
+                spans.push(codemap_diagnostic::SpanLabel {
+                    span: file.line_span(line - 1),
+                    style: codemap_diagnostic::SpanStyle::Primary,
+                    label: Some(label.to_string()),
+                });
+            }
+        }
+        if spans.is_empty() {
+            eprintln!("{}[{}]: {}", level, code, message);
+            eprintln!(" --> {}", req.info().url);
+            return;
+        }
+        let d = codemap_diagnostic::Diagnostic {
+            level,
+            message: message.to_owned(),
+            code: Some(code.to_owned()),
+            spans,
+        };
+        let mut emitter = codemap_diagnostic::Emitter::stderr(codemap_diagnostic::ColorConfig::Auto, Some(&map));
+        emitter.emit(&[d]);
+    }
+
     fn print(&mut self, data: HlsEvent) {
         eprintln!("Urk: {:?}", serde_json::to_string(&data));
         match data {
             HlsEvent::LoadedMain { req_id, variant_count } => {}
-            HlsEvent::MsnGoneBackwards { .. } => {}
+            HlsEvent::MsnGoneBackwards { delta, last_msn, this_msn } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("media sequence number went backwards from {} to {}", last_msn, this_msn), "msn_gone_backwards", "earlier value", "later value");
+            }
             HlsEvent::EndListTagRemoved => {}
-            HlsEvent::UnexpectedPlaylistPropertyAddition { .. } => {}
-            HlsEvent::UnexpectedPlaylistPropertyRemoval { .. } => {}
-            HlsEvent::TargetDurationChanged { .. } => {}
-            HlsEvent::PlaylistTypeChanged { .. } => {}
-            HlsEvent::ManifestHistoryChangedUri { .. } => {}
-            HlsEvent::ManifestHistoryAddedDiscontinuity { .. } => {}
-            HlsEvent::ManifestHistoryRemovedDiscontinuity { .. } => {}
-            HlsEvent::ManifestHistoryChangedSegmentDuration { .. } => {}
-            HlsEvent::ManifestHistoryChangedSegmentByterange { .. } => {}
-            HlsEvent::LiveSegmentsRemoved { .. } => {}
-            HlsEvent::ManifestStale { .. } => {}
+            HlsEvent::UnexpectedPlaylistPropertyAddition { delta, name } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("{} added to playlist", name), "unexpected_playlist_property_addition", "earlier playlist", "added");
+            }
+            HlsEvent::UnexpectedPlaylistPropertyRemoval { delta, name } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("{} removed from playlist", name), "unexpected_playlist_property_removal", "earlier value", "later playlist");
+            }
+            HlsEvent::TargetDurationChanged { delta, last_target_duration_millis, this_target_duration_millis } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("target duration changed from {}ms to {}ms", last_target_duration_millis, this_target_duration_millis), "target_duration_changed", "earlier value", "later value");
+            }
+            HlsEvent::PlaylistTypeChanged { delta, last_type, this_type } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("playlist type changed from {:?} to {:?}", last_type, this_type), "playlist_type_changed", "earlier value", "later value");
+            }
+            HlsEvent::ManifestHistoryChangedUri { delta, msn, .. } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("URI of segment {} changed", msn), "manifest_history_changed_uri", "earlier URI", "later URI");
+            }
+            HlsEvent::ManifestHistoryAddedDiscontinuity { delta, msn } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("discontinuity added before existing segment {}", msn), "manifest_history_added_discontinuity", "segment without discontinuity", "discontinuity added");
+            }
+            HlsEvent::ManifestHistoryRemovedDiscontinuity { delta, msn } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("discontinuity removed from before existing segment {}", msn), "manifest_history_removed_discontinuity", "discontinuity", "segment without discontinuity");
+            }
+            HlsEvent::ManifestHistoryChangedSegmentDuration { delta, msn, .. } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("duration of segment {} changed", msn), "manifest_history_changed_segment_duration", "earlier duration", "later duration");
+            }
+            HlsEvent::ManifestHistoryChangedSegmentByterange { delta, msn, .. } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("byte range of segment {} changed", msn), "manifest_history_changed_segment_byterange", "earlier byte range", "later byte range");
+            }
+            HlsEvent::ManifestHistoryChangedMap { delta, msn, .. } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("EXT-X-MAP of segment {} changed", msn), "manifest_history_changed_map", "earlier EXT-X-MAP", "later EXT-X-MAP");
+            }
+            HlsEvent::MapChangedWithoutDiscontinuity { at, msn, .. } => {
+                Self::emit_at(&at, codemap_diagnostic::Level::Error, &format!("EXT-X-MAP changed at segment {} without EXT-X-DISCONTINUITY", msn), "map_changed_without_discontinuity", "new EXT-X-MAP");
+            }
+            HlsEvent::LiveSegmentsRemoved { delta, removed_count, .. } => {
+                let level = if removed_count > 1 { codemap_diagnostic::Level::Error } else { codemap_diagnostic::Level::Warning };
+                Self::emit_delta(&delta, level, &format!("{} segment(s) removed from live playlist", removed_count), "live_segments_removed", "earlier media sequence", "later media sequence");
+            }
+            HlsEvent::ManifestStale { delta, since_list_update } => {
+                let level = if since_list_update > 2 { codemap_diagnostic::Level::Error } else { codemap_diagnostic::Level::Warning };
+                Self::emit_delta(&delta, level, &format!("playlist not updated in {} reloads", since_list_update), "manifest_stale", "last update", "stale playlist");
+            }
             HlsEvent::End { .. } => {}
             HlsEvent::SlowMediaManifestResponse { .. } => {}
             HlsEvent::CachedTooLong { .. } => {}
             HlsEvent::IncorrectContentType { .. } => {}
-            HlsEvent::ContentTypeChanged { .. } => {}
+            HlsEvent::ContentTypeChanged { delta, last_content_type, this_content_type } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Warning, &format!("Content-Type changed from {:?} to {:?}", last_content_type, this_content_type), "content_type_changed", "earlier response", "later response");
+            }
             HlsEvent::LastModifiedInFuture { .. } => {}
             HlsEvent::HttpErrorStatus { .. } => {}
             HlsEvent::HttpTimeout { req_id } => {
@@ -61,23 +184,15 @@ impl StderrLog {
                 eprintln!("     Note: request id {:?}", blob_uuid::to_blob(&req_id.id()));
             }
             HlsEvent::MediaPlaylistWithoutExtinf { req_id, url, start, end } => {
-                let mut map = codemap::CodeMap::new();
-                let req = req_id;
-                let file = map.add_file(req.info().url.to_string(), String::from_utf8_lossy(req.info().response.as_ref().unwrap().body.as_ref().unwrap().data.as_ref()).into_owned()).span;
-                let whence = file.subspan(start as u64, end as u64);
-                let label = codemap_diagnostic::SpanLabel {
-                    span: whence,
-                    style: codemap_diagnostic::SpanStyle::Primary,
-                    label: Some("Add an #EXTINF tag before this line".to_owned())
-                };
-                let d = codemap_diagnostic::Diagnostic {
-                    level: codemap_diagnostic::Level::Error,
-                    message: "URI without #EXTINF".to_owned(),
-                    code: Some("uri_without_extinf".to_owned()),
-                    spans: vec![label]
-                };
-                let mut emitter = codemap_diagnostic::Emitter::stderr(codemap_diagnostic::ColorConfig::Auto, Some(&map));
-                emitter.emit(&[d]);
+                Self::emit_snippet(&req_id, start, end, codemap_diagnostic::Level::Error, "URI without #EXTINF", "uri_without_extinf", "Add an #EXTINF tag before this line");
+            }
+            HlsEvent::PlaylistSyntaxError { req_id, kind, message, start, end } => {
+                if let (Some(start), Some(end)) = (start, end) {
+                    Self::emit_snippet(&req_id, start, end, codemap_diagnostic::Level::Error, "Malformed playlist", kind, &message);
+                } else {
+                    eprintln!("error[{}]: malformed playlist: {}", kind, message);
+                    eprintln!(" --> {}", req_id.info().url);
+                }
             }
             HlsEvent::HttpUnknownError { .. } => {}
             HlsEvent::HttpBodyError { .. } => {}
@@ -89,14 +204,172 @@ impl StderrLog {
             HlsEvent::ResponseSizeExceedsLimit { .. } => {}
             HlsEvent::NumberOfRequestsExceedsLimit { .. } => {}
             HlsEvent::LastModifiedChangedButBodiesIdentical { delta, this_last_modified, last_last_modified } => {
-                eprintln!(
-                    "Last-Modified changed from {} to {} but response bodies were identical",
-                    last_last_modified,
-                    this_last_modified,
-                )
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Warning, &format!("Last-Modified changed from {} to {} but response bodies were identical", last_last_modified, this_last_modified), "last_modified_changed_but_bodies_identical", "earlier response", "later response");
+            }
+            HlsEvent::MissedLastModifiedResponse { delta, .. } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Warning, "response cache validators unchanged, but status was not 304 Not Modified", "missed_last_modified_response", "earlier response", "later response");
             }
-            HlsEvent::MissedLastModifiedResponse { .. } => {}
             HlsEvent::DaterangeAttributeChanged { .. } => {}
+            HlsEvent::PartDurationsMismatchExtinf { .. } => {}
+            HlsEvent::PartTargetDurationExceeded { .. } => {}
+            HlsEvent::MissingPartInf { .. } => {}
+            HlsEvent::MissingPartHoldBack { .. } => {}
+            HlsEvent::PartHoldBackTooShort { .. } => {}
+            HlsEvent::PartTargetChanged { delta, last_part_target_millis, this_part_target_millis } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("part target duration changed from {}ms to {}ms", last_part_target_millis, this_part_target_millis), "part_target_changed", "earlier value", "later value");
+            }
+            HlsEvent::ManifestHistoryChangedPartUri { delta, msn, part, .. } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("URI of part {} of segment {} changed", part, msn), "manifest_history_changed_part_uri", "earlier URI", "later URI");
+            }
+            HlsEvent::ManifestHistoryChangedPartDuration { delta, msn, part, .. } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("duration of part {} of segment {} changed", part, msn), "manifest_history_changed_part_duration", "earlier duration", "later duration");
+            }
+            HlsEvent::PreloadHintNotHonoured { delta, msn, part, hint_uri, this_uri } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Warning, &format!("part {} of segment {} has URI {} rather than preload hint {}", part, msn, this_uri, hint_uri), "preload_hint_not_honoured", "preload hint", "part");
+            }
+            HlsEvent::BlockingReloadReturnedEarly { .. } => {}
+            HlsEvent::BlockingReloadTooSlow { .. } => {}
+            HlsEvent::BlockingReloadMissingRequested { .. } => {}
+            HlsEvent::UnrequestedDeltaUpdate { .. } => {}
+            HlsEvent::DeltaUpdateWithoutCanSkipUntil { .. } => {}
+            HlsEvent::DeltaUpdateSkippedSegmentsMismatch { .. } => {}
+            HlsEvent::DeltaUpdateSkipBoundaryExceeded { .. } => {}
+            HlsEvent::UnknownRecentlyRemovedDaterange { .. } => {}
+            HlsEvent::RecentlyRemovedDaterangePresent { .. } => {}
+            HlsEvent::DeltaUpdateUnusable { .. } => {}
+            HlsEvent::RenditionReportAhead { .. } => {}
+            HlsEvent::RenditionReportBehind { .. } => {}
+            HlsEvent::VariantMsnSkew { .. } => {}
+            HlsEvent::VariantSegmentDurationMismatch { .. } => {}
+            HlsEvent::VariantDiscontinuityMisaligned { .. } => {}
+            HlsEvent::VariantProgramDateTimeMismatch { .. } => {}
+            HlsEvent::ProgramDateTimeChanged { delta, msn, .. } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("program date time of segment {} changed", msn), "program_date_time_changed", "earlier value", "later value");
+            }
+            HlsEvent::ProgramDateTimeDrift { .. } => {}
+            HlsEvent::MissingProgramDateTimeAfterDiscontinuity { .. } => {}
+            HlsEvent::MsnGap { delta, last_msn, missed_count, .. } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Warning, &format!("{} segment(s) following segment {} were never seen in the playlist", missed_count, last_msn), "msn_gap", "earlier last segment", "later first segment");
+            }
+            HlsEvent::EmptyMediaPlaylist { .. } => {}
+            HlsEvent::VodPlaylistReport { .. } => {}
+            HlsEvent::VodMissingEndList { .. } => {}
+            HlsEvent::EventPlaylistMediaSequenceChanged { delta, last_media_sequence, this_media_sequence } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("media sequence of EVENT playlist changed from {} to {}", last_media_sequence, this_media_sequence), "event_playlist_media_sequence_changed", "earlier value", "later value");
+            }
+            HlsEvent::EventPlaylistSegmentsRemoved { delta, removed_count } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("{} segment(s) removed from start of EVENT playlist", removed_count), "event_playlist_segments_removed", "earlier first segment", "later first segment");
+            }
+            HlsEvent::EventPlaylistTooLarge { .. } => {}
+            HlsEvent::VariantStreamAdded { delta, uri } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Warning, &format!("variant stream {} added to main playlist", uri), "variant_stream_added", "earlier playlist", "added variant");
+            }
+            HlsEvent::VariantStreamRemoved { delta, uri } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("variant stream {} removed from main playlist", uri), "variant_stream_removed", "earlier variant", "later playlist");
+            }
+            HlsEvent::VariantBandwidthChanged { delta, uri, last_bandwidth, this_bandwidth } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Warning, &format!("bandwidth of variant stream {} changed from {:?} to {:?}", uri, last_bandwidth, this_bandwidth), "variant_bandwidth_changed", "earlier value", "later value");
+            }
+            HlsEvent::VariantCodecsChanged { delta, uri, last_codecs, this_codecs } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("codecs of variant stream {} changed from {:?} to {:?}", uri, last_codecs, this_codecs), "variant_codecs_changed", "earlier value", "later value");
+            }
+            HlsEvent::VariantResolutionChanged { delta, uri, last_resolution, this_resolution } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Warning, &format!("resolution of variant stream {} changed from {:?} to {:?}", uri, last_resolution, this_resolution), "variant_resolution_changed", "earlier value", "later value");
+            }
+            HlsEvent::RenditionAdded { delta, media_type, group_id, name } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Warning, &format!("{} rendition {:?} of group {:?} added to main playlist", media_type, name, group_id), "rendition_added", "earlier playlist", "added rendition");
+            }
+            HlsEvent::SegmentNotFound { .. } => {}
+            HlsEvent::SegmentAvailableLate { .. } => {}
+            HlsEvent::SegmentIncorrectContentType { .. } => {}
+            HlsEvent::SlowSegmentDownload { .. } => {}
+            HlsEvent::TsSegmentInvalid { .. } => {}
+            HlsEvent::TsMissingPatPmt { .. } => {}
+            HlsEvent::TsContinuityError { .. } => {}
+            HlsEvent::TsPcrRegression { .. } => {}
+            HlsEvent::TsTimestampDiscontinuity { .. } => {}
+            HlsEvent::TsStreamTypeNotInCodecs { .. } => {}
+            HlsEvent::Mp4InitSegmentInvalid { .. } => {}
+            HlsEvent::Mp4SegmentInvalid { .. } => {}
+            HlsEvent::Mp4DurationMismatch { .. } => {}
+            HlsEvent::Mp4DecodeTimeDiscontinuity { .. } => {}
+            HlsEvent::InitSegmentCodecsMismatch { .. } => {}
+            HlsEvent::VideoCodecsMismatch { .. } => {}
+            HlsEvent::VideoResolutionMismatch { .. } => {}
+            HlsEvent::VideoParametersChanged { .. } => {}
+            HlsEvent::SegmentNotIndependent { .. } => {}
+            HlsEvent::PeakBitrateExceedsBandwidth { .. } => {}
+            HlsEvent::AverageBitrateMismatch { .. } => {}
+            HlsEvent::ManifestHistoryChangedKey { delta, msn, .. } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("keys of segment {} changed", msn), "manifest_history_changed_key", "earlier keys", "later keys");
+            }
+            HlsEvent::KeyRotated { .. } => {}
+            HlsEvent::KeyWrongLength { .. } => {}
+            HlsEvent::SegmentDecryptionFailed { .. } => {}
+            HlsEvent::SessionKeyMethodNone { .. } => {}
+            HlsEvent::KeyUriSchemeMismatch { .. } => {}
+            HlsEvent::KeyPsshInvalid { .. } => {}
+            HlsEvent::KeyPsshSystemIdMismatch { .. } => {}
+            HlsEvent::KeyIdNotInPssh { .. } => {}
+            HlsEvent::SessionKeyMismatch { .. } => {}
+            HlsEvent::VariantKeyFormatVersionsMismatch { .. } => {}
+            HlsEvent::VariantKeyIdMismatch { .. } => {}
+            HlsEvent::Scte35Invalid { .. } => {}
+            HlsEvent::Scte35CrcMismatch { .. } => {}
+            HlsEvent::Scte35OutOfNetworkMismatch { .. } => {}
+            HlsEvent::Scte35DurationMismatch { .. } => {}
+            HlsEvent::Scte35EventIdReused { .. } => {}
+            HlsEvent::Scte35InWithoutOut { .. } => {}
+            HlsEvent::Scte35InEventIdMismatch { .. } => {}
+            HlsEvent::DaterangeEndBeforeStart { .. } => {}
+            HlsEvent::DaterangeDurationMismatch { .. } => {}
+            HlsEvent::DaterangeEndOnNextWithoutClass { .. } => {}
+            HlsEvent::DaterangeEndOnNextWithEnd { .. } => {}
+            HlsEvent::DaterangeOverlap { .. } => {}
+            HlsEvent::DaterangeWithoutProgramDateTime { .. } => {}
+            HlsEvent::VideoPictureParametersChanged { .. } => {}
+            HlsEvent::UnresolvedGroupId { at, media_type, group_id, .. } => {
+                Self::emit_at(&at, codemap_diagnostic::Level::Error, &format!("no {} group {:?} defined by EXT-X-MEDIA", media_type, group_id), "unresolved_group_id", "refers to missing group");
+            }
+            HlsEvent::RenditionGroupWithoutDefault { at, media_type, group_id } => {
+                Self::emit_at(&at, codemap_diagnostic::Level::Warning, &format!("no rendition of {} group {:?} has DEFAULT=YES", media_type, group_id), "rendition_group_without_default", "first rendition of group");
+            }
+            HlsEvent::RenditionGroupMultipleDefaults { at, media_type, group_id, default_count } => {
+                Self::emit_at(&at, codemap_diagnostic::Level::Error, &format!("{} renditions of {} group {:?} have DEFAULT=YES", default_count, media_type, group_id), "rendition_group_multiple_defaults", "first rendition of group");
+            }
+            HlsEvent::VariantMissingCodecs { at, .. } => {
+                Self::emit_at(&at, codemap_diagnostic::Level::Warning, "variant stream has no CODECS attribute", "variant_missing_codecs", "add CODECS");
+            }
+            HlsEvent::AverageBandwidthExceedsBandwidth { at, bandwidth, average_bandwidth, .. } => {
+                Self::emit_at(&at, codemap_diagnostic::Level::Error, &format!("AVERAGE-BANDWIDTH {} exceeds BANDWIDTH {}", average_bandwidth, bandwidth), "average_bandwidth_exceeds_bandwidth", "inconsistent bandwidth");
+            }
+            HlsEvent::IFrameStreamWithoutVariant { at, .. } => {
+                Self::emit_at(&at, codemap_diagnostic::Level::Warning, "no variant stream matches this I-frame stream's resolution and codecs", "i_frame_stream_without_variant", "unmatched I-frame stream");
+            }
+            HlsEvent::DuplicateUri { at, uri, first_line } => {
+                Self::emit_at(&at, codemap_diagnostic::Level::Warning, &format!("{} was already listed on line {}", uri, first_line), "duplicate_uri", "duplicate");
+            }
+            HlsEvent::ClosedCaptionsInconsistent { at, .. } => {
+                Self::emit_at(&at, codemap_diagnostic::Level::Error, "CLOSED-CAPTIONS is inconsistent with other variant streams", "closed_captions_inconsistent", "inconsistent CLOSED-CAPTIONS");
+            }
+            HlsEvent::RenditionRemoved { delta, media_type, group_id, name } => {
+                Self::emit_delta(&delta, codemap_diagnostic::Level::Error, &format!("{} rendition {:?} of group {:?} removed from main playlist", media_type, name, group_id), "rendition_removed", "earlier rendition", "later playlist");
+            }
+            HlsEvent::ExtinfExceedsTargetDuration { at, duration_millis, target_duration_millis, .. } => {
+                Self::emit_at(&at, codemap_diagnostic::Level::Error, &format!("segment duration {}ms exceeds target duration {}ms", duration_millis, target_duration_millis), "extinf_exceeds_target_duration", "rounded duration too long");
+            }
+            HlsEvent::ByteRangeNotContiguous { at, expected_offset, offset, .. } => {
+                Self::emit_at(&at, codemap_diagnostic::Level::Warning, &format!("byte range starts at offset {} rather than {}", offset, expected_offset), "byte_range_not_contiguous", "gap or overlap with previous segment");
+            }
+            HlsEvent::ByteRangeOffsetMissing { at, .. } => {
+                Self::emit_at(&at, codemap_diagnostic::Level::Error, "byte range has no offset, but previous segment is not from the same resource", "byte_range_offset_missing", "add an offset");
+            }
+            HlsEvent::ByteRangeInvalid { at, offset, length, .. } => {
+                Self::emit_at(&at, codemap_diagnostic::Level::Error, &format!("byte range of length {} at offset {} ends beyond the largest possible offset", length, offset), "byte_range_invalid", "invalid byte range");
+            }
+            HlsEvent::VersionTooLow { at, version, required_version, feature } => {
+                Self::emit_at(&at, codemap_diagnostic::Level::Error, &format!("{} requires EXT-X-VERSION {}, but playlist declares version {}", feature, required_version, version), "version_too_low", "requires a later version");
+            }
         }
     }
 }
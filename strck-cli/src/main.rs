@@ -6,8 +6,9 @@ use strck::http_snoop::{HttpInfo, ExtraHeader, HttpRef};
 use reqwest::header::HeaderMap;
 use log::*;
 use futures::FutureExt;
+use std::cell::RefCell;
+use std::rc::Rc;
 
-//mod media_manifest;
 mod cli;
 mod dash;
 mod event_log;
@@ -25,7 +26,7 @@ async fn main() {
     let logger = event_log::StderrLog::default();
 
     match cmd.cmd {
-        cli::Command::Hls { manifest } => {
+        cli::Command::Hls { manifest, delta_updates, vod, main_reload_interval, fetch_segments } => {
             let ten_seconds_millis = 10 * 1000;
             let latency_metric = hdrhistogram::Histogram::new_with_max(ten_seconds_millis, 1).unwrap();
             let (media_playlist_latency, media_playlist_latency_writer) = metric::create_metric_channel("manifest_latency", latency_metric);
@@ -34,7 +35,22 @@ async fn main() {
             let (stream_latency, stream_latency_writer) = metric::create_metric_channel("stream_latency", stream_latency_metric);
             let msn_regression_metric = hdrhistogram::Histogram::new_with_max(1000, 1).unwrap();
             let (msn_regression, msn_regression_writer) = metric::create_metric_channel("msn_regression", msn_regression_metric);
-            let ck = hls::HlsProcessor::new(client, manifest, logger, media_playlist_latency, stream_latency, msn_regression);
+            let mut ck = hls::HlsProcessor::new(client, manifest, logger, media_playlist_latency, stream_latency, msn_regression);
+            ck.delta_updates(delta_updates);
+            ck.vod(vod);
+            ck.fetch_segments(fetch_segments);
+            ck.main_reload_interval(main_reload_interval.map(std::time::Duration::from_secs));
+            // variant streams are only known once the main manifest is loaded, so their metric
+            // writers are started as each metric is created
+            let bitrate_writers = Rc::new(RefCell::new(vec![]));
+            let writers = bitrate_writers.clone();
+            ck.variant_bitrate_metric(move |uri| {
+                let one_gigabit = 1000 * 1000 * 1000;
+                let bitrate_metric = hdrhistogram::Histogram::new_with_max(one_gigabit, 1).unwrap();
+                let (metric, writer) = metric::create_metric_channel(&format!("segment_bitrate {}", uri), bitrate_metric);
+                writers.borrow_mut().push(tokio::spawn(writer.consume()));
+                metric
+            });
 
             let metrics = futures::future::join_all(vec![
                 media_playlist_latency_writer.consume().boxed_local(),
@@ -47,6 +63,8 @@ async fn main() {
                 (Err(e), _) => panic!("HlsCheck failed: {:?}", e),
                 (_, e) => panic!("some MetricWriter failed: {:?}", e),
             }
+            let writers: Vec<_> = bitrate_writers.borrow_mut().drain(..).collect();
+            futures::future::join_all(writers).await;
         },
         cli::Command::Dash { manifest } => {
             error!("Sorry, DASH isn't supported right now.  One of these days!");
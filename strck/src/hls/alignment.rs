@@ -0,0 +1,137 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time;
+use crate::event_log::EventSink;
+use crate::http_snoop::HttpRef;
+use super::HlsEvent;
+use super::timeline::{Timeline, TimelineSegment};
+
+/// How many of the latest segments of each variant are retained for comparison with the segments
+/// that later appear in the other variants
+const RETAINED_SEGMENTS: usize = 32;
+
+/// How many segments one variant may lag behind the last playlist seen for another variant, since
+/// the playlists are not loaded at the same instant
+const VARIANT_MSN_SKEW_TOLERANCE: usize = 1;
+
+/// Allowed difference between the durations or program-date-times given for the same segment in
+/// different variants
+const VARIANT_TIME_TOLERANCE: time::Duration = time::Duration::from_millis(100);
+
+struct VariantTimeline {
+    href: HttpRef,
+    /// the latest `RETAINED_SEGMENTS` segments of the variant's timeline, oldest first
+    segments: VecDeque<TimelineSegment>,
+    /// _media sequence number_ of the last segment in the variant's timeline
+    last_msn: Option<usize>,
+    /// the other variants that this variant has been reported as lagging behind, and has not
+    /// caught up with since
+    reported_skew: HashSet<reqwest::Url>,
+}
+impl VariantTimeline {
+    fn segment(&self, msn: usize) -> Option<&TimelineSegment> {
+        self.segments.iter().find(|s| s.msn == msn )
+    }
+}
+
+/// Shared between the tasks processing the playlist of each variant stream, so that the variants'
+/// `Timeline`s can be compared.  The segment with a given _media sequence number_ is expected to
+/// have the same duration, program-date-time and discontinuity in every variant.
+#[derive(Clone, Default)]
+pub struct VariantAlignment {
+    variants: Rc<RefCell<HashMap<reqwest::Url, VariantTimeline>>>,
+}
+impl VariantAlignment {
+    /// Compares the timeline of the variant at the given URL (as updated by the playlist from
+    /// `href`) with the other variants.  Each segment is compared with the other variants only once,
+    /// when it first appears in the timeline.  A variant lagging behind another is reported once,
+    /// and not again until it has caught up.
+    pub fn update<L: EventSink<Extra = HlsEvent>>(&self, url: &reqwest::Url, href: &HttpRef, timeline: &Timeline, log: &mut L) {
+        let mut variants = self.variants.borrow_mut();
+        let mut this = variants.remove(url).unwrap_or_else(|| VariantTimeline {
+            href: href.clone(),
+            segments: VecDeque::new(),
+            last_msn: None,
+            reported_skew: HashSet::new(),
+        });
+        let new_segments: Vec<&TimelineSegment> = timeline.segments()
+            .filter(|s| this.last_msn.map(|msn| s.msn > msn ).unwrap_or(true) )
+            .collect();
+        for (other_url, other) in variants.iter() {
+            if let (Some(last_msn), Some(other_last_msn)) = (timeline.last_msn(), other.last_msn) {
+                // the other variant's playlist was loaded before this one, so this variant being
+                // behind it can't be explained by the timing of the requests
+                if last_msn + VARIANT_MSN_SKEW_TOLERANCE < other_last_msn {
+                    if this.reported_skew.insert(other_url.clone()) {
+                        log.error(HlsEvent::VariantMsnSkew {
+                            req_id: href.clone(),
+                            other_req_id: other.href.clone(),
+                            last_msn,
+                            other_last_msn,
+                        })
+                    }
+                } else {
+                    this.reported_skew.remove(other_url);
+                }
+            }
+            for seg in &new_segments {
+                if let Some(other_seg) = other.segment(seg.msn) {
+                    Self::check_segment(href, seg, &other.href, other_seg, log);
+                }
+            }
+        }
+        this.href = href.clone();
+        this.last_msn = timeline.last_msn();
+        this.segments.extend(new_segments.into_iter().cloned());
+        while this.segments.len() > RETAINED_SEGMENTS {
+            this.segments.pop_front();
+        }
+        variants.insert(url.clone(), this);
+    }
+
+    /// forget the variant at the given URL, which is no longer listed in the main playlist
+    pub fn remove(&self, url: &reqwest::Url) {
+        let mut variants = self.variants.borrow_mut();
+        variants.remove(url);
+        for variant in variants.values_mut() {
+            variant.reported_skew.remove(url);
+        }
+    }
+
+    fn check_segment<L: EventSink<Extra = HlsEvent>>(href: &HttpRef, seg: &TimelineSegment, other_href: &HttpRef, other_seg: &TimelineSegment, log: &mut L) {
+        if abs_diff(seg.duration, other_seg.duration) > VARIANT_TIME_TOLERANCE {
+            log.error(HlsEvent::VariantSegmentDurationMismatch {
+                req_id: href.clone(),
+                other_req_id: other_href.clone(),
+                msn: seg.msn,
+                duration_millis: seg.duration.as_millis() as u64,
+                other_duration_millis: other_seg.duration.as_millis() as u64,
+            })
+        }
+        if seg.discontinuity != other_seg.discontinuity {
+            log.error(HlsEvent::VariantDiscontinuityMisaligned {
+                req_id: href.clone(),
+                other_req_id: other_href.clone(),
+                msn: seg.msn,
+                discontinuity: seg.discontinuity,
+            })
+        }
+        if let (Some(pdt), Some(other_pdt)) = (seg.program_date_time, other_seg.program_date_time) {
+            let diff = u128::from((pdt - other_pdt).num_milliseconds().unsigned_abs());
+            if diff > VARIANT_TIME_TOLERANCE.as_millis() {
+                log.error(HlsEvent::VariantProgramDateTimeMismatch {
+                    req_id: href.clone(),
+                    other_req_id: other_href.clone(),
+                    msn: seg.msn,
+                    program_date_time: pdt.to_rfc3339(),
+                    other_program_date_time: other_pdt.to_rfc3339(),
+                })
+            }
+        }
+    }
+}
+
+fn abs_diff(a: time::Duration, b: time::Duration) -> time::Duration {
+    if a > b { a - b } else { b - a }
+}
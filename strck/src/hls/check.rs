@@ -1,4 +1,4 @@
-use crate::hls::{HlsEvent, Delta, ManifestRef};
+use crate::hls::{HlsEvent, Delta, ManifestRef, BlockingRequest, SkipRequest};
 use crate::event_log::EventSink;
 use super::timeline::*;
 use std::time;
@@ -6,8 +6,12 @@ use hyper::http::HeaderValue;
 use std::str::FromStr;
 use crate::http_snoop::{HttpRef, HttpResponseInfo};
 use crate::metric::Metric;
+use crate::scte35;
 use reqwest::header;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use super::media_manifest::{DateRange, Key, MediaManifest, PreloadHintType};
+use super::rendition::RenditionPositions;
+use hls_m3u8::types::PlaylistType;
 
 // Set-of-u64 structure optimised for the case where multiple contiguous values are stored
 struct SequenceSet {
@@ -20,7 +24,25 @@ struct SeqSpan {
 
 struct PlaylistInfo {
     playlist: hls_m3u8::parser::MyMediaPlaylist,
+    manifest: MediaManifest,
     href: HttpRef,
+    /// length of the full playlist text (which may have been rebuilt from a delta update)
+    body_size: usize,
+}
+
+/// What is known of the `EXT-X-PROGRAM-DATE-TIME` of a segment
+#[derive(Clone)]
+struct ProgramDateTime {
+    value: chrono::DateTime<chrono::FixedOffset>,
+    /// the `EXTINF` duration of the segment, giving the program-date-time of the next segment
+    duration: time::Duration,
+    /// true if the value was given by a tag, rather than calculated from earlier segments
+    explicit: bool,
+}
+impl ProgramDateTime {
+    fn next(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        chrono::Duration::from_std(self.duration).ok().map(|d| self.value + d )
+    }
 }
 
 enum LastError {
@@ -39,21 +61,140 @@ pub struct MediaPlaylistCheck<L: EventSink<Extra = HlsEvent>, M: Metric> {
     ended: bool,
     msn_regression: M,
     last_error: LastError,
+    /// true if the last playlist loaded listed no segments
+    empty: bool,
+    /// the IDs of all `EXT-X-DATERANGE` tags seen so far
+    seen_dateranges: HashSet<String>,
+    /// the program-date-time of each segment still in the playlist (and of the segment before the
+    /// first), by _media sequence number_
+    program_date_times: BTreeMap<usize, ProgramDateTime>,
+    /// true once `EventPlaylistTooLarge` has been reported, so that it's only reported once
+    event_playlist_too_large: bool,
+    /// the SCTE-35 event ID (if any) of each `EXT-X-DATERANGE` ID still in the playlist seen with
+    /// `SCTE35-OUT`
+    scte35_outs: HashMap<String, Option<u32>>,
+    /// the `EXT-X-DATERANGE` ID of each SCTE-35 event ID used by a tag still in the playlist
+    scte35_event_ids: HashMap<u32, String>,
+    /// the pairs of `EXT-X-DATERANGE` IDs, both still in the playlist, already reported as
+    /// overlapping
+    reported_daterange_overlaps: HashSet<(String, String)>,
+}
+
+/// Allowed difference between a segment's `EXTINF` and the sum of its parts' durations, allowing for
+/// the rounding of each value to the number of decimal places in the playlist
+const PART_DURATION_SUM_TOLERANCE: f32 = 0.02;
+
+const BLOCKING_RELOAD_TIMEOUT_TARGET_DURATIONS: u32 = 3;
+
+/// Size of `EXT-X-PLAYLIST-TYPE:EVENT` playlist body at which to warn that the ever-growing playlist
+/// may soon be too large for some players to handle
+const EVENT_PLAYLIST_SIZE_WARNING_BYTES: usize = 1024 * 1024;
+
+/// Allowed difference between a segment's `EXT-X-PROGRAM-DATE-TIME` and the value implied by the
+/// previous segment's program-date-time and duration, allowing for rounding of `EXTINF` values
+const PROGRAM_DATE_TIME_DRIFT_TOLERANCE_MILLIS: i64 = 100;
+
+/// Allowed difference between the duration of a break given by SCTE-35 and by the `DURATION` or
+/// `PLANNED-DURATION` of the `EXT-X-DATERANGE` carrying it
+const SCTE35_DURATION_TOLERANCE_MILLIS: u64 = 100;
+
+/// Allowed difference between the `DURATION` of an `EXT-X-DATERANGE` and the time between its
+/// `START-DATE` and `END-DATE`, allowing for the rounding of each value
+const DATERANGE_DURATION_TOLERANCE_MILLIS: i64 = 1;
+
+/// How many segments an `EXT-X-RENDITION-REPORT` may differ from the last playlist seen for that
+/// rendition, since the two playlists are not loaded at the same instant
+const RENDITION_REPORT_MSN_TOLERANCE: u64 = 1;
+
+fn secs_to_millis(secs: f32) -> u64 {
+    (secs * 1000.0).round() as u64
+}
+
+/// the segments of the playlist, as they will be recorded in the `Timeline`
+fn timeline_segments(this: &PlaylistInfo) -> impl Iterator<Item = TimelineSegment> + '_ {
+    let mut discontinuity_sequence = this.manifest.discontinuity_sequence;
+    this.playlist.segments().enumerate().map(move |(i, s)| {
+        // EXT-X-DISCONTINUITY-SEQUENCE already accounts for any discontinuity at the first segment
+        if i > 0 && s.has_discontinuity() {
+            discontinuity_sequence += 1;
+        }
+        TimelineSegment::new(&s, discontinuity_sequence)
+    })
+}
+
+fn manifest_ref(info: &PlaylistInfo, line: Option<usize>) -> ManifestRef {
+    ManifestRef {
+        req_id: info.href.clone(),
+        // lines of a rebuilt playlist don't correspond to the response body
+        line: if info.manifest.rebuilt { None } else { line.map(|l| l + 1 ) },
+    }
+}
+
+/// true if the segments are encrypted with the same keys, and (if `compare_iv` is set) the same
+/// initialization vectors
+fn same_keys(a: &[Key], b: &[Key], compare_iv: bool) -> bool {
+    a.len() == b.len() && a.iter().all(|ka| b.iter().any(|kb| ka.same_key(kb) && (!compare_iv || ka.iv == kb.iv) ))
+}
+
+/// The period covered by an `EXT-X-DATERANGE` ID, combining the attributes of all its tags
+struct DaterangeExtent<'a> {
+    class: &'a str,
+    id: &'a str,
+    start: Option<chrono::DateTime<chrono::FixedOffset>>,
+    end: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// the line of the last tag with the ID
+    line: usize,
+}
+impl DaterangeExtent<'_> {
+    /// true if both ranges have a known start and end, and they overlap
+    fn overlaps(&self, other: &DaterangeExtent<'_>) -> bool {
+        match (self.start, self.end, other.start, other.end) {
+            (Some(start), Some(end), Some(other_start), Some(other_end)) => start < other_end && other_start < end,
+            _ => false,
+        }
+    }
+}
+
+/// the `EXT-X-DATERANGE` tags of the playlist that were not present in the `last` playlist
+fn new_dateranges<'a>(last: Option<&'a PlaylistInfo>, this: &'a PlaylistInfo) -> impl Iterator<Item = &'a DateRange> {
+    this.manifest.dateranges
+        .iter()
+        .filter(move |d| last.map(|l| !l.manifest.dateranges.iter().any(|ld| ld.tag == d.tag ) ).unwrap_or(true) )
 }
 
+/// a `Delta` not relating to any particular line of the playlists, e.g. for HTTP header changes
 fn delta(before: &PlaylistInfo, after: &PlaylistInfo) -> Delta {
     Delta {
-        before: ManifestRef {
-            req_id: before.href.clone(),
-            line: None
-        },
-        after: ManifestRef {
-            req_id: after.href.clone(),
-            line: None
-        }
+        before: manifest_ref(before, None),
+        after: manifest_ref(after, None),
     }
 }
 
+/// a `Delta` referring to the given (zero-based) line indexes in each playlist
+fn delta_at(before: &PlaylistInfo, before_line: Option<usize>, after: &PlaylistInfo, after_line: Option<usize>) -> Delta {
+    Delta {
+        before: manifest_ref(before, before_line),
+        after: manifest_ref(after, after_line),
+    }
+}
+
+/// a `Delta` referring to the given playlist tag, in whichever of the playlists it appears
+fn delta_tag(before: &PlaylistInfo, after: &PlaylistInfo, tag: &str) -> Delta {
+    delta_at(before, before.manifest.tag_line(tag), after, after.manifest.tag_line(tag))
+}
+
+/// The line of the given tag applying to the segment, or the line of the segment URI if `tag` is
+/// `None` (or the tag is absent)
+fn segment_line(info: &PlaylistInfo, msn: usize, tag: Option<&str>) -> Option<usize> {
+    let seg = info.manifest.segment(msn as u64)?;
+    Some(tag.and_then(|t| seg.tag_line(t) ).unwrap_or(seg.line))
+}
+
+/// a `Delta` referring to the given segment in each playlist
+fn delta_segment(before: &PlaylistInfo, after: &PlaylistInfo, msn: usize, tag: Option<&str>) -> Delta {
+    delta_at(before, segment_line(before, msn, tag), after, segment_line(after, msn, tag))
+}
+
 impl<L: EventSink<Extra = HlsEvent>, M: Metric> MediaPlaylistCheck<L, M> {
     pub fn new(log: L, msn_regression: M) -> MediaPlaylistCheck<L, M> {
         MediaPlaylistCheck {
@@ -66,6 +207,13 @@ impl<L: EventSink<Extra = HlsEvent>, M: Metric> MediaPlaylistCheck<L, M> {
             ended: false,
             msn_regression,
             last_error: LastError::None,
+            empty: false,
+            seen_dateranges: HashSet::new(),
+            program_date_times: BTreeMap::new(),
+            event_playlist_too_large: false,
+            scte35_outs: HashMap::new(),
+            scte35_event_ids: HashMap::new(),
+            reported_daterange_overlaps: HashSet::new(),
         }
     }
 
@@ -104,30 +252,66 @@ impl<L: EventSink<Extra = HlsEvent>, M: Metric> MediaPlaylistCheck<L, M> {
         self.last_error = LastError::Timeout;
     }
 
-    pub fn next_playlist(&mut self, href: HttpRef, playlist: hls_m3u8::parser::MyMediaPlaylist, total_time: time::Duration) {
+    pub fn timeline(&self) -> &Timeline {
+        &self.timeline
+    }
+
+    /// `blocking_request` gives the position in the playlist for which the server was asked to hold
+    /// the request, if a blocking playlist reload was performed
+    pub fn next_playlist(&mut self, href: HttpRef, playlist: hls_m3u8::parser::MyMediaPlaylist, manifest: MediaManifest, body_size: usize, blocking_request: Option<BlockingRequest>, total_time: time::Duration) {
         self.last_error = LastError::None;
         let playlist_info = PlaylistInfo {
             href: href.clone(),
             playlist,
+            manifest,
+            body_size,
         };
-        if let Some(last_playlist) = self.last_playlist.take() {
+        let is_empty = playlist_info.playlist.last_segment().is_none();
+        if is_empty {
+            // checks that compare segments are skipped until segments are listed again, and until
+            // then, last_playlist remains the last playlist that had segments
+            if !self.empty {
+                self.log.warning(HlsEvent::EmptyMediaPlaylist {
+                    req_id: href.clone(),
+                })
+            }
+        } else if let Some(last_playlist) = self.last_playlist.take() {
             self.check_invariant_properties(&last_playlist, &playlist_info);
             self.check_update(&last_playlist, &playlist_info);
+            self.check_parts(Some(&last_playlist), &playlist_info);
         } else {
             self.check_initial_configuration(&playlist_info);
-            self.timeline.append_new_segments(playlist_info.playlist.segments());
+            self.check_parts(None, &playlist_info);
+            self.check_program_date_times(None, &playlist_info);
+            self.check_map_changes(None, &playlist_info);
+            self.check_key_changes(None, &playlist_info);
+            self.check_scte35(None, &playlist_info);
+            self.check_daterange_rules(None, &playlist_info);
+            self.timeline.append_new_segments(timeline_segments(&playlist_info));
             // being the first copy of the playlist we've seen, it can't be stale,
             self.last_fresh_playlist_req = Some(playlist_info.href.clone());
         }
+        self.empty = is_empty;
+        if playlist_info.playlist.playlist_type == Some(PlaylistType::Event) {
+            self.check_event_playlist_size(&playlist_info);
+        }
         self.check_headers(&playlist_info);
-        // TODO: consider tuning the alert-level down.
-        if total_time >= playlist_info.playlist.target_duration {
+        if let Some(request) = blocking_request {
+            // the server is expected to hold on to a blocking request, so it being slow to respond
+            // is tested differently
+            self.check_blocking_response(&playlist_info, request, total_time);
+        } else if total_time >= playlist_info.playlist.target_duration {
+            // TODO: consider tuning the alert-level down.
             self.log.error(HlsEvent::SlowMediaManifestResponse {
                 req_id: href.clone(),
                 response_time_millis: total_time.as_millis() as u64,
                 target_duration_millis: playlist_info.playlist.target_duration.as_millis() as u64,
             })
         }
+        self.seen_dateranges.extend(
+            playlist_info.playlist.segments()
+                .filter_map(|s| s.date_range().map(|d| d.id.clone() ) )
+        );
         if playlist_info.playlist.has_end_list && !self.ended {
             self.log.info(HlsEvent::End {
                 req_id: href,
@@ -135,7 +319,145 @@ impl<L: EventSink<Extra = HlsEvent>, M: Metric> MediaPlaylistCheck<L, M> {
             // remember that we've observed EXT-X-ENDLIST so that we don't emit HlsEvent::End again
             self.ended = true;
         }
-        self.last_playlist = Some(playlist_info);
+        if !is_empty {
+            self.last_playlist = Some(playlist_info);
+        }
+    }
+
+    /// Checks a Playlist Delta Update before the full playlist is rebuilt from it.  Returns false
+    /// if the delta is inconsistent with the last playlist, and so can't be used to rebuild it.
+    pub fn delta_update(&mut self, href: &HttpRef, playlist: &hls_m3u8::parser::MyMediaPlaylist, delta: &MediaManifest, skip_request: Option<SkipRequest>) -> bool {
+        let skip = match delta.skip {
+            Some(ref skip) => skip,
+            None => return true,
+        };
+        if skip_request.is_none() {
+            self.log.error(HlsEvent::UnrequestedDeltaUpdate {
+                req_id: href.clone(),
+            })
+        }
+        let can_skip_until = self.last_playlist.as_ref()
+            .and_then(|l| l.manifest.server_control.as_ref() )
+            .and_then(|c| c.can_skip_until );
+        match can_skip_until {
+            None => self.log.error(HlsEvent::DeltaUpdateWithoutCanSkipUntil {
+                req_id: href.clone(),
+            }),
+            Some(can_skip_until) => {
+                let remaining: f32 = delta.segments.iter().filter_map(|s| s.duration ).sum();
+                if skip.skipped_segments > 0 && remaining < can_skip_until {
+                    self.log.error(HlsEvent::DeltaUpdateSkipBoundaryExceeded {
+                        req_id: href.clone(),
+                        skipped_segments: skip.skipped_segments,
+                        remaining_duration_millis: secs_to_millis(remaining),
+                        can_skip_until_millis: secs_to_millis(can_skip_until),
+                    })
+                }
+            }
+        }
+        for id in &skip.recently_removed_dateranges {
+            if !self.seen_dateranges.contains(id) {
+                self.log.warning(HlsEvent::UnknownRecentlyRemovedDaterange {
+                    req_id: href.clone(),
+                    daterange_id: id.clone(),
+                })
+            }
+            if playlist.segments().any(|s| s.date_range().map(|d| &d.id == id ).unwrap_or(false) ) {
+                self.log.error(HlsEvent::RecentlyRemovedDaterangePresent {
+                    req_id: href.clone(),
+                    daterange_id: id.clone(),
+                })
+            }
+        }
+        // work out how many segments should have been skipped from where the first listed segment
+        // appeared in the last playlist, if its URI identifies it unambiguously
+        let first = match delta.segments.first() {
+            Some(first) => first,
+            None => return true,
+        };
+        let last_manifest = match self.last_playlist {
+            Some(ref last) => &last.manifest,
+            None => return true,
+        };
+        let mut matches = last_manifest.segments.iter().filter(|s| s.uri == first.uri );
+        if let (Some(last_seg), None) = (matches.next(), matches.next()) {
+            if last_seg.msn >= delta.media_sequence {
+                let expected_skipped_segments = last_seg.msn - delta.media_sequence;
+                if expected_skipped_segments != skip.skipped_segments {
+                    self.log.error(HlsEvent::DeltaUpdateSkippedSegmentsMismatch {
+                        req_id: href.clone(),
+                        skipped_segments: skip.skipped_segments,
+                        expected_skipped_segments,
+                    });
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Compares each `EXT-X-RENDITION-REPORT` in the given playlist (loaded from `playlist_url`)
+    /// with the position most recently seen in the playlist of the rendition it reports on
+    pub fn rendition_reports(&mut self, href: &HttpRef, playlist_url: &reqwest::Url, manifest: &MediaManifest, positions: &RenditionPositions) {
+        for report in &manifest.rendition_reports {
+            let report_msn = match report.last_msn {
+                Some(msn) => msn,
+                None => continue,
+            };
+            let seen = match playlist_url.join(&report.uri).ok().and_then(|u| positions.get(&u) ) {
+                Some(seen) => seen,
+                // not a rendition that we are polling, or not loaded yet
+                None => continue,
+            };
+            if report_msn > seen.last_msn + RENDITION_REPORT_MSN_TOLERANCE {
+                self.log.error(HlsEvent::RenditionReportAhead {
+                    req_id: href.clone(),
+                    rendition_uri: report.uri.clone(),
+                    report_msn,
+                    report_part: report.last_part,
+                    seen_msn: seen.last_msn,
+                    seen_part: seen.last_part,
+                })
+            } else if report_msn + RENDITION_REPORT_MSN_TOLERANCE < seen.last_msn {
+                self.log.error(HlsEvent::RenditionReportBehind {
+                    req_id: href.clone(),
+                    rendition_uri: report.uri.clone(),
+                    report_msn,
+                    report_part: report.last_part,
+                    seen_msn: seen.last_msn,
+                    seen_part: seen.last_part,
+                })
+            }
+        }
+    }
+
+    fn check_blocking_response(&mut self, this: &PlaylistInfo, request: BlockingRequest, total_time: time::Duration) {
+        // "Clients SHOULD time out a Blocking Playlist Reload request after three times the
+        // Target Duration"
+        let timeout = this.playlist.target_duration * BLOCKING_RELOAD_TIMEOUT_TARGET_DURATIONS;
+        if total_time > timeout {
+            self.log.error(HlsEvent::BlockingReloadTooSlow {
+                req_id: this.href.clone(),
+                requested_msn: request.msn,
+                requested_part: request.part,
+                response_time_millis: total_time.as_millis() as u64,
+                timeout_millis: timeout.as_millis() as u64,
+            })
+        }
+        if !request.reached_by(&this.manifest) {
+            self.log.error(HlsEvent::BlockingReloadReturnedEarly {
+                req_id: this.href.clone(),
+                requested_msn: request.msn,
+                requested_part: request.part,
+                response_time_millis: total_time.as_millis() as u64,
+            })
+        } else if !request.listed_in(&this.manifest) {
+            self.log.error(HlsEvent::BlockingReloadMissingRequested {
+                req_id: this.href.clone(),
+                requested_msn: request.msn,
+                requested_part: request.part,
+            })
+        }
     }
 
     /// emit errors if things that are supposed to be fixed for the stream lifetime are actually
@@ -144,12 +466,12 @@ impl<L: EventSink<Extra = HlsEvent>, M: Metric> MediaPlaylistCheck<L, M> {
         if last.playlist.has_i_frames_only != this.playlist.has_i_frames_only {
             if last.playlist.has_i_frames_only {
                 self.log.error(HlsEvent::UnexpectedPlaylistPropertyRemoval {
-                    delta: delta(last, this),
+                    delta: delta_tag(last, this, "EXT-X-I-FRAMES-ONLY"),
                     name: "EXT-X-I-FRAMES-ONLY",
                 })
             } else {
                 self.log.error(HlsEvent::UnexpectedPlaylistPropertyAddition {
-                    delta: delta(last, this),
+                    delta: delta_tag(last, this, "EXT-X-I-FRAMES-ONLY"),
                     name: "EXT-X-I-FRAMES-ONLY",
                 })
             }
@@ -157,23 +479,32 @@ impl<L: EventSink<Extra = HlsEvent>, M: Metric> MediaPlaylistCheck<L, M> {
         if last.playlist.has_independent_segments != this.playlist.has_independent_segments {
             if last.playlist.has_independent_segments {
                 self.log.error(HlsEvent::UnexpectedPlaylistPropertyRemoval {
-                    delta: delta(last, this),
+                    delta: delta_tag(last, this, "EXT-X-INDEPENDENT-SEGMENTS"),
                     name: "ext-x-independent-segments",
                 })
             } else {
                 self.log.error(HlsEvent::UnexpectedPlaylistPropertyAddition {
-                    delta: delta(last, this),
+                    delta: delta_tag(last, this, "EXT-X-INDEPENDENT-SEGMENTS"),
                     name: "ext-x-independent-segments",
                 })
             }
         }
+        if let (Some(last_part_inf), Some(this_part_inf)) = (&last.manifest.part_inf, &this.manifest.part_inf) {
+            if last_part_inf.part_target != this_part_inf.part_target {
+                self.log.error(HlsEvent::PartTargetChanged {
+                    delta: delta_tag(last, this, "EXT-X-PART-INF"),
+                    last_part_target_millis: secs_to_millis(last_part_inf.part_target),
+                    this_part_target_millis: secs_to_millis(this_part_inf.part_target),
+                })
+            }
+        }
         // TODO: check version (property not currently exposed)
         //if last.playlist.version != this.playlist.version {
         //
         //}
         if last.playlist.target_duration != this.playlist.target_duration {
             self.log.error(HlsEvent::TargetDurationChanged {
-                delta: delta(last, this),
+                delta: delta_tag(last, this, "EXT-X-TARGETDURATION"),
                 last_target_duration_millis: last.playlist.target_duration.as_millis() as u64,
                 this_target_duration_millis: this.playlist.target_duration.as_millis() as u64,
             })
@@ -204,31 +535,30 @@ impl<L: EventSink<Extra = HlsEvent>, M: Metric> MediaPlaylistCheck<L, M> {
     }
 
     fn check_update(&mut self, last: &PlaylistInfo, this: &PlaylistInfo) {
-        // TODO: assert that the EXT-X-PROGRAM-DATE-TIME values continue to match up with the segments as items are removed from the top of the playlist etc
-
-        // TODO: handle playlists that are empty, without panicking
-
         // Once the stream ends, it doesn't make sense for it to start again
         if last.playlist.has_end_list && !this.playlist.has_end_list {
             self.log.warning(HlsEvent::EndListTagRemoved)
         }
+        if this.playlist.playlist_type == Some(PlaylistType::Event) {
+            self.check_event_playlist(last, this);
+        }
         // if the MSN changes, it should only ever increase
         if last.playlist.media_sequence > this.playlist.media_sequence {
             let regression = last.playlist.media_sequence - this.playlist.media_sequence;
             self.msn_regression.put(regression as u64);
             self.log.error(HlsEvent::MsnGoneBackwards {
-                delta: delta(last, this),
+                delta: delta_tag(last, this, "EXT-X-MEDIA-SEQUENCE"),
                 last_msn: last.playlist.media_sequence,
                 this_msn: this.playlist.media_sequence,
             })
-        } else {
+        } else if let (Some(last_seg), Some(this_seg)) = (last.playlist.last_segment(), this.playlist.last_segment()) {
             self.msn_regression.put(0);
-            let last_msn = last.playlist.last_segment().unwrap().number();
-            let this_msn = this.playlist.last_segment().unwrap().number();
+            let last_msn = last_seg.number();
+            let this_msn = this_seg.number();
             if last_msn > this_msn {
                 let removed_count = last_msn - this_msn;
                 let event = HlsEvent::LiveSegmentsRemoved {
-                    delta: delta(&last, &this),
+                    delta: delta_at(last, segment_line(last, last_msn, None), this, segment_line(this, this_msn, None)),
                     last_msn,
                     this_msn,
                     removed_count
@@ -242,7 +572,14 @@ impl<L: EventSink<Extra = HlsEvent>, M: Metric> MediaPlaylistCheck<L, M> {
             } else {
                 // we can only perform these checks when the MSN values are sane,
                 self.check_manifest_history_invariant(last, this);
+                self.check_part_history_invariant(last, this);
+                self.check_preload_hint(last, this);
                 self.check_stale(this);
+                self.check_program_date_times(Some(last), this);
+                self.check_map_changes(Some(last), this);
+                self.check_key_changes(Some(last), this);
+                self.check_scte35(Some(last), this);
+                self.check_daterange_rules(Some(last), this);
                 self.update_timeline(last, this);
                 self.check_daterange(last_msn, this);
             }
@@ -254,6 +591,45 @@ impl<L: EventSink<Extra = HlsEvent>, M: Metric> MediaPlaylistCheck<L, M> {
         }
     }
 
+    /// An event playlist may only have segments appended to it, so its `EXT-X-MEDIA-SEQUENCE` can't
+    /// change and segments can't be removed from the start
+    fn check_event_playlist(&mut self, last: &PlaylistInfo, this: &PlaylistInfo) {
+        if last.playlist.media_sequence != this.playlist.media_sequence {
+            self.log.error(HlsEvent::EventPlaylistMediaSequenceChanged {
+                delta: delta_tag(last, this, "EXT-X-MEDIA-SEQUENCE"),
+                last_media_sequence: last.playlist.media_sequence,
+                this_media_sequence: this.playlist.media_sequence,
+            })
+        }
+        // compare by URI rather than by media sequence number, in case the head segment was removed
+        // without the sequence numbers of the remaining segments changing too
+        let this_uris: HashSet<&str> = this.manifest.segments.iter().map(|s| s.uri.as_str() ).collect();
+        let removed_count = last.manifest.segments.iter()
+            .take_while(|s| !this_uris.contains(s.uri.as_str()) )
+            .count();
+        if removed_count > 0 {
+            let last_first_line = last.manifest.segments.first().map(|s| s.line );
+            let this_first_line = this.manifest.segments.first().map(|s| s.line );
+            self.log.error(HlsEvent::EventPlaylistSegmentsRemoved {
+                delta: delta_at(last, last_first_line, this, this_first_line),
+                removed_count,
+            })
+        }
+    }
+
+    fn check_event_playlist_size(&mut self, this: &PlaylistInfo) {
+        if this.body_size >= EVENT_PLAYLIST_SIZE_WARNING_BYTES && !self.event_playlist_too_large {
+            let segment_count = this.manifest.segments.len();
+            self.log.warning(HlsEvent::EventPlaylistTooLarge {
+                req_id: this.href.clone(),
+                body_size: this.body_size,
+                segment_count,
+                bytes_per_segment: this.body_size / segment_count.max(1),
+            });
+            self.event_playlist_too_large = true;
+        }
+    }
+
     fn check_last_modified_changed_but_bodies_identical(&mut self, last: &&PlaylistInfo, this: &&PlaylistInfo, this_resp: &HttpResponseInfo, last_resp: &HttpResponseInfo) {
         if this_resp.hash().unwrap() == last_resp.hash().unwrap() {
             // response bodies are identical
@@ -317,14 +693,138 @@ impl<L: EventSink<Extra = HlsEvent>, M: Metric> MediaPlaylistCheck<L, M> {
 
     fn update_timeline(&mut self, last: &PlaylistInfo, this: &PlaylistInfo) {
         self.timeline.remove_older_than(this.playlist.media_sequence);
-        // media-sequence-number of the final segment in the last playlist,
-        let end_msn = last.playlist.last_segment().unwrap().number();
-        let skip = if end_msn >= this.playlist.media_sequence {
-            1 + end_msn - this.playlist.media_sequence
-        } else {
-            0
-        };
-        self.timeline.append_new_segments(this.playlist.segments().skip(skip));
+        // media-sequence-number of the final segment already in the timeline,
+        let end_msn = self.timeline.last_msn();
+        let new_segments = timeline_segments(this)
+            .filter(|s| end_msn.map(|end| s.msn > end ).unwrap_or(true) );
+        if let Some(gap) = self.timeline.append_new_segments(new_segments) {
+            self.log.warning(HlsEvent::MsnGap {
+                delta: delta_at(last, segment_line(last, gap.last_msn, None), this, segment_line(this, gap.next_msn, None)),
+                last_msn: gap.last_msn,
+                next_msn: gap.next_msn,
+                missed_count: gap.next_msn - gap.last_msn - 1,
+            })
+        }
+    }
+
+    /// Checks each segment's `EXT-X-PROGRAM-DATE-TIME` against the value given for the same segment
+    /// by earlier playlists, and against the value implied by the previous segment
+    fn check_program_date_times(&mut self, last: Option<&PlaylistInfo>, this: &PlaylistInfo) {
+        let uses_program_date_time = this.playlist.segments().any(|s| s.program_date_time().is_some() );
+        let last_msn = last.and_then(|l| l.playlist.last_segment() ).map(|s| s.number() );
+        let mut prev = this.playlist.segments().next()
+            .and_then(|s| s.number().checked_sub(1) )
+            .and_then(|msn| self.program_date_times.get(&msn).cloned() );
+        for seg in this.playlist.segments() {
+            let msn = seg.number();
+            let expected = if seg.has_discontinuity() {
+                None
+            } else {
+                prev.as_ref().and_then(|p| p.next() )
+            };
+            let explicit = seg.program_date_time().map(|p| p.date_time );
+            let known = self.program_date_times.get(&msn);
+            match (explicit, known) {
+                (Some(value), Some(known)) if known.explicit => {
+                    if value != known.value {
+                        if let Some(last) = last {
+                            self.log.error(HlsEvent::ProgramDateTimeChanged {
+                                delta: delta_segment(last, this, msn, Some("EXT-X-PROGRAM-DATE-TIME")),
+                                msn,
+                                last_program_date_time: known.value.to_rfc3339(),
+                                this_program_date_time: value.to_rfc3339(),
+                            })
+                        }
+                    }
+                },
+                (Some(value), _) => {
+                    if let Some(expected) = expected {
+                        let drift = (value - expected).num_milliseconds();
+                        if drift.abs() > PROGRAM_DATE_TIME_DRIFT_TOLERANCE_MILLIS {
+                            self.log.error(HlsEvent::ProgramDateTimeDrift {
+                                req_id: this.href.clone(),
+                                msn,
+                                program_date_time: value.to_rfc3339(),
+                                expected_program_date_time: expected.to_rfc3339(),
+                                drift_millis: drift,
+                            })
+                        }
+                    }
+                },
+                (None, _) => {
+                    let is_new = last_msn.map(|last_msn| msn > last_msn ).unwrap_or(true);
+                    if seg.has_discontinuity() && uses_program_date_time && is_new {
+                        self.log.warning(HlsEvent::MissingProgramDateTimeAfterDiscontinuity {
+                            req_id: this.href.clone(),
+                            msn,
+                        })
+                    }
+                },
+            }
+            let duration = seg.duration().duration();
+            let this_pdt = explicit.map(|value| ProgramDateTime { value, duration, explicit: true })
+                .or_else(|| expected.map(|value| ProgramDateTime { value, duration, explicit: false }) );
+            if let Some(this_pdt) = this_pdt {
+                // a value given by a tag in an earlier playlist is kept in preference to one
+                // calculated from this playlist
+                let keep_known = known.map(|k| k.explicit && !this_pdt.explicit ).unwrap_or(false);
+                if !keep_known {
+                    self.program_date_times.insert(msn, this_pdt);
+                }
+            }
+            prev = self.program_date_times.get(&msn).cloned();
+        }
+        // forget segments that have been removed from the playlist, apart from the one immediately
+        // before the first remaining segment
+        self.program_date_times = self.program_date_times.split_off(&this.playlist.media_sequence.saturating_sub(1));
+    }
+
+    /// A segment using a different Media Initialization Section to the segment before it is
+    /// expected to be preceded by `EXT-X-DISCONTINUITY`.  Only segments new since the last playlist
+    /// are checked, to avoid repeating the same report on each reload.
+    fn check_map_changes(&mut self, last: Option<&PlaylistInfo>, this: &PlaylistInfo) {
+        let last_msn = last.and_then(|l| l.manifest.segments.last() ).map(|s| s.msn );
+        for pair in this.manifest.segments.windows(2) {
+            let (prev, seg) = (&pair[0], &pair[1]);
+            if last_msn.map(|last_msn| seg.msn <= last_msn ).unwrap_or(false) {
+                continue;
+            }
+            let changed = match (&prev.map, &seg.map) {
+                (Some(a), Some(b)) => !a.same_section(b),
+                (None, None) => false,
+                _ => true,
+            };
+            if changed && seg.tag_line("EXT-X-DISCONTINUITY").is_none() {
+                let line = seg.map.as_ref().map(|m| m.line ).unwrap_or(seg.line);
+                self.log.error(HlsEvent::MapChangedWithoutDiscontinuity {
+                    at: manifest_ref(this, Some(line)),
+                    msn: seg.msn,
+                    last_map: prev.map.as_ref().map(|m| m.to_string() ),
+                    this_map: seg.map.as_ref().map(|m| m.to_string() ),
+                })
+            }
+        }
+    }
+
+    /// Logs the rotation of segment keys, being a segment encrypted with a different key to the
+    /// segment before it.  As for `check_map_changes()`, only new segments are considered.
+    fn check_key_changes(&mut self, last: Option<&PlaylistInfo>, this: &PlaylistInfo) {
+        let last_msn = last.and_then(|l| l.manifest.segments.last() ).map(|s| s.msn );
+        for pair in this.manifest.segments.windows(2) {
+            let (prev, seg) = (&pair[0], &pair[1]);
+            if last_msn.map(|last_msn| seg.msn <= last_msn ).unwrap_or(false) {
+                continue;
+            }
+            if !same_keys(&prev.keys, &seg.keys, false) {
+                let line = seg.keys.last().map(|k| k.line ).unwrap_or(seg.line);
+                self.log.info(HlsEvent::KeyRotated {
+                    at: manifest_ref(this, Some(line)),
+                    msn: seg.msn,
+                    last_keys: prev.keys.iter().map(|k| k.to_string() ).collect(),
+                    this_keys: seg.keys.iter().map(|k| k.to_string() ).collect(),
+                })
+            }
+        }
     }
 
     fn check_manifest_history_invariant(&mut self, last: &PlaylistInfo, this: &PlaylistInfo) {
@@ -341,7 +841,7 @@ impl<L: EventSink<Extra = HlsEvent>, M: Metric> MediaPlaylistCheck<L, M> {
     fn check_segment_invariant(&mut self, last: &PlaylistInfo, this: &PlaylistInfo, last_seg: hls_m3u8::parser::MyMediaSegment, this_seg: hls_m3u8::parser::MyMediaSegment) {
         if last_seg.uri() != this_seg.uri() {
             self.log.error(HlsEvent::ManifestHistoryChangedUri {
-                delta: delta(last, this),
+                delta: delta_segment(last, this, this_seg.number(), None),
                 msn: this_seg.number(),
                 last_uri: last_seg.uri().to_string(),
                 this_uri: this_seg.uri().to_string(),
@@ -354,27 +854,52 @@ impl<L: EventSink<Extra = HlsEvent>, M: Metric> MediaPlaylistCheck<L, M> {
             println!("last,\n{:?}\nthis,\n{:?}", last_seg, this_seg);
             if this_seg.has_discontinuity() {
                 self.log.error(HlsEvent::ManifestHistoryAddedDiscontinuity {
-                    delta: delta(last, this),
+                    delta: delta_segment(last, this, this_seg.number(), Some("EXT-X-DISCONTINUITY")),
                     msn: this_seg.number(),
                 });
             } else {
                 self.log.error(HlsEvent::ManifestHistoryRemovedDiscontinuity {
-                    delta: delta(last, this),
+                    delta: delta_segment(last, this, this_seg.number(), Some("EXT-X-DISCONTINUITY")),
                     msn: this_seg.number(),
                 });
             }
         }
         if last_seg.duration() != this_seg.duration() {
             self.log.error(HlsEvent::ManifestHistoryChangedSegmentDuration {
-                delta: delta(last, this),
+                delta: delta_segment(last, this, this_seg.number(), Some("EXTINF")),
                 msn: this_seg.number(),
                 last_duration_millis: last_seg.duration().duration().as_millis() as u64,
                 this_duration_millis: this_seg.duration().duration().as_millis() as u64,
             });
         }
+        let last_map = last.manifest.segment(last_seg.number() as u64).and_then(|s| s.map.as_ref() );
+        let this_map = this.manifest.segment(this_seg.number() as u64).and_then(|s| s.map.as_ref() );
+        let same_map = match (last_map, this_map) {
+            (Some(a), Some(b)) => a.same_section(b),
+            (None, None) => true,
+            _ => false,
+        };
+        if !same_map {
+            self.log.error(HlsEvent::ManifestHistoryChangedMap {
+                delta: delta_at(last, last_map.map(|m| m.line ), this, this_map.map(|m| m.line )),
+                msn: this_seg.number(),
+                last_map: last_map.map(|m| m.to_string() ),
+                this_map: this_map.map(|m| m.to_string() ),
+            });
+        }
+        let last_keys = last.manifest.segment(last_seg.number() as u64).map(|s| &s.keys[..] ).unwrap_or(&[]);
+        let this_keys = this.manifest.segment(this_seg.number() as u64).map(|s| &s.keys[..] ).unwrap_or(&[]);
+        if !same_keys(last_keys, this_keys, true) {
+            self.log.error(HlsEvent::ManifestHistoryChangedKey {
+                delta: delta_at(last, last_keys.last().map(|k| k.line ), this, this_keys.last().map(|k| k.line )),
+                msn: this_seg.number(),
+                last_keys: last_keys.iter().map(|k| k.to_string() ).collect(),
+                this_keys: this_keys.iter().map(|k| k.to_string() ).collect(),
+            });
+        }
         if last_seg.byte_range() != this_seg.byte_range() {
             self.log.error(HlsEvent::ManifestHistoryChangedSegmentByterange {
-                delta: delta(last, this),
+                delta: delta_segment(last, this, this_seg.number(), Some("EXT-X-BYTERANGE")),
                 msn: this_seg.number(),
                 last_byterange: last_seg.byte_range().map(|r| r.as_byte_range().to_string() ),
                 this_byterange: this_seg.byte_range().map(|r| r.as_byte_range().to_string() ),
@@ -382,6 +907,124 @@ impl<L: EventSink<Extra = HlsEvent>, M: Metric> MediaPlaylistCheck<L, M> {
         }
     }
 
+    fn check_part_history_invariant(&mut self, last: &PlaylistInfo, this: &PlaylistInfo) {
+        for this_part in &this.manifest.parts {
+            if let Some(last_part) = last.manifest.part(this_part.msn, this_part.part_num) {
+                if last_part.uri != this_part.uri {
+                    self.log.error(HlsEvent::ManifestHistoryChangedPartUri {
+                        delta: delta_at(last, Some(last_part.line), this, Some(this_part.line)),
+                        msn: this_part.msn,
+                        part: this_part.part_num,
+                        last_uri: last_part.uri.clone(),
+                        this_uri: this_part.uri.clone(),
+                    });
+                    continue;
+                }
+                if last_part.duration != this_part.duration {
+                    self.log.error(HlsEvent::ManifestHistoryChangedPartDuration {
+                        delta: delta_at(last, Some(last_part.line), this, Some(this_part.line)),
+                        msn: this_part.msn,
+                        part: this_part.part_num,
+                        last_duration_millis: secs_to_millis(last_part.duration),
+                        this_duration_millis: secs_to_millis(this_part.duration),
+                    });
+                }
+            }
+        }
+    }
+
+    /// The part hinted by `EXT-X-PRELOAD-HINT` in the last playlist should turn up with the same
+    /// URI once it is listed in a later playlist
+    fn check_preload_hint(&mut self, last: &PlaylistInfo, this: &PlaylistInfo) {
+        if let Some(hint) = last.manifest.preload_hint(PreloadHintType::Part) {
+            if hint.byte_range_start.is_some() {
+                // parts addressed by byte range may legitimately share a URI
+                return;
+            }
+            if let Some(part) = this.manifest.part(hint.msn, hint.part_num) {
+                if part.uri != hint.uri {
+                    self.log.warning(HlsEvent::PreloadHintNotHonoured {
+                        delta: delta_at(last, Some(hint.line), this, Some(part.line)),
+                        msn: hint.msn,
+                        part: hint.part_num,
+                        hint_uri: hint.uri.clone(),
+                        this_uri: part.uri.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Checks the partial segments of the playlist.  Parts and segments that were already present
+    /// in the `last` playlist have been checked before, and are skipped.
+    fn check_parts(&mut self, last: Option<&PlaylistInfo>, this: &PlaylistInfo) {
+        let manifest = &this.manifest;
+        if last.is_none() {
+            self.check_low_latency_configuration(this);
+        }
+        if let Some(part_inf) = &manifest.part_inf {
+            let new_parts = manifest.parts
+                .iter()
+                .filter(|p| last.map(|l| l.manifest.part(p.msn, p.part_num).is_none() ).unwrap_or(true) );
+            for part in new_parts {
+                if part.duration > part_inf.part_target {
+                    self.log.error(HlsEvent::PartTargetDurationExceeded {
+                        req_id: this.href.clone(),
+                        msn: part.msn,
+                        part: part.part_num,
+                        duration_millis: secs_to_millis(part.duration),
+                        part_target_millis: secs_to_millis(part_inf.part_target),
+                    })
+                }
+            }
+        }
+        let new_segments = manifest.segments
+            .iter()
+            .filter(|s| last.map(|l| l.manifest.segment(s.msn).is_none() ).unwrap_or(true) );
+        for seg in new_segments {
+            let extinf = if let Some(d) = seg.duration { d } else { continue };
+            // older segments have their parts removed from the playlist, so only check segments
+            // where the parts are listed from the very first one
+            if manifest.part(seg.msn, 0).is_none() {
+                continue;
+            }
+            let parts_total: f32 = manifest.parts_of(seg.msn).map(|p| p.duration ).sum();
+            if (parts_total - extinf).abs() > PART_DURATION_SUM_TOLERANCE {
+                self.log.error(HlsEvent::PartDurationsMismatchExtinf {
+                    req_id: this.href.clone(),
+                    msn: seg.msn,
+                    extinf_millis: secs_to_millis(extinf),
+                    parts_total_millis: secs_to_millis(parts_total),
+                })
+            }
+        }
+    }
+
+    fn check_low_latency_configuration(&mut self, this: &PlaylistInfo) {
+        let manifest = &this.manifest;
+        match manifest.part_inf {
+            None => if !manifest.parts.is_empty() {
+                self.log.error(HlsEvent::MissingPartInf {
+                    req_id: this.href.clone(),
+                })
+            },
+            Some(ref part_inf) => {
+                match manifest.server_control.as_ref().and_then(|c| c.part_hold_back ) {
+                    None => self.log.error(HlsEvent::MissingPartHoldBack {
+                        req_id: this.href.clone(),
+                    }),
+                    Some(part_hold_back) => if part_hold_back < part_inf.part_target * 2.0 {
+                        self.log.error(HlsEvent::PartHoldBackTooShort {
+                            req_id: this.href.clone(),
+                            part_hold_back_millis: secs_to_millis(part_hold_back),
+                            part_target_millis: secs_to_millis(part_inf.part_target),
+                        })
+                    },
+                }
+            }
+        }
+    }
+
     fn check_initial_configuration(&mut self, this: &PlaylistInfo) {
         let content_type = this.href.info().response.as_ref().unwrap().headers.get(reqwest::header::CONTENT_TYPE);
         if content_type != Some(&HeaderValue::from_static("application/vnd.apple.mpegurl")) {
@@ -431,6 +1074,223 @@ impl<L: EventSink<Extra = HlsEvent>, M: Metric> MediaPlaylistCheck<L, M> {
             }
         }
     }
+    /// Checks the rules that the specification gives for `EXT-X-DATERANGE` tags, besides those
+    /// about attributes changing, for tags new since the `last` playlist
+    fn check_daterange_rules(&mut self, last: Option<&PlaylistInfo>, this: &PlaylistInfo) {
+        for range in new_dateranges(last, this) {
+            let at = || manifest_ref(this, Some(range.line));
+            if let (Some(start_date), Some(end_date)) = (range.start_date, range.end_date) {
+                if end_date < start_date {
+                    self.log.error(HlsEvent::DaterangeEndBeforeStart {
+                        at: at(),
+                        daterange_id: range.id.clone(),
+                        start_date: start_date.to_rfc3339(),
+                        end_date: end_date.to_rfc3339(),
+                    })
+                } else if let Some(duration) = range.duration {
+                    let duration_millis = secs_to_millis(duration) as i64;
+                    let end_minus_start_millis = (end_date - start_date).num_milliseconds();
+                    if (duration_millis - end_minus_start_millis).abs() > DATERANGE_DURATION_TOLERANCE_MILLIS {
+                        self.log.error(HlsEvent::DaterangeDurationMismatch {
+                            at: at(),
+                            daterange_id: range.id.clone(),
+                            duration_millis: duration_millis as u64,
+                            end_minus_start_millis: end_minus_start_millis as u64,
+                        })
+                    }
+                }
+            }
+            if range.end_on_next {
+                if range.class.is_none() {
+                    self.log.error(HlsEvent::DaterangeEndOnNextWithoutClass {
+                        at: at(),
+                        daterange_id: range.id.clone(),
+                    })
+                }
+                if range.duration.is_some() || range.end_date.is_some() {
+                    self.log.error(HlsEvent::DaterangeEndOnNextWithEnd {
+                        at: at(),
+                        daterange_id: range.id.clone(),
+                        duration: range.duration.is_some(),
+                        end_date: range.end_date.is_some(),
+                    })
+                }
+            }
+        }
+        self.check_daterange_overlaps(this);
+        // only reported when the playlist first comes to lack a program-date-time
+        let lacks_program_date_time = |info: &PlaylistInfo| {
+            !info.manifest.dateranges.is_empty()
+                && !info.manifest.segments.iter().any(|s| s.tag_line("EXT-X-PROGRAM-DATE-TIME").is_some() )
+        };
+        if lacks_program_date_time(this) && !last.map(lacks_program_date_time).unwrap_or(false) {
+            self.log.error(HlsEvent::DaterangeWithoutProgramDateTime {
+                at: manifest_ref(this, this.manifest.dateranges.first().map(|d| d.line )),
+            })
+        }
+    }
+
+    /// "EXT-X-DATERANGE tags with the same CLASS attribute MUST NOT indicate Date Ranges that
+    /// overlap".  Ranges are only compared once the end of each is known, from its `END-DATE` or
+    /// `DURATION`, which may be given by a later tag with the same ID.
+    fn check_daterange_overlaps(&mut self, this: &PlaylistInfo) {
+        // forget the pairs including a tag that has left the playlist
+        let listed: HashSet<&str> = this.manifest.dateranges.iter().map(|r| r.id.as_str() ).collect();
+        self.reported_daterange_overlaps.retain(|(a, b)| listed.contains(a.as_str()) && listed.contains(b.as_str()) );
+        let mut ranges: Vec<DaterangeExtent<'_>> = vec![];
+        for range in &this.manifest.dateranges {
+            let class = match range.class {
+                Some(ref class) => class.as_str(),
+                None => continue,
+            };
+            let end = range.end_date.or_else(|| {
+                let duration = chrono::Duration::milliseconds(secs_to_millis(range.duration?) as i64);
+                range.start_date.map(|start| start + duration )
+            });
+            match ranges.iter_mut().find(|r| r.id == range.id ) {
+                Some(r) => {
+                    r.start = r.start.or(range.start_date);
+                    r.end = r.end.or(end);
+                    r.line = range.line;
+                },
+                None => ranges.push(DaterangeExtent { class, id: &range.id, start: range.start_date, end, line: range.line }),
+            }
+        }
+        for (i, this_range) in ranges.iter().enumerate() {
+            for other in ranges[..i].iter().filter(|r| r.class == this_range.class ) {
+                if this_range.overlaps(other) && self.reported_daterange_overlaps.insert((other.id.to_string(), this_range.id.to_string())) {
+                    self.log.error(HlsEvent::DaterangeOverlap {
+                        at: manifest_ref(this, Some(this_range.line)),
+                        daterange_id: this_range.id.to_string(),
+                        other_daterange_id: other.id.to_string(),
+                        class: this_range.class.to_string(),
+                    })
+                }
+            }
+        }
+    }
+
+    /// Decodes the SCTE-35 attributes of `EXT-X-DATERANGE` tags new since the `last` playlist, and
+    /// checks them against each other and against the tags carrying them
+    fn check_scte35(&mut self, last: Option<&PlaylistInfo>, this: &PlaylistInfo) {
+        // forget the tags that have left the playlist, as the timeline forgets removed segments, so
+        // that their event IDs may be reused
+        let listed: HashSet<&str> = this.manifest.dateranges.iter().map(|r| r.id.as_str() ).collect();
+        self.scte35_outs.retain(|id, _| listed.contains(id.as_str()) );
+        self.scte35_event_ids.retain(|_, id| listed.contains(id.as_str()) );
+        for range in new_dateranges(last, this) {
+            for (attr_name, value) in range.scte35_attributes() {
+                let at = manifest_ref(this, Some(range.line));
+                let section = match scte35::parse_hex(value).and_then(|data| scte35::parse_splice_info_section(&data) ) {
+                    Ok(section) => section,
+                    Err(e) => {
+                        self.log.error(HlsEvent::Scte35Invalid {
+                            at,
+                            daterange_id: range.id.clone(),
+                            attr_name,
+                            message: format!("{:?}", e),
+                        });
+                        continue;
+                    }
+                };
+                self.check_splice_info(at, range, attr_name, section);
+            }
+        }
+    }
+
+    fn check_splice_info(&mut self, at: ManifestRef, range: &DateRange, attr_name: &'static str, section: scte35::SpliceInfoSection) {
+        if !section.crc_valid {
+            self.log.error(HlsEvent::Scte35CrcMismatch {
+                at: at.clone(),
+                daterange_id: range.id.clone(),
+                attr_name,
+                splice_info: section.clone(),
+            })
+        }
+        let expected_out_of_network = match attr_name {
+            "SCTE35-OUT" => Some(true),
+            "SCTE35-IN" => Some(false),
+            _ => None,
+        };
+        if let (Some(expected), Some(out_of_network)) = (expected_out_of_network, section.out_of_network()) {
+            if expected != out_of_network {
+                self.log.error(HlsEvent::Scte35OutOfNetworkMismatch {
+                    at: at.clone(),
+                    daterange_id: range.id.clone(),
+                    attr_name,
+                    splice_info: section.clone(),
+                })
+            }
+        }
+        // the duration of a splice in is not that of the break
+        if attr_name != "SCTE35-IN" {
+            let declared = match (range.duration, range.planned_duration) {
+                (Some(d), _) => Some(("DURATION", d)),
+                (None, Some(d)) => Some(("PLANNED-DURATION", d)),
+                _ => None,
+            };
+            if let (Some((duration_attr, duration)), Some(ticks)) = (declared, section.duration()) {
+                let duration_millis = secs_to_millis(duration);
+                let scte35_duration_millis = ticks * 1000 / scte35::TIMESTAMP_RATE;
+                let diff = if duration_millis > scte35_duration_millis { duration_millis - scte35_duration_millis } else { scte35_duration_millis - duration_millis };
+                if diff > SCTE35_DURATION_TOLERANCE_MILLIS {
+                    self.log.error(HlsEvent::Scte35DurationMismatch {
+                        at: at.clone(),
+                        daterange_id: range.id.clone(),
+                        attr_name,
+                        duration_attr,
+                        duration_millis,
+                        scte35_duration_millis,
+                        splice_info: section.clone(),
+                    })
+                }
+            }
+        }
+        let event_id = section.event_id();
+        if let Some(event_id) = event_id {
+            match self.scte35_event_ids.get(&event_id) {
+                Some(other_id) if other_id != &range.id => {
+                    self.log.error(HlsEvent::Scte35EventIdReused {
+                        at: at.clone(),
+                        daterange_id: range.id.clone(),
+                        other_daterange_id: other_id.clone(),
+                        event_id,
+                        splice_info: section.clone(),
+                    })
+                },
+                Some(_) => {},
+                None => {
+                    self.scte35_event_ids.insert(event_id, range.id.clone());
+                },
+            }
+        }
+        match attr_name {
+            "SCTE35-OUT" => {
+                self.scte35_outs.insert(range.id.clone(), event_id);
+            },
+            "SCTE35-IN" => match self.scte35_outs.get(&range.id) {
+                None => {
+                    self.log.error(HlsEvent::Scte35InWithoutOut {
+                        at,
+                        daterange_id: range.id.clone(),
+                        splice_info: section,
+                    })
+                },
+                Some(Some(out_event_id)) if event_id.is_some() && event_id != Some(*out_event_id) => {
+                    self.log.error(HlsEvent::Scte35InEventIdMismatch {
+                        at,
+                        daterange_id: range.id.clone(),
+                        out_event_id: *out_event_id,
+                        in_event_id: event_id.unwrap(),
+                        splice_info: section,
+                    })
+                },
+                Some(_) => {},
+            },
+            _ => {},
+        }
+    }
+
     fn check_daterange_attr_invariants(&mut self, this: &PlaylistInfo, this_range: &hls_m3u8::parser::ExtXDateRange, last_range: &hls_m3u8::parser::ExtXDateRange) {
         if let (Some(this_duration), Some(last_duration)) = (this_range.duration, last_range.duration) {
             if this_duration != last_duration {
@@ -0,0 +1,319 @@
+//! Structural checks of the DRM key system metadata given by `EXT-X-KEY` and `EXT-X-SESSION-KEY`
+//! tags.  No keys or licences are requested; only the playlists themselves, and the `pssh` boxes
+//! carried in their `data:` URIs, are examined.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::event_log::EventSink;
+use crate::http_snoop::HttpRef;
+use crate::mp4;
+use super::{HlsEvent, ManifestRef};
+use super::media_manifest::{Key, MediaManifest};
+
+/// The `KEYFORMAT` of FairPlay Streaming, whose keys are identified by `skd:` URIs
+const FAIRPLAY_KEYFORMAT: &str = "com.apple.streamingkeydelivery";
+
+/// The `KEYFORMAT` of PlayReady, whose `data:` URIs carry either a `pssh` box or a PlayReady Object
+const PLAYREADY_KEYFORMAT: &str = "com.microsoft.playready";
+const PLAYREADY_SYSTEM_ID: &str = "9a04f079-9840-4286-ab92-e65be0885f95";
+
+/// The prefix of a `KEYFORMAT` giving the DRM system ID of the `pssh` box in the key's `data:` URI,
+/// as used for Widevine
+const UUID_KEYFORMAT_PREFIX: &str = "urn:uuid:";
+
+/// The DRM system ID expected in the `pssh` box of keys with the given `KEYFORMAT`, if known
+fn expected_system_id(keyformat: &str) -> Option<uuid::Uuid> {
+    if keyformat == PLAYREADY_KEYFORMAT {
+        return uuid::Uuid::parse_str(PLAYREADY_SYSTEM_ID).ok();
+    }
+    if let Some(uuid) = keyformat.strip_prefix(UUID_KEYFORMAT_PREFIX) {
+        return uuid::Uuid::parse_str(uuid).ok();
+    }
+    None
+}
+
+/// Decodes the data of a `data:` URI, which is expected to be base64 encoded
+fn data_uri(uri: &str) -> Result<Vec<u8>, String> {
+    let rest = &uri["data:".len()..];
+    let comma = rest.find(',').ok_or_else(|| "missing ',' following the media type".to_string() )?;
+    if !rest[..comma].split(';').any(|p| p == "base64") {
+        return Err("data is not base64 encoded".to_string());
+    }
+    base64::decode(&rest[comma + 1..]).map_err(|e| e.to_string() )
+}
+
+fn key_id(id: u128) -> String {
+    format!("0x{:032X}", id)
+}
+
+fn key_ref(href: &HttpRef, manifest: &MediaManifest, key: &Key) -> ManifestRef {
+    ManifestRef {
+        req_id: href.clone(),
+        // lines of a rebuilt playlist don't correspond to the response body
+        line: if manifest.rebuilt { None } else { Some(key.line + 1) },
+    }
+}
+
+struct SessionKeys {
+    href: HttpRef,
+    keys: Vec<Key>,
+}
+
+struct VariantKeys {
+    href: HttpRef,
+    /// the keys of each segment in the last copy of the variant's playlist
+    segments: BTreeMap<u64, Vec<Key>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    session_keys: Option<SessionKeys>,
+    variants: HashMap<reqwest::Url, VariantKeys>,
+    /// `(keyformat, value, other_value)` of the differences between variants already reported, so
+    /// that they are not repeated for every segment
+    reported: HashSet<(String, String, String)>,
+}
+
+/// Shared between the tasks processing each media playlist, so that the keys of each can be
+/// compared with the `EXT-X-SESSION-KEY` tags of the main playlist, and the keys of the variant
+/// streams compared with each other
+#[derive(Clone, Default)]
+pub struct DrmKeys {
+    shared: Rc<RefCell<Shared>>,
+}
+impl DrmKeys {
+    /// record the `EXT-X-SESSION-KEY` tags of the main playlist loaded by the given request
+    pub fn set_session_keys(&self, href: &HttpRef, keys: &[Key]) {
+        self.shared.borrow_mut().session_keys = Some(SessionKeys {
+            href: href.clone(),
+            keys: keys.to_vec(),
+        });
+    }
+
+    /// The checks for the media playlist at the given URL.  The keys of variant streams of the
+    /// ladder are expected to match segment-for-segment.
+    pub fn playlist(&self, url: &reqwest::Url, ladder: bool) -> PlaylistKeys {
+        PlaylistKeys {
+            shared: self.shared.clone(),
+            url: url.clone(),
+            ladder,
+        }
+    }
+
+    /// forget the playlist at the given URL, which is no longer listed in the main playlist
+    pub fn remove(&self, url: &reqwest::Url) {
+        self.shared.borrow_mut().variants.remove(url);
+    }
+}
+
+/// Checks the keys of a single media playlist
+pub struct PlaylistKeys {
+    shared: Rc<RefCell<Shared>>,
+    url: reqwest::Url,
+    ladder: bool,
+}
+impl PlaylistKeys {
+    /// Checks the keys of segments new since the last copy of the playlist.  Each distinct key is
+    /// checked once, when first used by a new segment.
+    pub fn update<L: EventSink<Extra = HlsEvent>>(&self, href: &HttpRef, manifest: &MediaManifest, log: &mut L) {
+        let mut shared = self.shared.borrow_mut();
+        let prev_last_msn = shared.variants.get(&self.url).and_then(|v| v.segments.keys().next_back().copied() );
+        let is_new = |msn: u64| prev_last_msn.map(|last| msn > last ).unwrap_or(true);
+        let mut checked: Vec<&Key> = manifest.segments.iter()
+            .filter(|s| !is_new(s.msn) )
+            .flat_map(|s| s.keys.iter() )
+            .collect();
+        for seg in manifest.segments.iter().filter(|s| is_new(s.msn) ) {
+            for key in &seg.keys {
+                if checked.iter().any(|k| k.same_key(key) ) {
+                    continue;
+                }
+                checked.push(key);
+                let at = key_ref(href, manifest, key);
+                check_key_system(&at, key, log);
+                if let Some(ref session_keys) = shared.session_keys {
+                    check_session_key(&at, session_keys, key, log);
+                }
+            }
+        }
+        if self.ladder {
+            let shared = &mut *shared;
+            for seg in manifest.segments.iter().filter(|s| is_new(s.msn) ) {
+                for (other_url, other) in shared.variants.iter() {
+                    if other_url == &self.url {
+                        continue;
+                    }
+                    if let Some(other_keys) = other.segments.get(&seg.msn) {
+                        check_variant_keys(href, seg.msn, &seg.keys, &other.href, other_keys, &mut shared.reported, log);
+                    }
+                }
+            }
+        }
+        shared.variants.insert(self.url.clone(), VariantKeys {
+            href: href.clone(),
+            segments: manifest.segments.iter().map(|s| (s.msn, s.keys.clone()) ).collect(),
+        });
+    }
+}
+
+/// FairPlay keys are expected to have an `skd:` URI, and keys of other known DRM systems a `data:`
+/// URI holding a `pssh` box for that system, listing the `KEYID` if it lists key IDs at all
+fn check_key_system<L: EventSink<Extra = HlsEvent>>(at: &ManifestRef, key: &Key, log: &mut L) {
+    let uri = match key.uri {
+        Some(ref uri) => uri,
+        None => return,
+    };
+    let keyformat = key.keyformat();
+    if keyformat == FAIRPLAY_KEYFORMAT {
+        if !uri.starts_with("skd:") {
+            log.error(HlsEvent::KeyUriSchemeMismatch {
+                at: at.clone(),
+                keyformat: keyformat.to_string(),
+                uri: uri.clone(),
+                expected_scheme: "skd",
+            })
+        }
+        return;
+    }
+    let system_id = match expected_system_id(keyformat) {
+        Some(id) => id,
+        None => return,
+    };
+    if !uri.starts_with("data:") {
+        log.error(HlsEvent::KeyUriSchemeMismatch {
+            at: at.clone(),
+            keyformat: keyformat.to_string(),
+            uri: uri.clone(),
+            expected_scheme: "data",
+        });
+        return;
+    }
+    let data = match data_uri(uri) {
+        Ok(data) => data,
+        Err(message) => {
+            log.error(HlsEvent::KeyPsshInvalid {
+                at: at.clone(),
+                keyformat: keyformat.to_string(),
+                message,
+            });
+            return;
+        }
+    };
+    if keyformat == PLAYREADY_KEYFORMAT && !mp4::is_pssh(&data) {
+        // a PlayReady Object, which is not examined
+        return;
+    }
+    let pssh = match mp4::parse_pssh(&data) {
+        Ok(pssh) => pssh,
+        Err(e) => {
+            log.error(HlsEvent::KeyPsshInvalid {
+                at: at.clone(),
+                keyformat: keyformat.to_string(),
+                message: format!("{:?}", e),
+            });
+            return;
+        }
+    };
+    if &pssh.system_id != system_id.as_bytes() {
+        log.error(HlsEvent::KeyPsshSystemIdMismatch {
+            at: at.clone(),
+            keyformat: keyformat.to_string(),
+            system_id: uuid::Uuid::from_bytes(pssh.system_id).to_string(),
+        })
+    }
+    if let Some(keyid) = key.keyid {
+        if !pssh.key_ids.is_empty() && !pssh.key_ids.iter().any(|k| u128::from_be_bytes(*k) == keyid ) {
+            log.error(HlsEvent::KeyIdNotInPssh {
+                at: at.clone(),
+                keyid: key_id(keyid),
+                pssh_key_ids: pssh.key_ids.iter().map(|k| key_id(u128::from_be_bytes(*k)) ).collect(),
+            })
+        }
+    }
+}
+
+/// "If an EXT-X-SESSION-KEY is used, the values of the METHOD, KEYFORMAT, and KEYFORMATVERSIONS
+/// attributes MUST match any EXT-X-KEY with the same URI value."
+fn check_session_key<L: EventSink<Extra = HlsEvent>>(at: &ManifestRef, session_keys: &SessionKeys, key: &Key, log: &mut L) {
+    for session_key in session_keys.keys.iter().filter(|k| k.uri == key.uri ) {
+        if session_key.method != key.method
+            || session_key.keyformat() != key.keyformat()
+            || session_key.keyformatversions() != key.keyformatversions()
+        {
+            log.error(HlsEvent::SessionKeyMismatch {
+                at: at.clone(),
+                main_req_id: session_keys.href.clone(),
+                session_key: session_key.to_string(),
+                key: key.to_string(),
+            })
+        }
+    }
+}
+
+/// The same segment in each variant stream is expected to be encrypted with the same key, having
+/// the same `KEYFORMATVERSIONS` and `KEYID`, for each `KEYFORMAT` they have in common
+fn check_variant_keys<L: EventSink<Extra = HlsEvent>>(
+    href: &HttpRef,
+    msn: u64,
+    keys: &[Key],
+    other_href: &HttpRef,
+    other_keys: &[Key],
+    reported: &mut HashSet<(String, String, String)>,
+    log: &mut L,
+) {
+    for key in keys {
+        let keyformat = key.keyformat();
+        let other = match other_keys.iter().find(|k| k.keyformat() == keyformat ) {
+            Some(k) => k,
+            None => continue,
+        };
+        let versions = (keyformat.to_string(), key.keyformatversions().to_string(), other.keyformatversions().to_string());
+        if versions.1 != versions.2 && reported.insert(versions.clone()) {
+            log.error(HlsEvent::VariantKeyFormatVersionsMismatch {
+                req_id: href.clone(),
+                other_req_id: other_href.clone(),
+                msn,
+                keyformat: keyformat.to_string(),
+                keyformatversions: versions.1,
+                other_keyformatversions: versions.2,
+            })
+        }
+        if key.keyid != other.keyid {
+            let keyid = key.keyid.map(key_id);
+            let other_keyid = other.keyid.map(key_id);
+            let difference = (keyformat.to_string(), format!("{:?}", keyid), format!("{:?}", other_keyid));
+            if reported.insert(difference) {
+                log.error(HlsEvent::VariantKeyIdMismatch {
+                    req_id: href.clone(),
+                    other_req_id: other_href.clone(),
+                    msn,
+                    keyformat: keyformat.to_string(),
+                    keyid,
+                    other_keyid,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn data_uris() {
+        assert_eq!(data_uri("data:text/plain;base64,AAECAw=="), Ok(vec![0, 1, 2, 3]));
+        assert!(data_uri("data:text/plain,AAECAw==").is_err());
+        assert!(data_uri("data:text/plain;base64").is_err());
+        assert!(data_uri("data:;base64,!!").is_err());
+    }
+
+    #[test]
+    fn system_ids() {
+        let widevine = expected_system_id("urn:uuid:edef8ba9-79d6-4ace-a3c8-27dcd51d21ed").unwrap();
+        assert_eq!(widevine.as_bytes()[..4], [0xed, 0xef, 0x8b, 0xa9]);
+        assert!(expected_system_id(PLAYREADY_KEYFORMAT).is_some());
+        assert_eq!(expected_system_id("identity"), None);
+    }
+}
@@ -0,0 +1,324 @@
+use crate::hls::{HlsEvent, Delta, ManifestRef};
+use crate::event_log::EventSink;
+use crate::http_snoop::HttpRef;
+use super::main_manifest::{MainManifest, Media, Variant};
+use std::collections::HashMap;
+
+struct MainInfo {
+    manifest: MainManifest,
+    href: HttpRef,
+}
+
+fn manifest_ref(info: &MainInfo, line: Option<usize>) -> ManifestRef {
+    ManifestRef {
+        req_id: info.href.clone(),
+        line: line.map(|l| l + 1 ),
+    }
+}
+
+/// a `Delta` referring to the given (zero-based) line indexes in each playlist
+fn delta_at(before: &MainInfo, before_line: Option<usize>, after: &MainInfo, after_line: Option<usize>) -> Delta {
+    Delta {
+        before: manifest_ref(before, before_line),
+        after: manifest_ref(after, after_line),
+    }
+}
+
+fn body_hash(info: &MainInfo) -> Option<u64> {
+    info.href.info().response.as_ref().ok().and_then(|r| r.hash().ok() )
+}
+
+/// Renditions are identified by their type, group and name, since many have no URI
+fn same_media(a: &Media, b: &Media) -> bool {
+    a.media_type == b.media_type && a.group_id == b.group_id && a.name == b.name
+}
+
+/// The codecs listed in a `CODECS` attribute
+fn codecs(codecs: &Option<String>) -> Vec<&str> {
+    codecs.as_ref()
+        .map(|c| c.split(',').map(|c| c.trim() ).collect() )
+        .unwrap_or_default()
+}
+
+/// The `EXT-X-MEDIA` `TYPE` referred to by each of the group attributes of a variant stream, and the
+/// value of that attribute
+fn group_references(var: &Variant) -> Vec<(&'static str, &str)> {
+    let mut result = vec![];
+    if let Some(ref group_id) = var.audio {
+        result.push(("AUDIO", group_id.as_str()));
+    }
+    if let Some(ref group_id) = var.video {
+        result.push(("VIDEO", group_id.as_str()));
+    }
+    if let Some(ref group_id) = var.subtitles {
+        result.push(("SUBTITLES", group_id.as_str()));
+    }
+    if let Some(ref group_id) = var.closed_captions {
+        // the quoted-string group id is unquoted by the parser, but the enumerated-string NONE is not
+        // distinguishable from a group named "NONE", which is assumed not to be used
+        if group_id != "NONE" {
+            result.push(("CLOSED-CAPTIONS", group_id.as_str()));
+        }
+    }
+    result
+}
+
+/// Checks each copy of the main playlist loaded, comparing it to the last copy
+pub struct MainPlaylistCheck<L: EventSink<Extra = HlsEvent>> {
+    log: L,
+    last_manifest: Option<MainInfo>,
+}
+impl<L: EventSink<Extra = HlsEvent>> MainPlaylistCheck<L> {
+    pub fn new(log: L) -> MainPlaylistCheck<L> {
+        MainPlaylistCheck {
+            log,
+            last_manifest: None,
+        }
+    }
+
+    pub fn next_manifest(&mut self, href: HttpRef, manifest: MainManifest) {
+        let this = MainInfo {
+            href,
+            manifest,
+        };
+        if let Some(last) = self.last_manifest.take() {
+            self.check_variants(&last, &this);
+            self.check_media(&last, &this);
+            // avoid repeating the same reports for every reload of an unchanged playlist
+            if body_hash(&last).is_none() || body_hash(&last) != body_hash(&this) {
+                self.check_manifest(&this);
+            }
+        } else {
+            self.check_manifest(&this);
+        }
+        self.last_manifest = Some(this);
+    }
+
+    /// Checks of a single copy of the main playlist
+    fn check_manifest(&mut self, this: &MainInfo) {
+        self.check_group_references(this);
+        self.check_group_defaults(this);
+        self.check_variant_attributes(this);
+        self.check_i_frame_variants(this);
+        self.check_duplicate_uris(this);
+        self.check_closed_captions(this);
+        self.check_session_keys(this);
+    }
+
+    /// Each group referred to by a variant stream must be defined by `EXT-X-MEDIA` tags of the
+    /// matching `TYPE`
+    fn check_group_references(&mut self, this: &MainInfo) {
+        for var in &this.manifest.variants {
+            for (media_type, group_id) in group_references(var) {
+                let defined = this.manifest.media.iter()
+                    .any(|m| m.media_type == media_type && m.group_id == group_id );
+                if !defined {
+                    self.log.error(HlsEvent::UnresolvedGroupId {
+                        at: manifest_ref(this, Some(var.line)),
+                        uri: var.uri.clone(),
+                        media_type,
+                        group_id: group_id.to_string(),
+                    })
+                }
+            }
+        }
+    }
+
+    /// "A Group MUST NOT have more than one member with a DEFAULT attribute of YES", and without
+    /// one, players will have to choose a rendition for themselves
+    fn check_group_defaults(&mut self, this: &MainInfo) {
+        // the first rendition of each group, and the number of its renditions with DEFAULT=YES
+        let mut groups: Vec<(&Media, usize)> = vec![];
+        for media in &this.manifest.media {
+            let default_count = if media.default { 1 } else { 0 };
+            match groups.iter_mut().find(|(m, _)| m.media_type == media.media_type && m.group_id == media.group_id ) {
+                Some((_, count)) => *count += default_count,
+                None => groups.push((media, default_count)),
+            }
+        }
+        for (media, default_count) in groups {
+            let at = manifest_ref(this, Some(media.line));
+            let media_type = media.media_type.clone();
+            let group_id = media.group_id.clone();
+            if default_count == 0 {
+                self.log.warning(HlsEvent::RenditionGroupWithoutDefault {
+                    at,
+                    media_type,
+                    group_id,
+                })
+            } else if default_count > 1 {
+                self.log.error(HlsEvent::RenditionGroupMultipleDefaults {
+                    at,
+                    media_type,
+                    group_id,
+                    default_count,
+                })
+            }
+        }
+    }
+
+    fn check_variant_attributes(&mut self, this: &MainInfo) {
+        for var in &this.manifest.variants {
+            if var.codecs.is_none() {
+                self.log.warning(HlsEvent::VariantMissingCodecs {
+                    at: manifest_ref(this, Some(var.line)),
+                    uri: var.uri.clone(),
+                })
+            }
+            if let (Some(bandwidth), Some(average_bandwidth)) = (var.bandwidth, var.average_bandwidth) {
+                if average_bandwidth > bandwidth {
+                    self.log.error(HlsEvent::AverageBandwidthExceedsBandwidth {
+                        at: manifest_ref(this, Some(var.line)),
+                        uri: var.uri.clone(),
+                        bandwidth,
+                        average_bandwidth,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Each I-frame stream is expected to correspond to a variant stream with the same resolution
+    /// which includes the I-frame stream's codecs
+    fn check_i_frame_variants(&mut self, this: &MainInfo) {
+        for i_frame in this.manifest.variants.iter().filter(|v| v.i_frame ) {
+            let matched = this.manifest.variants.iter()
+                .filter(|v| !v.i_frame )
+                .any(|v| {
+                    let var_codecs = codecs(&v.codecs);
+                    (i_frame.resolution.is_none() || v.resolution == i_frame.resolution)
+                        && codecs(&i_frame.codecs).iter().all(|c| var_codecs.contains(c) )
+                });
+            if !matched {
+                self.log.warning(HlsEvent::IFrameStreamWithoutVariant {
+                    at: manifest_ref(this, Some(i_frame.line)),
+                    uri: i_frame.uri.clone(),
+                })
+            }
+        }
+    }
+
+    /// "The value of the METHOD attribute MUST NOT be NONE" for `EXT-X-SESSION-KEY`
+    fn check_session_keys(&mut self, this: &MainInfo) {
+        for key in this.manifest.session_keys.iter().filter(|k| k.method == "NONE" ) {
+            self.log.error(HlsEvent::SessionKeyMethodNone {
+                at: manifest_ref(this, Some(key.line)),
+            })
+        }
+    }
+
+    fn check_duplicate_uris(&mut self, this: &MainInfo) {
+        let mut first_lines: HashMap<&str, usize> = HashMap::new();
+        let uris = this.manifest.variants.iter()
+            .map(|v| (v.uri.as_str(), v.uri_line) )
+            .chain(this.manifest.media.iter().filter_map(|m| m.uri.as_ref().map(|u| (u.as_str(), m.line) ) ));
+        for (uri, line) in uris {
+            match first_lines.get(uri) {
+                Some(first_line) => self.log.warning(HlsEvent::DuplicateUri {
+                    at: manifest_ref(this, Some(line)),
+                    uri: uri.to_string(),
+                    first_line: first_line + 1,
+                }),
+                None => {
+                    first_lines.insert(uri, line);
+                },
+            }
+        }
+    }
+
+    /// "If any Variant Stream contains the CLOSED-CAPTIONS attribute with a value of NONE, then all
+    /// EXT-X-STREAM-INF tags MUST have this attribute with a value of NONE", and otherwise variants
+    /// with no closed captions among those that have them suggest a mistake
+    fn check_closed_captions(&mut self, this: &MainInfo) {
+        let variants: Vec<&Variant> = this.manifest.variants.iter().filter(|v| !v.i_frame ).collect();
+        let any_none = variants.iter().any(|v| v.closed_captions.as_deref() == Some("NONE") );
+        let any_group = variants.iter().any(|v| v.closed_captions.as_ref().map(|c| c != "NONE" ).unwrap_or(false) );
+        for var in variants {
+            let consistent = match var.closed_captions.as_deref() {
+                Some("NONE") => !any_group,
+                Some(_) => !any_none,
+                None => !any_none && !any_group,
+            };
+            if !consistent {
+                self.log.error(HlsEvent::ClosedCaptionsInconsistent {
+                    at: manifest_ref(this, Some(var.line)),
+                    uri: var.uri.clone(),
+                    closed_captions: var.closed_captions.clone(),
+                })
+            }
+        }
+    }
+
+    fn check_variants(&mut self, last: &MainInfo, this: &MainInfo) {
+        for last_var in &last.manifest.variants {
+            let this_var = this.manifest.variants.iter().find(|v| v.uri == last_var.uri );
+            let this_var = match this_var {
+                Some(v) => v,
+                None => {
+                    self.log.error(HlsEvent::VariantStreamRemoved {
+                        delta: delta_at(last, Some(last_var.uri_line), this, None),
+                        uri: last_var.uri.clone(),
+                    });
+                    continue;
+                }
+            };
+            if last_var.bandwidth != this_var.bandwidth {
+                self.log.warning(HlsEvent::VariantBandwidthChanged {
+                    delta: delta_at(last, Some(last_var.line), this, Some(this_var.line)),
+                    uri: this_var.uri.clone(),
+                    last_bandwidth: last_var.bandwidth,
+                    this_bandwidth: this_var.bandwidth,
+                })
+            }
+            if last_var.codecs != this_var.codecs {
+                // a player that has already chosen a variant based on its codecs may not be able to
+                // decode the new ones
+                self.log.error(HlsEvent::VariantCodecsChanged {
+                    delta: delta_at(last, Some(last_var.line), this, Some(this_var.line)),
+                    uri: this_var.uri.clone(),
+                    last_codecs: last_var.codecs.clone(),
+                    this_codecs: this_var.codecs.clone(),
+                })
+            }
+            if last_var.resolution != this_var.resolution {
+                self.log.warning(HlsEvent::VariantResolutionChanged {
+                    delta: delta_at(last, Some(last_var.line), this, Some(this_var.line)),
+                    uri: this_var.uri.clone(),
+                    last_resolution: last_var.resolution.clone(),
+                    this_resolution: this_var.resolution.clone(),
+                })
+            }
+        }
+        for this_var in &this.manifest.variants {
+            if !last.manifest.variants.iter().any(|v| v.uri == this_var.uri ) {
+                self.log.warning(HlsEvent::VariantStreamAdded {
+                    delta: delta_at(last, None, this, Some(this_var.uri_line)),
+                    uri: this_var.uri.clone(),
+                })
+            }
+        }
+    }
+
+    fn check_media(&mut self, last: &MainInfo, this: &MainInfo) {
+        for last_media in &last.manifest.media {
+            if !this.manifest.media.iter().any(|m| same_media(m, last_media) ) {
+                self.log.error(HlsEvent::RenditionRemoved {
+                    delta: delta_at(last, Some(last_media.line), this, None),
+                    media_type: last_media.media_type.clone(),
+                    group_id: last_media.group_id.clone(),
+                    name: last_media.name.clone(),
+                })
+            }
+        }
+        for this_media in &this.manifest.media {
+            if !last.manifest.media.iter().any(|m| same_media(m, this_media) ) {
+                self.log.warning(HlsEvent::RenditionAdded {
+                    delta: delta_at(last, None, this, Some(this_media.line)),
+                    media_type: this_media.media_type.clone(),
+                    group_id: this_media.group_id.clone(),
+                    name: this_media.name.clone(),
+                })
+            }
+        }
+    }
+}
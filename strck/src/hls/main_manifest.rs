@@ -0,0 +1,203 @@
+//! Line-based parser for the variant streams and renditions listed by a main playlist, keeping the
+//! attribute values and line numbers that are compared between reloads of the playlist.  The
+//! result is held alongside the `hls_m3u8::MasterPlaylist` parsed from the same response body.
+
+use super::media_manifest::{attributes, Key, MediaManifestError, MediaManifestErrorKind};
+
+pub struct MainManifest {
+    /// the `EXT-X-STREAM-INF` and `EXT-X-I-FRAME-STREAM-INF` variant streams, in playlist order
+    pub variants: Vec<Variant>,
+    /// the `EXT-X-MEDIA` renditions, in playlist order
+    pub media: Vec<Media>,
+    /// the `EXT-X-SESSION-KEY` tags, in playlist order
+    pub session_keys: Vec<Key>,
+}
+impl MainManifest {
+    const TAG_STREAM_INF: &'static str = "#EXT-X-STREAM-INF:";
+    const TAG_I_FRAME_STREAM_INF: &'static str = "#EXT-X-I-FRAME-STREAM-INF:";
+    const TAG_MEDIA: &'static str = "#EXT-X-MEDIA:";
+    const TAG_SESSION_KEY: &'static str = "#EXT-X-SESSION-KEY:";
+
+    pub fn parse(data: &str) -> Result<MainManifest, MediaManifestError> {
+        let mut variants = vec![];
+        let mut media = vec![];
+        let mut session_keys = vec![];
+        // an EXT-X-STREAM-INF tag, waiting for the URI line that follows it
+        let mut stream_inf: Option<Variant> = None;
+        for (number, l) in data.lines().enumerate() {
+            let l = l.trim();
+            let attrs = |tag: &str| {
+                attributes(&l[tag.len()..]).map_err(|kind| MediaManifestError { line: number, kind })
+            };
+            if l.starts_with(Self::TAG_STREAM_INF) {
+                stream_inf = Some(Variant::from_attributes(&attrs(Self::TAG_STREAM_INF)?, false, number)?);
+            } else if l.starts_with(Self::TAG_I_FRAME_STREAM_INF) {
+                let attrs = attrs(Self::TAG_I_FRAME_STREAM_INF)?;
+                let variant = Variant::from_attributes(&attrs, true, number)?;
+                if variant.uri.is_empty() {
+                    return Err(MediaManifestError { line: number, kind: MediaManifestErrorKind::MissingUriAttribute });
+                }
+                variants.push(variant);
+            } else if l.starts_with(Self::TAG_MEDIA) {
+                media.push(Media::from_attributes(&attrs(Self::TAG_MEDIA)?, number)?);
+            } else if l.starts_with(Self::TAG_SESSION_KEY) {
+                session_keys.push(Key::parse(attrs(Self::TAG_SESSION_KEY)?, number)?);
+            } else if !l.is_empty() && !l.starts_with('#') {
+                if let Some(mut variant) = stream_inf.take() {
+                    variant.uri = l.to_string();
+                    variant.uri_line = number;
+                    variants.push(variant);
+                }
+            }
+        }
+        Ok(MainManifest {
+            variants,
+            media,
+            session_keys,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub uri: String,
+    /// true for `EXT-X-I-FRAME-STREAM-INF`, rather than `EXT-X-STREAM-INF`
+    pub i_frame: bool,
+    pub bandwidth: Option<u64>,
+    pub average_bandwidth: Option<u64>,
+    pub codecs: Option<String>,
+    pub resolution: Option<String>,
+    pub audio: Option<String>,
+    pub video: Option<String>,
+    pub subtitles: Option<String>,
+    pub closed_captions: Option<String>,
+    /// zero-based index of the line holding the tag
+    pub line: usize,
+    /// zero-based index of the line holding the URI, which is the same as `line` for I-frame
+    /// streams
+    pub uri_line: usize,
+}
+impl Variant {
+    fn from_attributes(attrs: &[(&str, &str)], i_frame: bool, line: usize) -> Result<Variant, MediaManifestError> {
+        let mut variant = Variant {
+            uri: String::new(),
+            i_frame,
+            bandwidth: None,
+            average_bandwidth: None,
+            codecs: None,
+            resolution: None,
+            audio: None,
+            video: None,
+            subtitles: None,
+            closed_captions: None,
+            line,
+            uri_line: line,
+        };
+        for (k, v) in attrs {
+            match *k {
+                "URI" => variant.uri = v.to_string(),
+                "BANDWIDTH" => variant.bandwidth = Some(integer(line, "BANDWIDTH", v)?),
+                "AVERAGE-BANDWIDTH" => variant.average_bandwidth = Some(integer(line, "AVERAGE-BANDWIDTH", v)?),
+                "CODECS" => variant.codecs = Some(v.to_string()),
+                "RESOLUTION" => variant.resolution = Some(v.to_string()),
+                "AUDIO" => variant.audio = Some(v.to_string()),
+                "VIDEO" => variant.video = Some(v.to_string()),
+                "SUBTITLES" => variant.subtitles = Some(v.to_string()),
+                "CLOSED-CAPTIONS" => variant.closed_captions = Some(v.to_string()),
+                _ => (),
+            }
+        }
+        Ok(variant)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Media {
+    /// the `TYPE` attribute, e.g. `AUDIO`
+    pub media_type: String,
+    pub group_id: String,
+    pub name: String,
+    pub uri: Option<String>,
+    pub default: bool,
+    pub autoselect: bool,
+    pub instream_id: Option<String>,
+    /// zero-based index of the line holding the tag
+    pub line: usize,
+}
+impl Media {
+    fn from_attributes(attrs: &[(&str, &str)], line: usize) -> Result<Media, MediaManifestError> {
+        let mut media_type = None;
+        let mut group_id = String::new();
+        let mut name = String::new();
+        let mut uri = None;
+        let mut default = false;
+        let mut autoselect = false;
+        let mut instream_id = None;
+        for (k, v) in attrs {
+            match *k {
+                "TYPE" => media_type = Some(v.to_string()),
+                "GROUP-ID" => group_id = v.to_string(),
+                "NAME" => name = v.to_string(),
+                "URI" => uri = Some(v.to_string()),
+                "DEFAULT" => default = *v == "YES",
+                "AUTOSELECT" => autoselect = *v == "YES",
+                "INSTREAM-ID" => instream_id = Some(v.to_string()),
+                _ => (),
+            }
+        }
+        Ok(Media {
+            media_type: media_type.ok_or(MediaManifestError { line, kind: MediaManifestErrorKind::MissingTypeAttribute })?,
+            group_id,
+            name,
+            uri,
+            default,
+            autoselect,
+            instream_id,
+            line,
+        })
+    }
+}
+
+fn integer(line: usize, name: &'static str, value: &str) -> Result<u64, MediaManifestError> {
+    value.parse::<u64>()
+        .map_err(|_| MediaManifestError { line, kind: MediaManifestErrorKind::BadDecimal { name, value: value.to_string() } })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn variants_and_media() {
+        let data = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"English\",DEFAULT=YES,URI=\"audio.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=202000,CODECS=\"mp4a.40.2,avc1.42C01E\",RESOLUTION=192x108,AUDIO=\"aud\"
+video.m3u8
+#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=50000,URI=\"iframe.m3u8\"
+#EXT-X-SESSION-KEY:METHOD=SAMPLE-AES,URI=\"skd://key\",KEYFORMAT=\"com.apple.streamingkeydelivery\"
+";
+        let manifest = MainManifest::parse(data).unwrap();
+        assert_eq!(manifest.variants.len(), 2);
+        let v = &manifest.variants[0];
+        assert_eq!(v.uri, "video.m3u8");
+        assert!(!v.i_frame);
+        assert_eq!(v.bandwidth, Some(202000));
+        assert_eq!(v.codecs.as_deref(), Some("mp4a.40.2,avc1.42C01E"));
+        assert_eq!(v.resolution.as_deref(), Some("192x108"));
+        assert_eq!(v.audio.as_deref(), Some("aud"));
+        assert_eq!((v.line, v.uri_line), (2, 3));
+        let v = &manifest.variants[1];
+        assert_eq!(v.uri, "iframe.m3u8");
+        assert!(v.i_frame);
+        assert_eq!(v.uri_line, 4);
+        assert_eq!(manifest.media.len(), 1);
+        let m = &manifest.media[0];
+        assert_eq!(m.media_type, "AUDIO");
+        assert_eq!(m.group_id, "aud");
+        assert!(m.default);
+        assert_eq!(m.uri.as_deref(), Some("audio.m3u8"));
+        assert_eq!(manifest.session_keys.len(), 1);
+        assert_eq!(manifest.session_keys[0].keyformat(), "com.apple.streamingkeydelivery");
+        assert_eq!(manifest.session_keys[0].line, 5);
+    }
+}
@@ -0,0 +1,1021 @@
+//! Line-based parser for the Low-Latency HLS tags of a media playlist, which
+//! `hls_m3u8::parser::MyMediaPlaylist` does not expose.  The result is held alongside the
+//! `MyMediaPlaylist` parsed from the same response body.
+
+use std::fmt;
+
+#[derive(Debug)]
+pub struct MediaManifestError {
+    pub line: usize,
+    pub kind: MediaManifestErrorKind
+}
+
+fn err(line: usize, kind: MediaManifestErrorKind) -> MediaManifestError {
+    MediaManifestError {
+        line,
+        kind,
+    }
+}
+
+#[derive(Debug)]
+pub enum MediaManifestErrorKind {
+    BadMediaSequenceNumber,
+    BadSegmentDuration(String),
+    BadPartDuration(String),
+    BadDecimal { name: &'static str, value: String },
+    BadPreloadHintType(String),
+    BadByteRange(String),
+    BadIv(String),
+    BadKeyId(String),
+    BadDate { name: &'static str, value: String },
+    MalformedAttributeList,
+    MissingDurationAttribute,
+    MissingUriAttribute,
+    MissingTypeAttribute,
+    MissingMethodAttribute,
+    MissingIdAttribute,
+    MissingPartTargetAttribute,
+    MissingSkippedSegmentsAttribute,
+    /// the media sequence number following a segment (or skipped segments) exceeds the largest
+    /// possible value
+    MediaSequenceOverflow,
+    /// a segment has more partial segments than can be numbered
+    TooManyParts,
+}
+impl MediaManifestErrorKind {
+    /// Identifies the sort of problem, as the `kind` of a `PlaylistSyntaxError` event
+    pub fn name(&self) -> &'static str {
+        match self {
+            MediaManifestErrorKind::BadMediaSequenceNumber => "bad_media_sequence_number",
+            MediaManifestErrorKind::BadSegmentDuration(_) => "bad_segment_duration",
+            MediaManifestErrorKind::BadPartDuration(_) => "bad_part_duration",
+            MediaManifestErrorKind::BadDecimal { .. } => "bad_decimal",
+            MediaManifestErrorKind::BadPreloadHintType(_) => "bad_preload_hint_type",
+            MediaManifestErrorKind::BadByteRange(_) => "bad_byte_range",
+            MediaManifestErrorKind::BadIv(_) => "bad_iv",
+            MediaManifestErrorKind::BadKeyId(_) => "bad_key_id",
+            MediaManifestErrorKind::BadDate { .. } => "bad_date",
+            MediaManifestErrorKind::MalformedAttributeList => "malformed_attribute_list",
+            MediaManifestErrorKind::MissingDurationAttribute
+            | MediaManifestErrorKind::MissingUriAttribute
+            | MediaManifestErrorKind::MissingTypeAttribute
+            | MediaManifestErrorKind::MissingMethodAttribute
+            | MediaManifestErrorKind::MissingIdAttribute
+            | MediaManifestErrorKind::MissingPartTargetAttribute
+            | MediaManifestErrorKind::MissingSkippedSegmentsAttribute => "missing_attribute",
+            MediaManifestErrorKind::MediaSequenceOverflow => "media_sequence_overflow",
+            MediaManifestErrorKind::TooManyParts => "too_many_parts",
+        }
+    }
+}
+
+/// The byte offsets of the start and end of the line with the given index, excluding the line
+/// terminator
+pub fn line_span(body: &str, line: usize) -> Option<(usize, usize)> {
+    let mut start = 0;
+    for (index, text) in body.split('\n').enumerate() {
+        if index == line {
+            return Some((start, start + text.trim_end_matches('\r').len()));
+        }
+        start += text.len() + 1;
+    }
+    None
+}
+
+/// Splits an HLS _attribute-list_ into name / value pairs.  The surrounding quotes are removed from
+/// _quoted-string_ values, and a comma within a _quoted-string_ does not end the value.
+pub(crate) fn attributes(list: &str) -> Result<Vec<(&str, &str)>, MediaManifestErrorKind> {
+    let mut result = vec![];
+    let mut rest = list.trim();
+    while !rest.is_empty() {
+        let eq = rest.find('=').ok_or(MediaManifestErrorKind::MalformedAttributeList)?;
+        let name = rest[..eq].trim();
+        rest = rest[eq + 1..].trim_start();
+        let value = if rest.starts_with('"') {
+            let end = rest[1..].find('"').ok_or(MediaManifestErrorKind::MalformedAttributeList)? + 1;
+            let value = &rest[1..end];
+            rest = &rest[end + 1..];
+            value
+        } else {
+            let end = rest.find(',').unwrap_or(rest.len());
+            let value = rest[..end].trim();
+            rest = &rest[end..];
+            value
+        };
+        result.push((name, value));
+        rest = rest.trim_start();
+        if rest.starts_with(',') {
+            rest = &rest[1..];
+        } else if !rest.is_empty() {
+            return Err(MediaManifestErrorKind::MalformedAttributeList);
+        }
+    }
+    Ok(result)
+}
+
+/// Parses a _decimal-floating-point_ value, which excludes negative numbers, `NaN` and `inf`
+fn non_negative_decimal(value: &str) -> Option<f32> {
+    value.parse::<f32>().ok().filter(|v| v.is_finite() && *v >= 0.0 )
+}
+
+fn decimal(line: usize, name: &'static str, value: &str) -> Result<f32, MediaManifestError> {
+    non_negative_decimal(value)
+        .ok_or_else(|| err(line, MediaManifestErrorKind::BadDecimal { name, value: value.to_string() }))
+}
+
+pub struct MediaManifest {
+    /// the value of `EXT-X-VERSION`, if given
+    pub version: Option<u32>,
+    pub media_sequence: u64,
+    /// the value of `EXT-X-DISCONTINUITY-SEQUENCE`, which applies to the first segment
+    pub discontinuity_sequence: u64,
+    pub segments: Vec<Seg>,
+    pub parts: Vec<Part>,
+    pub preload_hints: Vec<PreloadHint>,
+    pub server_control: Option<ServerControl>,
+    pub part_inf: Option<PartInf>,
+    /// present if this is a Playlist Delta Update
+    pub skip: Option<Skip>,
+    pub rendition_reports: Vec<RenditionReport>,
+    /// the `EXT-X-DATERANGE` tags, in playlist order
+    pub dateranges: Vec<DateRange>,
+    /// the name (e.g. `EXT-X-TARGETDURATION`) and line index of each tag that applies to the
+    /// playlist as a whole, rather than to a particular segment
+    pub tags: Vec<(String, usize)>,
+    /// true if parsed from a playlist rebuilt from a Playlist Delta Update, in which case line
+    /// numbers do not refer to the body of any HTTP response
+    pub rebuilt: bool,
+}
+impl MediaManifest {
+    const TAG_VERSION: &'static str = "#EXT-X-VERSION:";
+    const TAG_MEDIA_SEQ: &'static str = "#EXT-X-MEDIA-SEQUENCE:";
+    const TAG_BYTERANGE: &'static str = "#EXT-X-BYTERANGE:";
+    const TAG_MAP: &'static str = "#EXT-X-MAP:";
+    const TAG_KEY: &'static str = "#EXT-X-KEY:";
+    const TAG_DATERANGE: &'static str = "#EXT-X-DATERANGE:";
+    const TAG_DISCONTINUITY_SEQ: &'static str = "#EXT-X-DISCONTINUITY-SEQUENCE:";
+    const TAG_EXTINF: &'static str = "#EXTINF:";
+    const TAG_PART: &'static str = "#EXT-X-PART:";
+    const TAG_PART_INF: &'static str = "#EXT-X-PART-INF:";
+    const TAG_PRELOAD_HINT: &'static str = "#EXT-X-PRELOAD-HINT:";
+    const TAG_SERVER_CONTROL: &'static str = "#EXT-X-SERVER-CONTROL:";
+    const TAG_SKIP: &'static str = "#EXT-X-SKIP:";
+    const TAG_RENDITION_REPORT: &'static str = "#EXT-X-RENDITION-REPORT:";
+    /// tags that apply to the following media segment, rather than to the playlist as a whole
+    const MEDIA_SEGMENT_TAGS: &'static [&'static str] = &[
+        "#EXTINF:",
+        "#EXT-X-BYTERANGE:",
+        "#EXT-X-DISCONTINUITY",
+        "#EXT-X-KEY:",
+        "#EXT-X-MAP:",
+        "#EXT-X-PROGRAM-DATE-TIME:",
+        "#EXT-X-DATERANGE:",
+        "#EXT-X-GAP",
+        "#EXT-X-BITRATE:",
+        "#EXT-X-PART:",
+    ];
+
+    pub fn parse(data: &str) -> Result<MediaManifest, MediaManifestError> {
+        let mut segments = vec![];
+        let mut parts = vec![];
+        let mut preload_hints = vec![];
+        let mut server_control = None;
+        let mut part_inf = None;
+        let mut skip = None;
+        let mut rendition_reports = vec![];
+        let mut dateranges = vec![];
+        let mut version = None;
+        let mut byte_range = None;
+        let mut map = None;
+        let mut keys: Vec<Key> = vec![];
+        let mut media_sequence = 0;
+        let mut discontinuity_sequence = 0;
+        let mut next_msn = 0;
+        let mut next_part_num = 0;
+        let mut duration = None;
+        let mut first_line = None;
+        let mut tags = vec![];
+        let mut segment_tags = vec![];
+        for (number, l) in data.lines().enumerate() {
+            let l = l.trim();
+            let attrs = |tag: &str| {
+                attributes(&l[tag.len()..]).map_err(|kind| err(number, kind))
+            };
+            if l.starts_with("#EXT") {
+                let name = l[1..].split(':').next().unwrap().to_string();
+                if Self::MEDIA_SEGMENT_TAGS.iter().any(|t| l == *t || (t.ends_with(':') && l.starts_with(t)) ) {
+                    if first_line.is_none() {
+                        first_line = Some(number);
+                    }
+                    segment_tags.push((name, number));
+                } else {
+                    tags.push((name, number));
+                }
+            }
+            if let Some(value) = l.strip_prefix(Self::TAG_VERSION) {
+                version = Some(value.parse()
+                    .map_err(|_| err(number, MediaManifestErrorKind::BadDecimal { name: "EXT-X-VERSION", value: value.to_string() }))?);
+            } else if let Some(value) = l.strip_prefix(Self::TAG_BYTERANGE) {
+                byte_range = Some(ByteRange::parse(value).ok_or_else(|| err(number, MediaManifestErrorKind::BadByteRange(value.to_string())))?);
+            } else if l.starts_with(Self::TAG_MAP) {
+                let mut uri = None;
+                let mut byte_range = None;
+                for (k, v) in attrs(Self::TAG_MAP)? {
+                    match k {
+                        "URI" => uri = Some(v),
+                        "BYTERANGE" => byte_range = Some(ByteRange::parse(v).ok_or_else(|| err(number, MediaManifestErrorKind::BadByteRange(v.to_string())))?),
+                        _ => (),
+                    }
+                }
+                map = Some(Map {
+                    uri: uri.ok_or_else(|| err(number, MediaManifestErrorKind::MissingUriAttribute))?.to_string(),
+                    byte_range,
+                    line: number,
+                });
+            } else if l.starts_with(Self::TAG_KEY) {
+                let key = Key::parse(attrs(Self::TAG_KEY)?, number)?;
+                if key.method == "NONE" {
+                    keys.clear();
+                } else {
+                    // keys for other key systems remain in effect alongside this one
+                    keys.retain(|k| k.keyformat() != key.keyformat() );
+                    keys.push(key);
+                }
+            } else if l.starts_with(Self::TAG_DATERANGE) {
+                dateranges.push(DateRange::parse(attrs(Self::TAG_DATERANGE)?, l, next_msn, number)?);
+            } else if let Some(value) = l.strip_prefix(Self::TAG_MEDIA_SEQ) {
+                media_sequence = value
+                    .parse()
+                    .map_err(|_| err(number, MediaManifestErrorKind::BadMediaSequenceNumber))?;
+                next_msn = media_sequence;
+            } else if let Some(value) = l.strip_prefix(Self::TAG_DISCONTINUITY_SEQ) {
+                discontinuity_sequence = value.parse()
+                    .map_err(|_| err(number, MediaManifestErrorKind::BadDecimal { name: "EXT-X-DISCONTINUITY-SEQUENCE", value: value.to_string() }))?;
+            } else if l.starts_with(Self::TAG_SKIP) {
+                let mut skipped_segments = None;
+                let mut recently_removed_dateranges = vec![];
+                for (k, v) in attrs(Self::TAG_SKIP)? {
+                    match k {
+                        "SKIPPED-SEGMENTS" => skipped_segments = Some(v.parse().map_err(|_| err(number, MediaManifestErrorKind::BadDecimal { name: "SKIPPED-SEGMENTS", value: v.to_string() }))?),
+                        "RECENTLY-REMOVED-DATERANGES" => recently_removed_dateranges = v.split('\t').filter(|id| !id.is_empty() ).map(ToOwned::to_owned).collect(),
+                        _ => (),
+                    }
+                }
+                let skipped_segments = skipped_segments.ok_or_else(|| err(number, MediaManifestErrorKind::MissingSkippedSegmentsAttribute))?;
+                // the segments that follow are numbered as though the skipped ones were present
+                next_msn = next_msn.checked_add(skipped_segments)
+                    .ok_or_else(|| err(number, MediaManifestErrorKind::MediaSequenceOverflow))?;
+                skip = Some(Skip {
+                    skipped_segments,
+                    recently_removed_dateranges,
+                    line: number,
+                });
+            } else if let Some(value) = l.strip_prefix(Self::TAG_EXTINF) {
+                let value = value.split(',').next().unwrap();
+                duration = Some(non_negative_decimal(value).ok_or_else(|| err(number, MediaManifestErrorKind::BadSegmentDuration(value.to_string())) )?);
+            } else if l.starts_with(Self::TAG_PART) {
+                let mut duration = None;
+                let mut uri = None;
+                let mut independent = false;
+                let mut gap = false;
+                let mut byte_range = None;
+                for (k, v) in attrs(Self::TAG_PART)? {
+                    match k {
+                        "DURATION" => duration = Some(non_negative_decimal(v).ok_or_else(|| err(number, MediaManifestErrorKind::BadPartDuration(v.to_string())) )?),
+                        "URI" => uri = Some(v),
+                        "INDEPENDENT" => independent = v == "YES",
+                        "GAP" => gap = v == "YES",
+                        "BYTERANGE" => byte_range = Some(v.to_string()),
+                        _ => (),
+                    }
+                }
+                let part = Part {
+                    msn: next_msn,
+                    part_num: next_part_num,
+                    duration: duration.ok_or_else(|| err(number, MediaManifestErrorKind::MissingDurationAttribute))?,
+                    uri: uri.ok_or_else(|| err(number, MediaManifestErrorKind::MissingUriAttribute))?.to_string(),
+                    independent,
+                    gap,
+                    byte_range,
+                    line: number,
+                };
+                next_part_num = next_part_num.checked_add(1)
+                    .ok_or_else(|| err(number, MediaManifestErrorKind::TooManyParts))?;
+                parts.push(part);
+            } else if l.starts_with(Self::TAG_PART_INF) {
+                let mut part_target = None;
+                for (k, v) in attrs(Self::TAG_PART_INF)? {
+                    if k == "PART-TARGET" {
+                        part_target = Some(decimal(number, "PART-TARGET", v)?);
+                    }
+                }
+                part_inf = Some(PartInf {
+                    part_target: part_target.ok_or_else(|| err(number, MediaManifestErrorKind::MissingPartTargetAttribute))?,
+                });
+            } else if l.starts_with(Self::TAG_PRELOAD_HINT) {
+                let mut hint_type = None;
+                let mut uri = None;
+                let mut byte_range_start = None;
+                let mut byte_range_length = None;
+                for (k, v) in attrs(Self::TAG_PRELOAD_HINT)? {
+                    match k {
+                        "TYPE" => hint_type = Some(match v {
+                            "PART" => PreloadHintType::Part,
+                            "MAP" => PreloadHintType::Map,
+                            _ => return Err(err(number, MediaManifestErrorKind::BadPreloadHintType(v.to_string()))),
+                        }),
+                        "URI" => uri = Some(v),
+                        "BYTERANGE-START" => byte_range_start = Some(v.parse().map_err(|_| err(number, MediaManifestErrorKind::BadDecimal { name: "BYTERANGE-START", value: v.to_string() }))?),
+                        "BYTERANGE-LENGTH" => byte_range_length = Some(v.parse().map_err(|_| err(number, MediaManifestErrorKind::BadDecimal { name: "BYTERANGE-LENGTH", value: v.to_string() }))?),
+                        _ => (),
+                    }
+                }
+                preload_hints.push(PreloadHint {
+                    hint_type: hint_type.ok_or_else(|| err(number, MediaManifestErrorKind::MissingTypeAttribute))?,
+                    uri: uri.ok_or_else(|| err(number, MediaManifestErrorKind::MissingUriAttribute))?.to_string(),
+                    byte_range_start,
+                    byte_range_length,
+                    msn: next_msn,
+                    part_num: next_part_num,
+                    line: number,
+                });
+            } else if l.starts_with(Self::TAG_SERVER_CONTROL) {
+                let mut control = ServerControl::default();
+                for (k, v) in attrs(Self::TAG_SERVER_CONTROL)? {
+                    match k {
+                        "CAN-SKIP-UNTIL" => control.can_skip_until = Some(decimal(number, "CAN-SKIP-UNTIL", v)?),
+                        "CAN-SKIP-DATERANGES" => control.can_skip_dateranges = v == "YES",
+                        "HOLD-BACK" => control.hold_back = Some(decimal(number, "HOLD-BACK", v)?),
+                        "PART-HOLD-BACK" => control.part_hold_back = Some(decimal(number, "PART-HOLD-BACK", v)?),
+                        "CAN-BLOCK-RELOAD" => control.can_block_reload = v == "YES",
+                        _ => (),
+                    }
+                }
+                server_control = Some(control);
+            } else if l.starts_with(Self::TAG_RENDITION_REPORT) {
+                let mut uri = None;
+                let mut last_msn = None;
+                let mut last_part = None;
+                for (k, v) in attrs(Self::TAG_RENDITION_REPORT)? {
+                    match k {
+                        "URI" => uri = Some(v),
+                        "LAST-MSN" => last_msn = Some(v.parse().map_err(|_| err(number, MediaManifestErrorKind::BadDecimal { name: "LAST-MSN", value: v.to_string() }))?),
+                        "LAST-PART" => last_part = Some(v.parse().map_err(|_| err(number, MediaManifestErrorKind::BadDecimal { name: "LAST-PART", value: v.to_string() }))?),
+                        _ => (),
+                    }
+                }
+                rendition_reports.push(RenditionReport {
+                    uri: uri.ok_or_else(|| err(number, MediaManifestErrorKind::MissingUriAttribute))?.to_string(),
+                    last_msn,
+                    last_part,
+                    line: number,
+                });
+            } else if l.starts_with("#") || l.is_empty() {
+                // ignore
+            } else {
+                let seg = Seg {
+                    msn: next_msn,
+                    duration: duration.take(),
+                    byte_range: byte_range.take(),
+                    map: map.clone(),
+                    keys: keys.clone(),
+                    uri: l.to_string(),
+                    first_line: first_line.take().unwrap_or(number),
+                    line: number,
+                    tags: std::mem::take(&mut segment_tags),
+                };
+                next_msn = next_msn.checked_add(1)
+                    .ok_or_else(|| err(number, MediaManifestErrorKind::MediaSequenceOverflow))?;
+                next_part_num = 0;
+                segments.push(seg);
+            }
+        }
+        Ok(MediaManifest {
+            version,
+            media_sequence,
+            discontinuity_sequence,
+            segments,
+            parts,
+            preload_hints,
+            server_control,
+            part_inf,
+            skip,
+            rendition_reports,
+            dateranges,
+            tags,
+            rebuilt: false,
+        })
+    }
+
+    /// the line index of the first occurrence of the given playlist tag
+    pub fn tag_line(&self, name: &str) -> Option<usize> {
+        self.tags.iter().find(|(n, _)| n == name ).map(|(_, line)| *line )
+    }
+
+    pub fn segment(&self, msn: u64) -> Option<&Seg> {
+        self.segments.iter().find(|s| s.msn == msn)
+    }
+
+    pub fn part(&self, msn: u64, part_num: u16) -> Option<&Part> {
+        self.parts.iter().find(|p| p.msn == msn && p.part_num == part_num)
+    }
+
+    /// the parts of the given segment that are listed in this playlist
+    pub fn parts_of(&self, msn: u64) -> impl Iterator<Item = &Part> {
+        self.parts.iter().filter(move |p| p.msn == msn)
+    }
+
+    pub fn preload_hint(&self, hint_type: PreloadHintType) -> Option<&PreloadHint> {
+        self.preload_hints.iter().find(|h| h.hint_type == hint_type)
+    }
+
+    pub fn can_block_reload(&self) -> bool {
+        self.server_control.as_ref().map(|c| c.can_block_reload).unwrap_or(false)
+    }
+
+    /// The media sequence number of the last segment or, if parts are listed beyond the last
+    /// segment, of the last part; i.e. the value that `EXT-X-RENDITION-REPORT` would give as
+    /// `LAST-MSN` for this playlist
+    pub fn last_msn(&self) -> Option<u64> {
+        let last_seg = self.segments.last().map(|s| s.msn );
+        let last_part = self.parts.last().map(|p| p.msn );
+        last_seg.max(last_part)
+    }
+
+    /// The index of the last part, if the last part belongs to the segment given by `last_msn()`
+    pub fn last_part(&self) -> Option<u16> {
+        let last_msn = self.last_msn()?;
+        self.parts.last().filter(|p| p.msn == last_msn ).map(|p| p.part_num )
+    }
+}
+
+#[derive(Debug)]
+pub struct Seg {
+    pub msn: u64,
+    /// value from the `EXTINF` tag preceding the segment URI, in seconds
+    pub duration: Option<f32>,
+    pub byte_range: Option<ByteRange>,
+    /// the `EXT-X-MAP` in effect for this segment, being the last one preceding it
+    pub map: Option<Map>,
+    /// the `EXT-X-KEY` tags in effect for this segment, one for each `KEYFORMAT`
+    pub keys: Vec<Key>,
+    pub uri: String,
+    /// zero-based index of the line holding the first tag that applies to this segment
+    pub first_line: usize,
+    /// zero-based index of the line holding the segment URI
+    pub line: usize,
+    /// the name and line index of each tag applying to this segment
+    pub tags: Vec<(String, usize)>,
+}
+impl Seg {
+    /// the line index of the given tag (e.g. `EXTINF`) applying to this segment
+    pub fn tag_line(&self, name: &str) -> Option<usize> {
+        self.tags.iter().find(|(n, _)| n == name ).map(|(_, line)| *line )
+    }
+}
+
+#[derive(Debug)]
+pub struct Part {
+    pub msn: u64,
+    pub part_num: u16,
+    pub duration: f32,
+    pub uri: String,
+    pub independent: bool,
+    pub gap: bool,
+    pub byte_range: Option<String>,
+    /// zero-based index of the line holding the `EXT-X-PART` tag
+    pub line: usize,
+}
+
+/// The value of an `EXT-X-BYTERANGE` tag
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteRange {
+    pub length: u64,
+    /// if absent, the range starts at the byte following the range of the previous segment
+    pub offset: Option<u64>,
+}
+impl ByteRange {
+    /// Parses a `<n>[@<o>]` value, rejecting a zero length
+    fn parse(value: &str) -> Option<ByteRange> {
+        let mut parts = value.trim().splitn(2, '@');
+        let length = parts.next()?.parse::<u64>().ok().filter(|l| *l > 0 )?;
+        let offset = match parts.next() {
+            Some(o) => Some(o.parse().ok()?),
+            None => None,
+        };
+        Some(ByteRange { length, offset })
+    }
+}
+impl fmt::Display for ByteRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "{}@{}", self.length, offset),
+            None => write!(f, "{}", self.length),
+        }
+    }
+}
+
+/// The value of an `EXT-X-KEY` tag, giving how the segments that follow are encrypted
+#[derive(Debug, Clone)]
+pub struct Key {
+    /// `NONE`, `AES-128`, `SAMPLE-AES` or `SAMPLE-AES-CTR`
+    pub method: String,
+    pub uri: Option<String>,
+    pub iv: Option<u128>,
+    pub keyformat: Option<String>,
+    pub keyformatversions: Option<String>,
+    pub keyid: Option<u128>,
+    /// zero-based index of the line holding the tag
+    pub line: usize,
+}
+impl Key {
+    /// parses the attributes of an `EXT-X-KEY` or `EXT-X-SESSION-KEY` tag
+    pub(crate) fn parse(attrs: Vec<(&str, &str)>, line: usize) -> Result<Key, MediaManifestError> {
+        let mut method = None;
+        let mut uri = None;
+        let mut iv = None;
+        let mut keyformat = None;
+        let mut keyformatversions = None;
+        let mut keyid = None;
+        for (k, v) in attrs {
+            match k {
+                "METHOD" => method = Some(v.to_string()),
+                "URI" => uri = Some(v.to_string()),
+                "IV" => iv = Some(hex_128(v).ok_or_else(|| err(line, MediaManifestErrorKind::BadIv(v.to_string())))?),
+                "KEYFORMAT" => keyformat = Some(v.to_string()),
+                "KEYFORMATVERSIONS" => keyformatversions = Some(v.to_string()),
+                "KEYID" => keyid = Some(hex_128(v).ok_or_else(|| err(line, MediaManifestErrorKind::BadKeyId(v.to_string())))?),
+                _ => (),
+            }
+        }
+        let method = method.ok_or_else(|| err(line, MediaManifestErrorKind::MissingMethodAttribute))?;
+        if method != "NONE" && uri.is_none() {
+            return Err(err(line, MediaManifestErrorKind::MissingUriAttribute));
+        }
+        Ok(Key { method, uri, iv, keyformat, keyformatversions, keyid, line })
+    }
+
+    /// the `KEYFORMAT`, or its default value `identity`
+    pub fn keyformat(&self) -> &str {
+        self.keyformat.as_deref().unwrap_or("identity")
+    }
+
+    /// the `KEYFORMATVERSIONS`, or its default value `1`
+    pub fn keyformatversions(&self) -> &str {
+        self.keyformatversions.as_deref().unwrap_or("1")
+    }
+
+    /// true if both tags give the same key, regardless of the `IV` it's used with
+    pub fn same_key(&self, other: &Key) -> bool {
+        self.method == other.method
+            && self.uri == other.uri
+            && self.keyformat() == other.keyformat()
+            && self.keyformatversions() == other.keyformatversions()
+            && self.keyid == other.keyid
+    }
+}
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "METHOD={}", self.method)?;
+        if let Some(ref uri) = self.uri {
+            write!(f, ",URI=\"{}\"", uri)?;
+        }
+        if let Some(iv) = self.iv {
+            write!(f, ",IV=0x{:032X}", iv)?;
+        }
+        if let Some(ref keyformat) = self.keyformat {
+            write!(f, ",KEYFORMAT=\"{}\"", keyformat)?;
+        }
+        if let Some(ref keyformatversions) = self.keyformatversions {
+            write!(f, ",KEYFORMATVERSIONS=\"{}\"", keyformatversions)?;
+        }
+        if let Some(keyid) = self.keyid {
+            write!(f, ",KEYID=0x{:032X}", keyid)?;
+        }
+        Ok(())
+    }
+}
+
+/// An `IV` or `KEYID` attribute value, being a 128 bit hexadecimal-sequence
+fn hex_128(value: &str) -> Option<u128> {
+    if !(value.starts_with("0x") || value.starts_with("0X")) || value.len() < 3 || value.len() > 2 + 32 {
+        return None;
+    }
+    u128::from_str_radix(&value[2..], 16).ok()
+}
+
+/// An `EXT-X-DATERANGE` tag
+#[derive(Debug, Clone)]
+pub struct DateRange {
+    pub id: String,
+    pub class: Option<String>,
+    pub start_date: Option<chrono::DateTime<chrono::FixedOffset>>,
+    pub end_date: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// the `DURATION` and `PLANNED-DURATION`, in seconds
+    pub duration: Option<f32>,
+    pub planned_duration: Option<f32>,
+    pub scte35_cmd: Option<String>,
+    pub scte35_out: Option<String>,
+    pub scte35_in: Option<String>,
+    pub end_on_next: bool,
+    /// the text of the tag, by which the same tag is recognised in later copies of the playlist
+    pub tag: String,
+    /// the media sequence number of the segment following the tag
+    pub next_msn: u64,
+    /// zero-based index of the line holding the tag
+    pub line: usize,
+}
+impl DateRange {
+    fn parse(attrs: Vec<(&str, &str)>, tag: &str, next_msn: u64, line: usize) -> Result<DateRange, MediaManifestError> {
+        let date = |name: &'static str, value: &str| {
+            chrono::DateTime::parse_from_rfc3339(value)
+                .map_err(|_| err(line, MediaManifestErrorKind::BadDate { name, value: value.to_string() }))
+        };
+        let mut id = None;
+        let mut range = DateRange {
+            id: String::new(),
+            class: None,
+            start_date: None,
+            end_date: None,
+            duration: None,
+            planned_duration: None,
+            scte35_cmd: None,
+            scte35_out: None,
+            scte35_in: None,
+            end_on_next: false,
+            tag: tag.to_string(),
+            next_msn,
+            line,
+        };
+        for (k, v) in attrs {
+            match k {
+                "ID" => id = Some(v.to_string()),
+                "CLASS" => range.class = Some(v.to_string()),
+                "START-DATE" => range.start_date = Some(date("START-DATE", v)?),
+                "END-DATE" => range.end_date = Some(date("END-DATE", v)?),
+                "DURATION" => range.duration = Some(decimal(line, "DURATION", v)?),
+                "PLANNED-DURATION" => range.planned_duration = Some(decimal(line, "PLANNED-DURATION", v)?),
+                "SCTE35-CMD" => range.scte35_cmd = Some(v.to_string()),
+                "SCTE35-OUT" => range.scte35_out = Some(v.to_string()),
+                "SCTE35-IN" => range.scte35_in = Some(v.to_string()),
+                "END-ON-NEXT" => range.end_on_next = v == "YES",
+                _ => (),
+            }
+        }
+        range.id = id.ok_or_else(|| err(line, MediaManifestErrorKind::MissingIdAttribute))?;
+        Ok(range)
+    }
+
+    /// the `SCTE35-CMD`, `SCTE35-OUT` and `SCTE35-IN` attributes present, with their names
+    pub fn scte35_attributes(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        vec![("SCTE35-CMD", &self.scte35_cmd), ("SCTE35-OUT", &self.scte35_out), ("SCTE35-IN", &self.scte35_in)]
+            .into_iter()
+            .filter_map(|(name, value)| value.as_ref().map(|v| (name, v.as_str()) ) )
+    }
+}
+
+/// The value of an `EXT-X-MAP` tag, giving the Media Initialization Section for the segments that
+/// follow
+#[derive(Debug, Clone)]
+pub struct Map {
+    pub uri: String,
+    /// if present, the offset is required, or else taken to be zero
+    pub byte_range: Option<ByteRange>,
+    /// zero-based index of the line holding the tag
+    pub line: usize,
+}
+impl Map {
+    /// true if both tags identify the same Media Initialization Section
+    pub fn same_section(&self, other: &Map) -> bool {
+        self.uri == other.uri && self.byte_range == other.byte_range
+    }
+}
+impl fmt::Display for Map {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "URI=\"{}\"", self.uri)?;
+        if let Some(range) = self.byte_range {
+            write!(f, ",BYTERANGE=\"{}\"", range)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreloadHintType {
+    Part,
+    Map,
+}
+
+#[derive(Debug)]
+pub struct PreloadHint {
+    pub hint_type: PreloadHintType,
+    pub uri: String,
+    pub byte_range_start: Option<u64>,
+    pub byte_range_length: Option<u64>,
+    /// the media sequence number of the segment to which a hinted part will belong
+    pub msn: u64,
+    /// the position within its parent segment that a hinted part will take
+    pub part_num: u16,
+    /// zero-based index of the line holding the `EXT-X-PRELOAD-HINT` tag
+    pub line: usize,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ServerControl {
+    pub can_skip_until: Option<f32>,
+    pub can_skip_dateranges: bool,
+    pub hold_back: Option<f32>,
+    pub part_hold_back: Option<f32>,
+    pub can_block_reload: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PartInf {
+    pub part_target: f32,
+}
+
+/// An `EXT-X-RENDITION-REPORT` tag, giving the position that another rendition's playlist has
+/// reached
+#[derive(Debug)]
+pub struct RenditionReport {
+    /// relative to the URL of the playlist holding the report
+    pub uri: String,
+    pub last_msn: Option<u64>,
+    pub last_part: Option<u16>,
+    /// zero-based index of the line holding the `EXT-X-RENDITION-REPORT` tag
+    pub line: usize,
+}
+
+/// The `EXT-X-SKIP` tag that takes the place of the older segments in a Playlist Delta Update
+#[derive(Debug)]
+pub struct Skip {
+    pub skipped_segments: u64,
+    pub recently_removed_dateranges: Vec<String>,
+    /// zero-based index of the line holding the `EXT-X-SKIP` tag
+    pub line: usize,
+}
+
+/// Reconstructs the text of the full playlist from a Playlist Delta Update, by putting the lines
+/// of the skipped segments from an earlier copy of the full playlist in place of the `EXT-X-SKIP`
+/// tag.  If `skipped_dateranges` is true (the update was requested with `_HLS_skip=v2`), tags of
+/// the earlier playlist the server left out are restored too.  Tags whose IDs are listed by
+/// `RECENTLY-REMOVED-DATERANGES` are dropped.  Returns `None` if the earlier playlist doesn't
+/// list all of the skipped segments.
+pub fn rebuild_delta_update<'a>(last_body: &'a str, last: &'a MediaManifest, delta_body: &'a str, delta: &MediaManifest, skipped_dateranges: bool) -> Option<String> {
+    let skip = delta.skip.as_ref()?;
+    let removed = |range: &DateRange| skip.recently_removed_dateranges.contains(&range.id);
+    let delta_lines: Vec<&str> = delta_body.lines().collect();
+    let mut result: Vec<&str> = delta_lines[..skip.line].to_vec();
+    if skip.skipped_segments > 0 {
+        let first = last.segment(delta.media_sequence)?;
+        let end = last.segment(delta.media_sequence.checked_add(skip.skipped_segments - 1)?)?;
+        let removed_lines: Vec<usize> = last.dateranges.iter().filter(|r| removed(r) ).map(|r| r.line ).collect();
+        result.extend(
+            last_body.lines()
+                .enumerate()
+                .skip(first.first_line)
+                .take(end.line + 1 - first.first_line)
+                .filter(|(number, _)| !removed_lines.contains(number) )
+                .map(|(_, l)| l )
+        );
+    }
+    result.extend(&delta_lines[skip.line + 1..]);
+    if skipped_dateranges {
+        let rebuilt = MediaManifest::parse(&result.join("\n")).ok()?;
+        let missing = last.dateranges.iter()
+            .filter(|r| !removed(r) && !rebuilt.dateranges.iter().any(|d| d.tag == r.tag ) );
+        // each goes before the segment that followed it, or after the last segment
+        let mut inserts: Vec<(usize, &str)> = missing
+            .map(|r| {
+                let line = rebuilt.segments.iter().find(|s| s.msn >= r.next_msn ).map(|s| s.first_line )
+                    .or_else(|| rebuilt.segments.last().map(|s| s.line + 1 ) )
+                    .unwrap_or(result.len());
+                (line, r.tag.as_str())
+            })
+            .collect();
+        inserts.sort_by_key(|(line, _)| *line );
+        // inserting from the end keeps the positions of earlier inserts valid
+        for (line, tag) in inserts.into_iter().rev() {
+            result.insert(line, tag);
+        }
+    }
+    let mut body = result.join("\n");
+    body.push('\n');
+    Some(body)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn quoted_attributes() {
+        let attrs = attributes(r#"DURATION=0.5,URI="a,b.mp4", INDEPENDENT=YES"#).unwrap();
+        assert_eq!(attrs, vec![("DURATION", "0.5"), ("URI", "a,b.mp4"), ("INDEPENDENT", "YES")]);
+        assert!(attributes(r#"URI="unterminated"#).is_err());
+    }
+
+    #[test]
+    fn parts() {
+        let pl = "#EXTM3U
+#EXT-X-TARGETDURATION:4
+#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK=1.0
+#EXT-X-PART-INF:PART-TARGET=0.5
+#EXT-X-MEDIA-SEQUENCE:10
+#EXT-X-PART:DURATION=0.5,URI=\"10.0.mp4\",INDEPENDENT=YES
+#EXT-X-PART:DURATION=0.5,URI=\"10.1.mp4\"
+#EXTINF:1,
+10.mp4
+
+#EXT-X-PART:DURATION=0.5,URI=\"11.0.mp4\",INDEPENDENT=YES
+#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"11.1.mp4\"
+#EXT-X-RENDITION-REPORT:URI=\"../audio/a.m3u8\",LAST-MSN=11,LAST-PART=0";
+        let manifest = MediaManifest::parse(pl).unwrap();
+        assert_eq!(manifest.segments.len(), 1);
+        assert_eq!(manifest.segments[0].msn, 10);
+        assert_eq!(manifest.segments[0].duration, Some(1.0));
+        assert_eq!(manifest.parts_of(10).count(), 2);
+        let part = manifest.part(11, 0).unwrap();
+        assert!(part.independent);
+        assert_eq!(part.uri, "11.0.mp4");
+        let hint = manifest.preload_hint(PreloadHintType::Part).unwrap();
+        assert_eq!((hint.msn, hint.part_num), (11, 1));
+        assert!(manifest.can_block_reload());
+        assert_eq!(manifest.server_control.as_ref().unwrap().part_hold_back, Some(1.0));
+        assert_eq!(manifest.part_inf, Some(PartInf { part_target: 0.5 }));
+        assert_eq!((manifest.last_msn(), manifest.last_part()), (Some(11), Some(0)));
+        assert_eq!(manifest.tag_line("EXT-X-PART-INF"), Some(3));
+        assert_eq!(manifest.segments[0].tag_line("EXTINF"), Some(7));
+        assert_eq!(manifest.segments[0].tag_line("EXT-X-PART"), Some(5));
+        let report = &manifest.rendition_reports[0];
+        assert_eq!((report.uri.as_str(), report.last_msn, report.last_part), ("../audio/a.m3u8", Some(11), Some(0)));
+        let bad_target = pl.replace("PART-TARGET=0.5", "PART-TARGET=-1");
+        assert!(matches!(MediaManifest::parse(&bad_target), Err(MediaManifestError { line: 3, kind: MediaManifestErrorKind::BadDecimal { name: "PART-TARGET", .. } })));
+        let (start, end) = line_span(&bad_target, 3).unwrap();
+        assert_eq!(&bad_target[start..end], "#EXT-X-PART-INF:PART-TARGET=-1");
+        let bad_extinf = pl.replace("#EXTINF:1,", "#EXTINF:NaN,");
+        assert!(matches!(MediaManifest::parse(&bad_extinf), Err(MediaManifestError { line: 7, kind: MediaManifestErrorKind::BadSegmentDuration(_) })));
+        let bad_part = pl.replace("DURATION=0.5,URI=\"10.1.mp4\"", "DURATION=inf,URI=\"10.1.mp4\"");
+        assert!(matches!(MediaManifest::parse(&bad_part), Err(MediaManifestError { line: 6, kind: MediaManifestErrorKind::BadPartDuration(_) })));
+    }
+
+    #[test]
+    fn keys() {
+        let pl = "#EXTM3U
+#EXT-X-TARGETDURATION:2
+#EXT-X-KEY:METHOD=AES-128,URI=\"k1\",IV=0x0000000000000000000000000000000A
+#EXT-X-KEY:METHOD=SAMPLE-AES,URI=\"skd://k\",KEYFORMAT=\"com.apple.streamingkeydelivery\",KEYFORMATVERSIONS=\"1\",KEYID=0x000000000000000000000000000000FF
+#EXTINF:2,
+10.ts
+#EXT-X-KEY:METHOD=AES-128,URI=\"k2\"
+#EXTINF:2,
+11.ts
+#EXT-X-KEY:METHOD=NONE
+#EXTINF:2,
+12.ts
+";
+        let manifest = MediaManifest::parse(pl).unwrap();
+        let keys: Vec<_> = manifest.segments.iter().map(|s| s.keys.iter().map(|k| k.to_string() ).collect::<Vec<_>>() ).collect();
+        assert_eq!(keys, vec![
+            vec!["METHOD=AES-128,URI=\"k1\",IV=0x0000000000000000000000000000000A".to_string(), "METHOD=SAMPLE-AES,URI=\"skd://k\",KEYFORMAT=\"com.apple.streamingkeydelivery\",KEYFORMATVERSIONS=\"1\",KEYID=0x000000000000000000000000000000FF".to_string()],
+            vec!["METHOD=SAMPLE-AES,URI=\"skd://k\",KEYFORMAT=\"com.apple.streamingkeydelivery\",KEYFORMATVERSIONS=\"1\",KEYID=0x000000000000000000000000000000FF".to_string(), "METHOD=AES-128,URI=\"k2\"".to_string()],
+            vec![],
+        ]);
+        assert_eq!(manifest.segments[0].keys[0].iv, Some(10));
+        assert_eq!(manifest.segments[0].keys[1].keyid, Some(0xFF));
+        let bad_iv = pl.replace("IV=0x0000000000000000000000000000000A", "IV=10");
+        assert!(matches!(MediaManifest::parse(&bad_iv), Err(MediaManifestError { line: 2, kind: MediaManifestErrorKind::BadIv(_) })));
+    }
+
+    #[test]
+    fn dateranges() {
+        let pl = "#EXTM3U
+#EXT-X-TARGETDURATION:2
+#EXT-X-MEDIA-SEQUENCE:10
+#EXTINF:2,
+10.ts
+#EXT-X-DATERANGE:ID=\"ad1\",CLASS=\"com.example\",START-DATE=\"2021-05-01T08:00:02Z\",PLANNED-DURATION=30.5,SCTE35-OUT=0xFC30
+#EXTINF:2,
+11.ts
+";
+        let manifest = MediaManifest::parse(pl).unwrap();
+        assert_eq!(manifest.dateranges.len(), 1);
+        let range = &manifest.dateranges[0];
+        assert_eq!((range.id.as_str(), range.class.as_deref(), range.next_msn, range.line), ("ad1", Some("com.example"), 11, 5));
+        assert_eq!(range.start_date.map(|d| d.timestamp() ), Some(1619856002));
+        assert_eq!(range.planned_duration, Some(30.5));
+        assert_eq!(range.scte35_attributes().collect::<Vec<_>>(), vec![("SCTE35-OUT", "0xFC30")]);
+        let bad_date = pl.replace("2021-05-01T08:00:02Z", "yesterday");
+        assert!(matches!(MediaManifest::parse(&bad_date), Err(MediaManifestError { line: 5, kind: MediaManifestErrorKind::BadDate { name: "START-DATE", .. } })));
+    }
+
+    #[test]
+    fn numbering_overflow() {
+        let pl = "#EXTM3U\n#EXT-X-TARGETDURATION:2\n#EXT-X-MEDIA-SEQUENCE:18446744073709551614\n#EXTINF:2,\na.ts\n#EXTINF:2,\nb.ts\n";
+        assert!(matches!(MediaManifest::parse(pl), Err(MediaManifestError { line: 6, kind: MediaManifestErrorKind::MediaSequenceOverflow })));
+        let pl = "#EXTM3U\n#EXT-X-TARGETDURATION:2\n#EXT-X-MEDIA-SEQUENCE:10\n#EXT-X-SKIP:SKIPPED-SEGMENTS=18446744073709551615\n";
+        assert!(matches!(MediaManifest::parse(pl), Err(MediaManifestError { line: 3, kind: MediaManifestErrorKind::MediaSequenceOverflow })));
+        let pl = format!("#EXTM3U\n#EXT-X-TARGETDURATION:2\n{}", "#EXT-X-PART:DURATION=0.5,URI=\"p.mp4\"\n".repeat(usize::from(u16::MAX) + 1));
+        assert!(matches!(MediaManifest::parse(&pl), Err(MediaManifestError { line: 65537, kind: MediaManifestErrorKind::TooManyParts })));
+    }
+
+    #[test]
+    fn rebuild() {
+        let full = "#EXTM3U
+#EXT-X-TARGETDURATION:2
+#EXT-X-SERVER-CONTROL:CAN-SKIP-UNTIL=4
+#EXT-X-MEDIA-SEQUENCE:10
+#EXT-X-PROGRAM-DATE-TIME:2021-05-01T08:00:00Z
+#EXTINF:2,
+10.ts
+#EXTINF:2,
+11.ts
+#EXTINF:2,
+12.ts
+#EXTINF:2,
+13.ts
+";
+        let delta = "#EXTM3U
+#EXT-X-TARGETDURATION:2
+#EXT-X-SERVER-CONTROL:CAN-SKIP-UNTIL=4
+#EXT-X-MEDIA-SEQUENCE:11
+#EXT-X-SKIP:SKIPPED-SEGMENTS=2
+#EXTINF:2,
+13.ts
+#EXTINF:2,
+14.ts
+";
+        let delta_manifest = MediaManifest::parse(delta).unwrap();
+        assert_eq!(delta_manifest.segments[0].msn, 13);
+        let rebuilt = rebuild_delta_update(full, &MediaManifest::parse(full).unwrap(), delta, &delta_manifest, false).unwrap();
+        let manifest = MediaManifest::parse(&rebuilt).unwrap();
+        assert!(manifest.skip.is_none());
+        let uris: Vec<_> = manifest.segments.iter().map(|s| (s.msn, s.uri.as_str()) ).collect();
+        assert_eq!(uris, vec![(11, "11.ts"), (12, "12.ts"), (13, "13.ts"), (14, "14.ts")]);
+        // segment 10 isn't in the delta, but segment 11 must be,
+        let delta = delta.replace("MEDIA-SEQUENCE:11", "MEDIA-SEQUENCE:9");
+        let delta_manifest = MediaManifest::parse(&delta).unwrap();
+        assert!(rebuild_delta_update(full, &MediaManifest::parse(full).unwrap(), &delta, &delta_manifest, false).is_none());
+    }
+
+    #[test]
+    fn rebuild_skipped_dateranges() {
+        let last = "#EXTM3U
+#EXT-X-TARGETDURATION:2
+#EXT-X-SERVER-CONTROL:CAN-SKIP-UNTIL=4,CAN-SKIP-DATERANGES=YES
+#EXT-X-MEDIA-SEQUENCE:10
+#EXTINF:2,
+10.ts
+#EXT-X-DATERANGE:ID=\"ad1\",START-DATE=\"2021-05-01T08:00:02Z\"
+#EXTINF:2,
+11.ts
+#EXT-X-DATERANGE:ID=\"ad2\",START-DATE=\"2021-05-01T08:00:04Z\"
+#EXTINF:2,
+12.ts
+#EXT-X-DATERANGE:ID=\"ad3\",START-DATE=\"2021-05-01T08:00:06Z\"
+#EXTINF:2,
+13.ts
+#EXT-X-DATERANGE:ID=\"ad4\",START-DATE=\"2021-05-01T08:00:08Z\"
+";
+        // the full playlist the delta stands for, from which "ad2" has been removed
+        let full = "#EXTM3U
+#EXT-X-TARGETDURATION:2
+#EXT-X-SERVER-CONTROL:CAN-SKIP-UNTIL=4,CAN-SKIP-DATERANGES=YES
+#EXT-X-MEDIA-SEQUENCE:11
+#EXT-X-DATERANGE:ID=\"ad1\",START-DATE=\"2021-05-01T08:00:02Z\"
+#EXTINF:2,
+11.ts
+#EXTINF:2,
+12.ts
+#EXT-X-DATERANGE:ID=\"ad3\",START-DATE=\"2021-05-01T08:00:06Z\"
+#EXTINF:2,
+13.ts
+#EXT-X-DATERANGE:ID=\"ad4\",START-DATE=\"2021-05-01T08:00:08Z\"
+#EXT-X-DATERANGE:ID=\"ad5\",START-DATE=\"2021-05-01T08:00:08Z\"
+#EXTINF:2,
+14.ts
+";
+        // "ad3" and "ad4" were sent before, so are skipped although their segments aren't
+        let delta = "#EXTM3U
+#EXT-X-TARGETDURATION:2
+#EXT-X-SERVER-CONTROL:CAN-SKIP-UNTIL=4,CAN-SKIP-DATERANGES=YES
+#EXT-X-MEDIA-SEQUENCE:11
+#EXT-X-SKIP:SKIPPED-SEGMENTS=2,RECENTLY-REMOVED-DATERANGES=\"ad2\"
+#EXTINF:2,
+13.ts
+#EXT-X-DATERANGE:ID=\"ad5\",START-DATE=\"2021-05-01T08:00:08Z\"
+#EXTINF:2,
+14.ts
+";
+        let last_manifest = MediaManifest::parse(last).unwrap();
+        let delta_manifest = MediaManifest::parse(delta).unwrap();
+        assert_eq!(delta_manifest.skip.as_ref().unwrap().recently_removed_dateranges, vec!["ad2"]);
+        let rebuilt = rebuild_delta_update(last, &last_manifest, delta, &delta_manifest, true).unwrap();
+        assert_eq!(rebuilt, full);
+        let ids = |body: &str| MediaManifest::parse(body).unwrap().dateranges.into_iter().map(|r| (r.id, r.next_msn) ).collect::<Vec<_>>();
+        assert_eq!(ids(&rebuilt), ids(full));
+        // a delta requested with _HLS_skip=YES doesn't leave out tags, so none are restored
+        let rebuilt = rebuild_delta_update(last, &last_manifest, delta, &delta_manifest, false).unwrap();
+        assert_eq!(ids(&rebuilt), vec![("ad1".to_string(), 11), ("ad5".to_string(), 14)]);
+    }
+}
@@ -1,5 +1,4 @@
 use crate::http_snoop;
-use hls_m3u8::tags::VariantStream;
 use crate::event_log::EventSink;
 use futures::prelude::*;
 use serde_derive::Serialize;
@@ -8,19 +7,39 @@ use std::time;
 use reqwest::header;
 use hyper::StatusCode;
 use crate::metric::Metric;
+use crate::scte35;
 use crate::http_snoop::{Snoop, HttpRef, Error};
 use std::convert::TryFrom;
 use hls_m3u8::parser::ParseError;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::cell::RefCell;
 
-// TODO: support VOD + Event manifests as well as Live
-
+mod alignment;
 pub mod check;
-mod timeline;
+mod drm;
+mod main_check;
+pub mod main_manifest;
+pub mod media_manifest;
+mod rendition;
+mod segment;
+mod segment_check;
+pub mod timeline;
+mod vod;
 
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct ManifestRef {
     req_id: HttpRef,
-    line: Option<usize>,  // ambition; not reality
+    /// one-based number of the line within the response body that the event relates to, if any
+    line: Option<usize>,
+}
+impl ManifestRef {
+    pub fn req_id(&self) -> &HttpRef {
+        &self.req_id
+    }
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
 }
 
 /// two media manifests between which something of note changed
@@ -29,6 +48,14 @@ pub struct Delta {
     before: ManifestRef,
     after: ManifestRef,
 }
+impl Delta {
+    pub fn before(&self) -> &ManifestRef {
+        &self.before
+    }
+    pub fn after(&self) -> &ManifestRef {
+        &self.after
+    }
+}
 
 
 #[derive(Serialize)]
@@ -92,6 +119,36 @@ pub enum HlsEvent {
         last_byterange: Option<String>,
         this_byterange: Option<String>,
     },
+    /// The `EXT-X-MAP` applying to a segment changed since the segment was listed in an earlier
+    /// copy of the playlist
+    ManifestHistoryChangedMap {
+        delta: Delta,
+        msn: usize,
+        last_map: Option<String>,
+        this_map: Option<String>,
+    },
+    /// The keys used to encrypt a segment changed since a previous copy of the playlist
+    ManifestHistoryChangedKey {
+        delta: Delta,
+        msn: usize,
+        last_keys: Vec<String>,
+        this_keys: Vec<String>,
+    },
+    /// A segment is encrypted with different keys to the segment before it
+    KeyRotated {
+        at: ManifestRef,
+        msn: u64,
+        last_keys: Vec<String>,
+        this_keys: Vec<String>,
+    },
+    /// A segment uses a different Media Initialization Section to the segment before it, but is not
+    /// preceded by `EXT-X-DISCONTINUITY`
+    MapChangedWithoutDiscontinuity {
+        at: ManifestRef,
+        msn: u64,
+        last_map: Option<String>,
+        this_map: Option<String>,
+    },
     /// One or more segments that used to be and the end of the playlist have disappeared
     LiveSegmentsRemoved {
         delta: Delta,
@@ -99,6 +156,27 @@ pub enum HlsEvent {
         this_msn: usize,
         removed_count: usize,
     },
+    /// The `EXT-X-MEDIA-SEQUENCE` of an `EXT-X-PLAYLIST-TYPE:EVENT` playlist changed
+    EventPlaylistMediaSequenceChanged {
+        delta: Delta,
+        last_media_sequence: usize,
+        this_media_sequence: usize,
+    },
+    /// Segments have disappeared from the start of an `EXT-X-PLAYLIST-TYPE:EVENT` playlist, which
+    /// may only have segments appended
+    EventPlaylistSegmentsRemoved {
+        delta: Delta,
+        removed_count: usize,
+    },
+    /// An `EXT-X-PLAYLIST-TYPE:EVENT` playlist has grown large enough that it may soon be too big
+    /// for some players to handle
+    EventPlaylistTooLarge {
+        req_id: HttpRef,
+        body_size: usize,
+        segment_count: usize,
+        /// the average growth in size of the playlist for each segment added
+        bytes_per_segment: usize,
+    },
     /// We've performed multiple manifest reloads without receiving an update.
     ManifestStale {
         delta: Delta,
@@ -177,6 +255,16 @@ pub enum HlsEvent {
         start: usize,
         end: usize,
     },
+    /// The playlist is malformed.  `kind` identifies the sort of problem reported by the parser,
+    /// and `start` / `end` give its byte offsets within the response body, where the parser reports
+    /// them.
+    PlaylistSyntaxError {
+        req_id: HttpRef,
+        kind: &'static str,
+        message: String,
+        start: Option<usize>,
+        end: Option<usize>,
+    },
     LastModifiedChangedButBodiesIdentical {
         delta: Delta,
         this_last_modified: String,
@@ -197,7 +285,716 @@ pub enum HlsEvent {
         attr_name: String,
         prev_value: String,
         this_value: String,
-    }
+    },
+    /// The `END-DATE` of an `EXT-X-DATERANGE` is before its `START-DATE`
+    DaterangeEndBeforeStart {
+        at: ManifestRef,
+        daterange_id: String,
+        start_date: String,
+        end_date: String,
+    },
+    /// The `DURATION` of an `EXT-X-DATERANGE` differs from the time between its `START-DATE` and
+    /// `END-DATE`
+    DaterangeDurationMismatch {
+        at: ManifestRef,
+        daterange_id: String,
+        duration_millis: u64,
+        end_minus_start_millis: u64,
+    },
+    /// An `EXT-X-DATERANGE` has `END-ON-NEXT=YES` but no `CLASS`
+    DaterangeEndOnNextWithoutClass {
+        at: ManifestRef,
+        daterange_id: String,
+    },
+    /// An `EXT-X-DATERANGE` has `END-ON-NEXT=YES` as well as `DURATION` or `END-DATE`
+    DaterangeEndOnNextWithEnd {
+        at: ManifestRef,
+        daterange_id: String,
+        /// true if `DURATION` is present
+        duration: bool,
+        /// true if `END-DATE` is present
+        end_date: bool,
+    },
+    /// Two `EXT-X-DATERANGE`s with the same `CLASS` overlap in time
+    DaterangeOverlap {
+        at: ManifestRef,
+        daterange_id: String,
+        other_daterange_id: String,
+        class: String,
+    },
+    /// The playlist has `EXT-X-DATERANGE` tags, but no `EXT-X-PROGRAM-DATE-TIME`
+    DaterangeWithoutProgramDateTime {
+        at: ManifestRef,
+    },
+    /// An `SCTE35-CMD`, `SCTE35-OUT` or `SCTE35-IN` attribute doesn't hold a valid
+    /// `splice_info_section`
+    Scte35Invalid {
+        at: ManifestRef,
+        daterange_id: String,
+        attr_name: &'static str,
+        message: String,
+    },
+    /// The `CRC_32` of an SCTE-35 `splice_info_section` is incorrect
+    Scte35CrcMismatch {
+        at: ManifestRef,
+        daterange_id: String,
+        attr_name: &'static str,
+        splice_info: scte35::SpliceInfoSection,
+    },
+    /// The `out_of_network_indicator` of an SCTE-35 `splice_insert` is not set in `SCTE35-OUT`, or is
+    /// set in `SCTE35-IN`
+    Scte35OutOfNetworkMismatch {
+        at: ManifestRef,
+        daterange_id: String,
+        attr_name: &'static str,
+        splice_info: scte35::SpliceInfoSection,
+    },
+    /// The `break_duration` or `segmentation_duration` of SCTE-35 data differs from the `DURATION`
+    /// (or if absent, `PLANNED-DURATION`) of the `EXT-X-DATERANGE` carrying it
+    Scte35DurationMismatch {
+        at: ManifestRef,
+        daterange_id: String,
+        attr_name: &'static str,
+        /// `DURATION` or `PLANNED-DURATION`
+        duration_attr: &'static str,
+        duration_millis: u64,
+        scte35_duration_millis: u64,
+        splice_info: scte35::SpliceInfoSection,
+    },
+    /// An SCTE-35 event ID was already used by an `EXT-X-DATERANGE` with a different ID, i.e. for a
+    /// different break
+    Scte35EventIdReused {
+        at: ManifestRef,
+        daterange_id: String,
+        other_daterange_id: String,
+        event_id: u32,
+        splice_info: scte35::SpliceInfoSection,
+    },
+    /// An `EXT-X-DATERANGE` has `SCTE35-IN`, but no tag with the same ID was seen with `SCTE35-OUT`
+    Scte35InWithoutOut {
+        at: ManifestRef,
+        daterange_id: String,
+        splice_info: scte35::SpliceInfoSection,
+    },
+    /// The SCTE-35 event ID of `SCTE35-IN` differs from that of the `SCTE35-OUT` with the same
+    /// `EXT-X-DATERANGE` ID
+    Scte35InEventIdMismatch {
+        at: ManifestRef,
+        daterange_id: String,
+        out_event_id: u32,
+        in_event_id: u32,
+        splice_info: scte35::SpliceInfoSection,
+    },
+    /// The `EXT-X-PART` durations of a segment don't add up to the duration given by its `EXTINF`
+    PartDurationsMismatchExtinf {
+        req_id: HttpRef,
+        msn: u64,
+        extinf_millis: u64,
+        parts_total_millis: u64,
+    },
+    /// A partial segment is longer than the `PART-TARGET` from `EXT-X-PART-INF`
+    PartTargetDurationExceeded {
+        req_id: HttpRef,
+        msn: u64,
+        part: u16,
+        duration_millis: u64,
+        part_target_millis: u64,
+    },
+    /// The playlist contains `EXT-X-PART` tags, but no `EXT-X-PART-INF`
+    MissingPartInf {
+        req_id: HttpRef,
+    },
+    /// The playlist contains `EXT-X-PART-INF`, but `EXT-X-SERVER-CONTROL` does not specify
+    /// `PART-HOLD-BACK`
+    MissingPartHoldBack {
+        req_id: HttpRef,
+    },
+    /// `PART-HOLD-BACK` is required to be at least twice `PART-TARGET`
+    PartHoldBackTooShort {
+        req_id: HttpRef,
+        part_hold_back_millis: u64,
+        part_target_millis: u64,
+    },
+    PartTargetChanged {
+        delta: Delta,
+        last_part_target_millis: u64,
+        this_part_target_millis: u64,
+    },
+    ManifestHistoryChangedPartUri {
+        delta: Delta,
+        msn: u64,
+        part: u16,
+        last_uri: String,
+        this_uri: String,
+    },
+    ManifestHistoryChangedPartDuration {
+        delta: Delta,
+        msn: u64,
+        part: u16,
+        last_duration_millis: u64,
+        this_duration_millis: u64,
+    },
+    /// The part announced by the `EXT-X-PRELOAD-HINT` of the earlier playlist was listed in the
+    /// later playlist with a different URI
+    PreloadHintNotHonoured {
+        delta: Delta,
+        msn: u64,
+        part: u16,
+        hint_uri: String,
+        this_uri: String,
+    },
+    /// A blocking playlist reload request was answered before the playlist had reached the
+    /// requested segment / part
+    BlockingReloadReturnedEarly {
+        req_id: HttpRef,
+        requested_msn: u64,
+        requested_part: Option<u16>,
+        response_time_millis: u64,
+    },
+    /// The server held a blocking playlist reload request for longer than three times the target
+    /// duration
+    BlockingReloadTooSlow {
+        req_id: HttpRef,
+        requested_msn: u64,
+        requested_part: Option<u16>,
+        response_time_millis: u64,
+        timeout_millis: u64,
+    },
+    /// The response to a blocking playlist reload request went past the requested position in the
+    /// playlist, but does not list the requested segment / part itself
+    BlockingReloadMissingRequested {
+        req_id: HttpRef,
+        requested_msn: u64,
+        requested_part: Option<u16>,
+    },
+    /// A Playlist Delta Update (`EXT-X-SKIP`) was received without having been requested
+    UnrequestedDeltaUpdate {
+        req_id: HttpRef,
+    },
+    /// A Playlist Delta Update was received, but the earlier playlist did not advertise
+    /// `CAN-SKIP-UNTIL`
+    DeltaUpdateWithoutCanSkipUntil {
+        req_id: HttpRef,
+    },
+    /// The `SKIPPED-SEGMENTS` value of a Playlist Delta Update disagrees with where the first
+    /// listed segment appeared in the earlier playlist
+    DeltaUpdateSkippedSegmentsMismatch {
+        req_id: HttpRef,
+        skipped_segments: u64,
+        expected_skipped_segments: u64,
+    },
+    /// A Playlist Delta Update skipped segments within `CAN-SKIP-UNTIL` of the end of the playlist
+    DeltaUpdateSkipBoundaryExceeded {
+        req_id: HttpRef,
+        skipped_segments: u64,
+        remaining_duration_millis: u64,
+        can_skip_until_millis: u64,
+    },
+    /// `RECENTLY-REMOVED-DATERANGES` names an ID that was never seen in an `EXT-X-DATERANGE` tag
+    UnknownRecentlyRemovedDaterange {
+        req_id: HttpRef,
+        daterange_id: String,
+    },
+    /// `RECENTLY-REMOVED-DATERANGES` names an ID that is still present in the playlist
+    RecentlyRemovedDaterangePresent {
+        req_id: HttpRef,
+        daterange_id: String,
+    },
+    /// The full playlist could not be rebuilt from a Playlist Delta Update and the last full
+    /// playlist; for instance because segments were skipped that the last playlist didn't list
+    DeltaUpdateUnusable {
+        req_id: HttpRef,
+        skipped_segments: u64,
+    },
+    /// An `EXT-X-RENDITION-REPORT` gives a `LAST-MSN` beyond anything seen in the playlist of the
+    /// rendition it reports on
+    RenditionReportAhead {
+        req_id: HttpRef,
+        rendition_uri: String,
+        report_msn: u64,
+        report_part: Option<u16>,
+        seen_msn: u64,
+        seen_part: Option<u16>,
+    },
+    /// An `EXT-X-RENDITION-REPORT` gives a `LAST-MSN` that is well behind the playlist of the
+    /// rendition it reports on
+    RenditionReportBehind {
+        req_id: HttpRef,
+        rendition_uri: String,
+        report_msn: u64,
+        report_part: Option<u16>,
+        seen_msn: u64,
+        seen_part: Option<u16>,
+    },
+    /// The media playlist lists no segments.  Checks that compare segments between playlists are
+    /// skipped until segments are listed again.
+    EmptyMediaPlaylist {
+        req_id: HttpRef,
+    },
+    /// The playlist window moved past segments that were never seen in any playlist load
+    MsnGap {
+        delta: Delta,
+        last_msn: usize,
+        next_msn: usize,
+        missed_count: usize,
+    },
+    /// A segment listed in a media playlist could not be found, even after retrying
+    SegmentNotFound {
+        req_id: HttpRef,
+        msn: u64,
+        attempts: u32,
+    },
+    /// A segment listed in a media playlist was initially not found, but became available later
+    SegmentAvailableLate {
+        req_id: HttpRef,
+        /// the first request for the segment, which was not found
+        not_found_req_id: HttpRef,
+        msn: u64,
+        delay_millis: u64,
+    },
+    /// A segment was served with a `Content-Type` that doesn't match the type of the segment
+    SegmentIncorrectContentType {
+        req_id: HttpRef,
+        msn: u64,
+        content_type: Option<String>,
+        expected: &'static [&'static str],
+    },
+    /// Downloading a segment took longer than its duration, so a player would not be able to keep
+    /// up with playback
+    SlowSegmentDownload {
+        req_id: HttpRef,
+        msn: u64,
+        download_time_millis: u64,
+        duration_millis: u64,
+    },
+    /// A transport stream segment could not be demultiplexed
+    TsSegmentInvalid {
+        req_id: HttpRef,
+        msn: u64,
+        message: String,
+    },
+    /// A transport stream segment doesn't start with a PAT, followed by a PMT before any other
+    /// packets, so a player can't start decoding at this segment
+    TsMissingPatPmt {
+        req_id: HttpRef,
+        msn: u64,
+    },
+    /// The continuity counter of a TS packet doesn't follow on from the last packet with the same
+    /// PID.  Only the first error for each PID in a segment is reported, along with the total.
+    TsContinuityError {
+        req_id: HttpRef,
+        msn: u64,
+        pid: u16,
+        /// index of the packet within the segment
+        packet: usize,
+        expected: u8,
+        actual: u8,
+        error_count: usize,
+    },
+    /// A PCR went backwards within a segment, without the adaptation field's
+    /// discontinuity_indicator being set
+    TsPcrRegression {
+        req_id: HttpRef,
+        msn: u64,
+        pid: u16,
+        packet: usize,
+        last_pcr: u64,
+        pcr: u64,
+    },
+    /// The first presentation timestamp of a segment doesn't follow on from the end of the previous
+    /// segment, though the segment is not preceded by `EXT-X-DISCONTINUITY`
+    TsTimestampDiscontinuity {
+        req_id: HttpRef,
+        prev_req_id: HttpRef,
+        msn: u64,
+        expected_pts: u64,
+        pts: u64,
+        jump_millis: i64,
+    },
+    /// The PMT of a transport stream segment lists a stream of a type not declared in the variant
+    /// stream's `CODECS` attribute
+    TsStreamTypeNotInCodecs {
+        req_id: HttpRef,
+        msn: u64,
+        pid: u16,
+        stream_type: u8,
+        codecs: String,
+    },
+    /// The profile or level of the video in a transport stream segment, from its sequence parameter
+    /// set, doesn't match that given for the codec by the variant stream's `CODECS` attribute
+    VideoCodecsMismatch {
+        req_id: HttpRef,
+        msn: u64,
+        codec: String,
+        codecs: String,
+    },
+    /// The picture size given by the sequence parameter set of a segment doesn't match the variant
+    /// stream's `RESOLUTION` attribute
+    VideoResolutionMismatch {
+        req_id: HttpRef,
+        msn: u64,
+        resolution: String,
+        declared: String,
+    },
+    /// The resolution, profile or level of the video changed from one segment to the next without
+    /// an `EXT-X-DISCONTINUITY`
+    VideoParametersChanged {
+        req_id: HttpRef,
+        prev_req_id: HttpRef,
+        msn: u64,
+        last_codec: String,
+        this_codec: String,
+        last_resolution: String,
+        this_resolution: String,
+    },
+    /// The content of a picture parameter set changed from one segment to the next without an
+    /// `EXT-X-DISCONTINUITY`, while keeping the same `pic_parameter_set_id`
+    VideoPictureParametersChanged {
+        req_id: HttpRef,
+        prev_req_id: HttpRef,
+        msn: u64,
+        pps_id: u32,
+    },
+    /// The playlist has `EXT-X-INDEPENDENT-SEGMENTS`, but the video of a segment doesn't start with
+    /// a key frame
+    SegmentNotIndependent {
+        req_id: HttpRef,
+        msn: u64,
+        uri: String,
+    },
+    /// The URI of a key doesn't have the scheme expected for its `KEYFORMAT`; `skd:` for FairPlay,
+    /// or `data:` for the DRM systems whose `pssh` box is carried in the URI
+    KeyUriSchemeMismatch {
+        at: ManifestRef,
+        keyformat: String,
+        uri: String,
+        expected_scheme: &'static str,
+    },
+    /// The `data:` URI of a key doesn't hold a valid `pssh` box
+    KeyPsshInvalid {
+        at: ManifestRef,
+        keyformat: String,
+        message: String,
+    },
+    /// The `pssh` box in the `data:` URI of a key is for a different DRM system to that given by
+    /// the `KEYFORMAT`
+    KeyPsshSystemIdMismatch {
+        at: ManifestRef,
+        keyformat: String,
+        system_id: String,
+    },
+    /// The `pssh` box in the `data:` URI of a key lists key IDs, but not the `KEYID` of the tag
+    KeyIdNotInPssh {
+        at: ManifestRef,
+        keyid: String,
+        pssh_key_ids: Vec<String>,
+    },
+    /// A key has the same URI as an `EXT-X-SESSION-KEY` of the main playlist, but a different
+    /// `METHOD`, `KEYFORMAT` or `KEYFORMATVERSIONS`
+    SessionKeyMismatch {
+        at: ManifestRef,
+        main_req_id: HttpRef,
+        session_key: String,
+        key: String,
+    },
+    /// The same segment is encrypted using different `KEYFORMATVERSIONS` of a `KEYFORMAT` in two
+    /// variant streams
+    VariantKeyFormatVersionsMismatch {
+        req_id: HttpRef,
+        other_req_id: HttpRef,
+        msn: u64,
+        keyformat: String,
+        keyformatversions: String,
+        other_keyformatversions: String,
+    },
+    /// The same segment is encrypted with keys having different `KEYID`s in two variant streams
+    VariantKeyIdMismatch {
+        req_id: HttpRef,
+        other_req_id: HttpRef,
+        msn: u64,
+        keyformat: String,
+        keyid: Option<String>,
+        other_keyid: Option<String>,
+    },
+    /// An `AES-128` key was not exactly 16 bytes long
+    KeyWrongLength {
+        req_id: HttpRef,
+        length: usize,
+    },
+    /// An `AES-128` encrypted segment could not be decrypted with its key
+    SegmentDecryptionFailed {
+        req_id: HttpRef,
+        msn: u64,
+        uri: String,
+    },
+    /// The bitrate of a run of consecutive segments lasting between 0.5 and 1.5 times the target
+    /// duration exceeds the variant stream's `BANDWIDTH` attribute
+    PeakBitrateExceedsBandwidth {
+        req_id: HttpRef,
+        first_req_id: HttpRef,
+        first_msn: u64,
+        last_msn: u64,
+        bitrate: u64,
+        bandwidth: u64,
+    },
+    /// The average bitrate of the segments downloaded so far differs from the variant stream's
+    /// `AVERAGE-BANDWIDTH` attribute by more than 10%
+    AverageBitrateMismatch {
+        req_id: HttpRef,
+        msn: u64,
+        bitrate: u64,
+        average_bandwidth: u64,
+        duration_millis: u64,
+    },
+    /// A Media Initialization Section given by `EXT-X-MAP` could not be parsed as fragmented MP4
+    Mp4InitSegmentInvalid {
+        req_id: HttpRef,
+        message: String,
+    },
+    /// The codec of a track described by a Media Initialization Section is not declared by the
+    /// variant stream's `CODECS` attribute
+    InitSegmentCodecsMismatch {
+        req_id: HttpRef,
+        track_id: u32,
+        codec: String,
+        codecs: String,
+    },
+    /// A segment following `EXT-X-MAP` could not be parsed as fragmented MP4
+    Mp4SegmentInvalid {
+        req_id: HttpRef,
+        msn: u64,
+        message: String,
+    },
+    /// The summed durations of the samples of a track in a fragmented MP4 segment don't match the
+    /// segment's `EXTINF` duration
+    Mp4DurationMismatch {
+        req_id: HttpRef,
+        msn: u64,
+        track_id: u32,
+        duration_millis: u64,
+        extinf_millis: u64,
+    },
+    /// The `tfdt` baseMediaDecodeTime of a track in a fragmented MP4 segment doesn't follow on from
+    /// the end of the previous segment, though the segment is not preceded by `EXT-X-DISCONTINUITY`
+    Mp4DecodeTimeDiscontinuity {
+        req_id: HttpRef,
+        prev_req_id: HttpRef,
+        msn: u64,
+        track_id: u32,
+        /// in the track's timescale
+        expected_decode_time: u64,
+        decode_time: u64,
+    },
+    /// A variant stream was added to the main playlist since it was last loaded
+    VariantStreamAdded {
+        delta: Delta,
+        uri: String,
+    },
+    /// A variant stream was removed from the main playlist since it was last loaded
+    VariantStreamRemoved {
+        delta: Delta,
+        uri: String,
+    },
+    /// The `BANDWIDTH` of a variant stream changed between loads of the main playlist
+    VariantBandwidthChanged {
+        delta: Delta,
+        uri: String,
+        last_bandwidth: Option<u64>,
+        this_bandwidth: Option<u64>,
+    },
+    /// The `CODECS` of a variant stream changed between loads of the main playlist
+    VariantCodecsChanged {
+        delta: Delta,
+        uri: String,
+        last_codecs: Option<String>,
+        this_codecs: Option<String>,
+    },
+    /// The `RESOLUTION` of a variant stream changed between loads of the main playlist
+    VariantResolutionChanged {
+        delta: Delta,
+        uri: String,
+        last_resolution: Option<String>,
+        this_resolution: Option<String>,
+    },
+    /// An `EXT-X-MEDIA` rendition was added to the main playlist since it was last loaded
+    RenditionAdded {
+        delta: Delta,
+        media_type: String,
+        group_id: String,
+        name: String,
+    },
+    /// An `EXT-X-MEDIA` rendition was removed from the main playlist since it was last loaded
+    RenditionRemoved {
+        delta: Delta,
+        media_type: String,
+        group_id: String,
+        name: String,
+    },
+    /// The main playlist has an `EXT-X-SESSION-KEY` with `METHOD=NONE`
+    SessionKeyMethodNone {
+        at: ManifestRef,
+    },
+    /// A variant stream's `AUDIO`, `VIDEO`, `SUBTITLES` or `CLOSED-CAPTIONS` attribute names a group
+    /// for which there is no `EXT-X-MEDIA` tag of the matching `TYPE`
+    UnresolvedGroupId {
+        at: ManifestRef,
+        uri: String,
+        media_type: &'static str,
+        group_id: String,
+    },
+    /// None of the renditions of an `EXT-X-MEDIA` group has `DEFAULT=YES`
+    RenditionGroupWithoutDefault {
+        at: ManifestRef,
+        media_type: String,
+        group_id: String,
+    },
+    /// More than one of the renditions of an `EXT-X-MEDIA` group has `DEFAULT=YES`
+    RenditionGroupMultipleDefaults {
+        at: ManifestRef,
+        media_type: String,
+        group_id: String,
+        default_count: usize,
+    },
+    /// A variant stream has no `CODECS` attribute, so players must download media to discover
+    /// whether they can play it
+    VariantMissingCodecs {
+        at: ManifestRef,
+        uri: String,
+    },
+    /// A variant stream's `AVERAGE-BANDWIDTH` is greater than its peak `BANDWIDTH`
+    AverageBandwidthExceedsBandwidth {
+        at: ManifestRef,
+        uri: String,
+        bandwidth: u64,
+        average_bandwidth: u64,
+    },
+    /// No variant stream has the resolution and codecs of an `EXT-X-I-FRAME-STREAM-INF` stream
+    IFrameStreamWithoutVariant {
+        at: ManifestRef,
+        uri: String,
+    },
+    /// The same URI is given for more than one variant stream or rendition
+    DuplicateUri {
+        at: ManifestRef,
+        uri: String,
+        /// one-based number of the line where the URI first appears
+        first_line: usize,
+    },
+    /// A variant stream's `CLOSED-CAPTIONS` attribute is inconsistent with the other variants, i.e.
+    /// `NONE` in some variants but not all, or absent where other variants have closed captions
+    ClosedCaptionsInconsistent {
+        at: ManifestRef,
+        uri: String,
+        closed_captions: Option<String>,
+    },
+    /// This variant's playlist is behind the playlist last loaded for another variant
+    VariantMsnSkew {
+        req_id: HttpRef,
+        other_req_id: HttpRef,
+        last_msn: usize,
+        other_last_msn: usize,
+    },
+    /// The segment with the same media sequence number has a different `EXTINF` duration in
+    /// another variant
+    VariantSegmentDurationMismatch {
+        req_id: HttpRef,
+        other_req_id: HttpRef,
+        msn: usize,
+        duration_millis: u64,
+        other_duration_millis: u64,
+    },
+    /// The segment with the same media sequence number is preceded by `EXT-X-DISCONTINUITY` in one
+    /// variant but not in another
+    VariantDiscontinuityMisaligned {
+        req_id: HttpRef,
+        other_req_id: HttpRef,
+        msn: usize,
+        /// true if the discontinuity is present in this variant but missing in the other
+        discontinuity: bool,
+    },
+    /// The segment with the same media sequence number has a different `EXT-X-PROGRAM-DATE-TIME` in
+    /// another variant
+    VariantProgramDateTimeMismatch {
+        req_id: HttpRef,
+        other_req_id: HttpRef,
+        msn: usize,
+        program_date_time: String,
+        other_program_date_time: String,
+    },
+    /// The `EXT-X-PROGRAM-DATE-TIME` of a segment differs from the value given for the same segment
+    /// in an earlier playlist
+    ProgramDateTimeChanged {
+        delta: Delta,
+        msn: usize,
+        last_program_date_time: String,
+        this_program_date_time: String,
+    },
+    /// The `EXT-X-PROGRAM-DATE-TIME` of a segment differs from the program-date-time of the
+    /// previous segment plus its `EXTINF` duration
+    ProgramDateTimeDrift {
+        req_id: HttpRef,
+        msn: usize,
+        program_date_time: String,
+        expected_program_date_time: String,
+        drift_millis: i64,
+    },
+    /// A segment following `EXT-X-DISCONTINUITY` has no `EXT-X-PROGRAM-DATE-TIME`, even though the
+    /// playlist uses program-date-times
+    MissingProgramDateTimeAfterDiscontinuity {
+        req_id: HttpRef,
+        msn: usize,
+    },
+    /// Summary of the one-pass check of a VOD playlist, logged after any problems found
+    VodPlaylistReport {
+        req_id: HttpRef,
+        segment_count: usize,
+        total_duration_millis: u64,
+        target_duration_millis: u64,
+        has_end_list: bool,
+        /// the number of errors and warnings logged while checking the playlist
+        problem_count: usize,
+    },
+    /// A playlist checked as VOD has no `EXT-X-ENDLIST` tag
+    VodMissingEndList {
+        req_id: HttpRef,
+    },
+    /// The segment's `EXTINF` duration, rounded to the nearest integer, exceeds
+    /// `EXT-X-TARGETDURATION`
+    ExtinfExceedsTargetDuration {
+        at: ManifestRef,
+        msn: u64,
+        duration_millis: u64,
+        target_duration_millis: u64,
+    },
+    /// The segment's `EXT-X-BYTERANGE` doesn't start where the previous segment's sub-range of the
+    /// same resource ended, leaving a gap or overlap
+    ByteRangeNotContiguous {
+        at: ManifestRef,
+        msn: u64,
+        expected_offset: u64,
+        offset: u64,
+    },
+    /// The segment's `EXT-X-BYTERANGE` has no offset, but the previous segment was not a sub-range
+    /// of the same resource
+    ByteRangeOffsetMissing {
+        at: ManifestRef,
+        msn: u64,
+    },
+    /// The end of the segment's `EXT-X-BYTERANGE` sub-range is beyond the largest possible offset
+    ByteRangeInvalid {
+        at: ManifestRef,
+        msn: u64,
+        offset: u64,
+        length: u64,
+    },
+    /// The playlist uses a feature requiring a later protocol version than given by
+    /// `EXT-X-VERSION` (which defaults to `1` if absent)
+    VersionTooLow {
+        at: ManifestRef,
+        version: u32,
+        required_version: u32,
+        feature: &'static str,
+    },
 }
 fn ser_playlist_type<S>(ty: &Option<PlaylistType>, s: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
     match ty {
@@ -232,12 +1029,15 @@ impl From<HlsManifestError> for HlsEvent {
             HlsManifestError::Utf8(req_id, err) => HlsEvent::PlaylistUtf8Error {
                 req_id,
             },
-            HlsManifestError::Parse(req_id, err) => HlsEvent::PlaylistParseError {
-                req_id,
-            },
-            HlsManifestError::Parse2(req_id, err) => HlsEvent::PlaylistParseError {
+            HlsManifestError::Parse(req_id, err) => parse_err_to_event(req_id, &err),
+            HlsManifestError::Parse2(req_id, err) => HlsEvent::PlaylistSyntaxError {
                 req_id,
+                kind: "main_playlist",
+                message: format!("{:?}", err),
+                start: None,
+                end: None,
             },
+            HlsManifestError::MediaManifest(req_id, err, span) => media_manifest_err_to_event(req_id, &err, span),
             HlsManifestError::Url(req_id, err) => HlsEvent::PlaylistMalformedUrl {
                 req_id,
             },
@@ -268,6 +1068,8 @@ pub enum HlsManifestError {
     Utf8(HttpRef, std::string::FromUtf8Error),
     Parse(HttpRef, hls_m3u8::parser::ParseError),
     Parse2(HttpRef, hls_m3u8::Error),
+    /// also gives the byte offsets of the playlist line at which the error was found
+    MediaManifest(HttpRef, media_manifest::MediaManifestError, Option<(usize, usize)>),
     Url(HttpRef, url::ParseError),
     ResponseSizeExceedsLimit(HttpRef, usize),
     NumberOfRequestsExceedsLimit(u64),
@@ -305,6 +1107,9 @@ impl HlsManifestError {
     }
 }
 
+/// Creates a metric for the media playlist with the given URI
+type MetricFor<M> = Rc<dyn Fn(&str) -> M>;
+
 pub struct HlsProcessor<S: Snoop, L: EventSink<Extra = HlsEvent>, M: Metric> {
     client: http_snoop::Client<S>,
     url: reqwest::Url,
@@ -312,6 +1117,10 @@ pub struct HlsProcessor<S: Snoop, L: EventSink<Extra = HlsEvent>, M: Metric> {
     manifest_latency: M,
     stream_latency: M,
     msn_regression: M,
+    /// gives the metric for the segment bitrate of the variant stream with the given URI
+    variant_bitrate: Option<MetricFor<M>>,
+    options: MediaPlaylistOptions,
+    main_reload_interval: Option<time::Duration>,
 }
 impl<S: Snoop, L: EventSink<Extra = HlsEvent>, M: Metric> HlsProcessor<S, L, M> {
     pub fn new(
@@ -328,10 +1137,45 @@ impl<S: Snoop, L: EventSink<Extra = HlsEvent>, M: Metric> HlsProcessor<S, L, M>
             log,
             manifest_latency,
             stream_latency,
-            msn_regression
+            msn_regression,
+            variant_bitrate: None,
+            options: MediaPlaylistOptions::default(),
+            main_reload_interval: None,
         }
     }
 
+    /// Request Playlist Delta Updates (using `_HLS_skip`) from servers that advertise
+    /// `CAN-SKIP-UNTIL`, rebuilding and checking the full playlist from each one
+    pub fn delta_updates(&mut self, enabled: bool) {
+        self.options.delta_updates = enabled;
+    }
+
+    /// Check each media playlist once, as a complete VOD playlist, rather than polling for
+    /// updates.  Playlists declaring `EXT-X-PLAYLIST-TYPE:VOD` are always checked this way.
+    pub fn vod(&mut self, enabled: bool) {
+        self.options.vod = enabled;
+    }
+
+    /// Download each segment as it appears in a media playlist, checking that it's available and
+    /// delivered promptly
+    pub fn fetch_segments(&mut self, enabled: bool) {
+        self.options.fetch_segments = enabled;
+    }
+
+    /// Reload the main manifest at the given interval while media playlists are still being
+    /// processed, checking for changes from the last copy, and starting or stopping the processing
+    /// of media playlists as they are added to or removed from it
+    pub fn main_reload_interval(&mut self, interval: Option<time::Duration>) {
+        self.main_reload_interval = interval;
+    }
+
+    /// Record the bitrate of each downloaded segment of each variant stream, in bits per second, to
+    /// a metric created for the variant by the given function, which is passed the variant's URI.
+    /// Only has an effect when segments are fetched.
+    pub fn variant_bitrate_metric(&mut self, metric_for: impl Fn(&str) -> M + 'static) {
+        self.variant_bitrate = Some(Rc::new(metric_for));
+    }
+
     pub async fn start(mut self) -> Result<(), HlsManifestError> {
         let res = self.run().await;
         println!("HlsProcessor::run() -> {:?}", res);
@@ -341,46 +1185,151 @@ impl<S: Snoop, L: EventSink<Extra = HlsEvent>, M: Metric> HlsProcessor<S, L, M>
         res
     }
     async fn run(&mut self) -> Result<(), HlsManifestError> {
-        let url = self.url.clone();
-        let client = self.client.clone();
-        let log = self.log.clone();
-        // TODO: periodically reload the main manifest while live, and asset invariants
+        let mut log = self.log.clone();
         let (href, body) = self.load_main_manifest().await?;
-        let main_manifest = hls_m3u8::MasterPlaylist::try_from(body.as_str())
-            .map_err(|e| HlsManifestError::Parse2(href.clone(), e) )?;
-        let variant_urls: Result<Vec<_>, _> = main_manifest.variant_streams.iter().map(|var| {
-            match var {
-                VariantStream::ExtXIFrame { uri, .. } | VariantStream::ExtXStreamInf { uri, .. } => {
-                    url.join(uri)
-                },
-            }
-        }).collect();
-        let mut variant_urls = variant_urls
+        let manifest = parse_main_manifest(&href, &body, true, &mut log)?;
+        let urls = media_playlist_urls(&self.url, &manifest)
             .map_err(|e| HlsManifestError::Url(href.clone(), e))?;
-        self.log.info(HlsEvent::LoadedMain {
+        log.info(HlsEvent::LoadedMain {
             req_id: href.clone(),
-            variant_count: variant_urls.len(),
+            variant_count: manifest.variants.len(),
         });
-
-        let media_urls: Result<Vec<_>, _> = main_manifest.media.iter().filter_map(|media| media.uri().map(|u| url.join(u)) ).collect();
-        let media_urls = media_urls
-            .map_err(|e| HlsManifestError::Url(href, e))?;
-        variant_urls.extend(media_urls);
+        let drm_keys = drm::DrmKeys::default();
+        drm_keys.set_session_keys(&href, &manifest.session_keys);
+        let mut main_check = main_check::MainPlaylistCheck::new(log.clone());
+        main_check.next_manifest(href, manifest);
 
         // unlike a real HLS client, we process all media-manifests in parallel rather than
         // sticking with a single bitrate
-        let items = futures::future::join_all(
-            variant_urls.into_iter().map(move |u| {
-                let mut log = log.clone();
-                // TODO: ideally track separate stream_latency metrics per stream rather than aggregating
-                process_media_manifest(client.clone(), self.manifest_latency.clone(), self.stream_latency.clone(), self.msn_regression.clone(), u, log.clone())
-                    .map_err(move |res| log.error(res.into()) )
-            })
-        ).await;
-        println!("items {:?}", items);
+        let positions = rendition::RenditionPositions::default();
+        let alignment = alignment::VariantAlignment::default();
+        let mut tasks = futures::stream::FuturesUnordered::new();
+        // the media playlists listed in the last copy of the main playlist, with handles to stop
+        // processing those still running should they be removed from the main playlist
+        let mut started: HashMap<reqwest::Url, Option<future::AbortHandle>> = HashMap::new();
+        for entry in urls {
+            if !started.contains_key(&entry.url) {
+                let url = entry.url.clone();
+                let (task, handle) = self.media_task(entry, &positions, &alignment, &drm_keys);
+                tasks.push(task);
+                started.insert(url, Some(handle));
+            }
+        }
+        // the last main playlist body loaded, so that problems with an unchanged playlist aren't
+        // reported again on every reload
+        let mut last_body = body;
+        let mut reload = Box::pin(self.reload_main_manifest());
+        while !tasks.is_empty() {
+            let next = match future::select(tasks.next(), &mut reload).await {
+                future::Either::Left((finished, _)) => future::Either::Left(finished),
+                future::Either::Right((result, _)) => future::Either::Right(result),
+            };
+            match next {
+                future::Either::Left(Some((url, Ok(())))) => {
+                    // the playlist ended, or processing it failed; either way there's nothing to stop
+                    if let Some(handle) = started.get_mut(&url) {
+                        handle.take();
+                    }
+                },
+                future::Either::Left(_) => {},
+                future::Either::Right(result) => {
+                    reload = Box::pin(self.reload_main_manifest());
+                    let (href, body) = match result {
+                        Ok(r) => r,
+                        Err(e) => {
+                            log.error(e.into());
+                            continue;
+                        }
+                    };
+                    let changed = body != last_body;
+                    let parsed = parse_main_manifest(&href, &body, changed, &mut log);
+                    last_body = body;
+                    let manifest = match parsed {
+                        Ok(m) => m,
+                        Err(e) => {
+                            if changed {
+                                log.error(e.into());
+                            }
+                            continue;
+                        }
+                    };
+                    let urls = match media_playlist_urls(&self.url, &manifest) {
+                        Ok(u) => u,
+                        Err(e) => {
+                            log.error(HlsManifestError::Url(href, e).into());
+                            continue;
+                        }
+                    };
+                    drm_keys.set_session_keys(&href, &manifest.session_keys);
+                    main_check.next_manifest(href, manifest);
+                    let listed: HashSet<_> = urls.iter().map(|e| e.url.clone() ).collect();
+                    started.retain(|url, handle| {
+                        if listed.contains(url) {
+                            return true;
+                        }
+                        if let Some(handle) = handle {
+                            handle.abort();
+                        }
+                        positions.remove(url);
+                        alignment.remove(url);
+                        drm_keys.remove(url);
+                        false
+                    });
+                    for entry in urls {
+                        if !started.contains_key(&entry.url) {
+                            let url = entry.url.clone();
+                            let (task, handle) = self.media_task(entry, &positions, &alignment, &drm_keys);
+                            tasks.push(task);
+                            started.insert(url, Some(handle));
+                        }
+                    }
+                },
+            }
+        }
         Ok(())
     }
 
+    /// Processes the given media playlist until it ends or the returned handle is used to stop it
+    fn media_task(&self, entry: MediaPlaylistEntry, positions: &rendition::RenditionPositions, alignment: &alignment::VariantAlignment, drm_keys: &drm::DrmKeys) -> (impl Future<Output = (reqwest::Url, Result<(), future::Aborted>)>, future::AbortHandle) {
+        let mut log = self.log.clone();
+        let url = entry.url;
+        let alignment = if entry.ladder { Some(alignment.clone()) } else { None };
+        let keys = drm_keys.playlist(&url, entry.ladder);
+        // TODO: ideally track separate stream_latency metrics per stream rather than aggregating
+        let bitrate_metric = match (&entry.variant, &self.variant_bitrate) {
+            (Some(variant), Some(metric_for)) if self.options.fetch_segments => Some(metric_for(&variant.uri)),
+            _ => None,
+        };
+        let playlist = MediaPlaylistContext {
+            url: url.clone(),
+            variant: entry.variant,
+            bitrate_metric: bitrate_metric.clone(),
+            options: self.options,
+            positions: positions.clone(),
+            alignment,
+            keys,
+        };
+        let task = process_media_manifest(self.client.clone(), self.manifest_latency.clone(), self.stream_latency.clone(), self.msn_regression.clone(), playlist, log.clone())
+            .map(move |res| if let Err(e) = res { log.error(e.into()) } );
+        let (task, handle) = future::abortable(task);
+        (task.map(move |res| {
+            if let Some(metric) = bitrate_metric {
+                metric.close();
+            }
+            (url, res)
+        }), handle)
+    }
+
+    /// Waits for the main manifest reload interval, then loads the main manifest again.  Never
+    /// completes if periodic reloading is not enabled.
+    async fn reload_main_manifest(&self) -> Result<(HttpRef, String), HlsManifestError> {
+        match self.main_reload_interval {
+            Some(interval) => tokio::time::delay_for(interval).await,
+            None => future::pending().await,
+        }
+        self.load_main_manifest().await
+    }
+
     async fn load_main_manifest(&self) -> Result<(HttpRef, String), HlsManifestError>{
         let mut req = self.client.get(self.url.clone());
         req.content_role("hls_main_manifest");
@@ -396,13 +1345,145 @@ impl<S: Snoop, L: EventSink<Extra = HlsEvent>, M: Metric> HlsProcessor<S, L, M>
     }
 }
 
+/// `hls_m3u8` checks the syntax of the playlist as a whole, while `MainManifest` keeps the details
+/// that `MainPlaylistCheck` examines.  Problems reported by `hls_m3u8` are logged rather than
+/// returned, since it rejects some playlists (e.g. with unresolved `GROUP-ID` references) for which
+/// `MainPlaylistCheck` can give more specific diagnostics.  They are only logged if `report_errors`
+/// is true, which is not the case for a reload giving the same body as before.
+fn parse_main_manifest<L: EventSink<Extra = HlsEvent>>(href: &HttpRef, body: &str, report_errors: bool, log: &mut L) -> Result<main_manifest::MainManifest, HlsManifestError> {
+    if let Err(e) = hls_m3u8::MasterPlaylist::try_from(body) {
+        if report_errors {
+            log.error(HlsManifestError::Parse2(href.clone(), e).into());
+        }
+    }
+    main_manifest::MainManifest::parse(body)
+        .map_err(|e| {
+            let span = media_manifest::line_span(body, e.line);
+            HlsManifestError::MediaManifest(href.clone(), e, span)
+        })
+}
+
+/// A media playlist listed in the main manifest
+struct MediaPlaylistEntry {
+    url: reqwest::Url,
+    /// true for the variant streams of the ladder (excluding I-frame playlists and renditions),
+    /// which are expected to be aligned segment-for-segment
+    ladder: bool,
+    /// the variant stream, if this isn't the playlist of a rendition
+    variant: Option<main_manifest::Variant>,
+}
+
+fn media_playlist_urls(base: &reqwest::Url, manifest: &main_manifest::MainManifest) -> Result<Vec<MediaPlaylistEntry>, url::ParseError> {
+    let variants = manifest.variants.iter()
+        .map(|v| base.join(&v.uri).map(|url| MediaPlaylistEntry { url, ladder: !v.i_frame, variant: Some(v.clone()) } ) );
+    let media = manifest.media.iter()
+        .filter_map(|m| m.uri.as_ref() )
+        .map(|uri| base.join(uri).map(|url| MediaPlaylistEntry { url, ladder: false, variant: None } ) );
+    variants.chain(media).collect()
+}
+
+/// The position in the playlist that a blocking playlist reload asks the server to wait for, given
+/// in the `_HLS_msn` and `_HLS_part` query parameters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockingRequest {
+    pub msn: u64,
+    pub part: Option<u16>,
+}
+impl BlockingRequest {
+    /// the segment or part that will follow the last one listed in the given playlist
+    fn following(manifest: &media_manifest::MediaManifest) -> BlockingRequest {
+        if manifest.part_inf.is_some() {
+            if let Some(part) = manifest.parts.last() {
+                return if manifest.segment(part.msn).is_some() {
+                    BlockingRequest { msn: part.msn + 1, part: Some(0) }
+                } else {
+                    BlockingRequest { msn: part.msn, part: Some(part.part_num + 1) }
+                }
+            }
+        }
+        match manifest.segments.last() {
+            Some(s) => BlockingRequest { msn: s.msn + 1, part: None },
+            // wait for the first segment to be added to an empty playlist
+            None => BlockingRequest { msn: manifest.media_sequence, part: None },
+        }
+    }
+
+    fn add_to(&self, url: &mut reqwest::Url) {
+        url.query_pairs_mut().append_pair("_HLS_msn", &self.msn.to_string());
+        if let Some(part) = self.part {
+            url.query_pairs_mut().append_pair("_HLS_part", &part.to_string());
+        }
+    }
+
+    /// true if the playlist lists the requested segment or part, or anything following it
+    pub fn reached_by(&self, manifest: &media_manifest::MediaManifest) -> bool {
+        let last_seg = manifest.segments.last().map(|s| s.msn );
+        if last_seg.map(|msn| msn >= self.msn ).unwrap_or(false) {
+            return true;
+        }
+        let last_part = manifest.parts.last().map(|p| (p.msn, p.part_num) );
+        match (self.part, last_part) {
+            (Some(part), Some(last_part)) => last_part >= (self.msn, part),
+            (None, Some((msn, _))) => msn > self.msn,
+            (_, None) => false,
+        }
+    }
+
+    /// true if the playlist lists the requested segment or part itself
+    pub fn listed_in(&self, manifest: &media_manifest::MediaManifest) -> bool {
+        match self.part {
+            Some(part) => manifest.part(self.msn, part).is_some(),
+            None => manifest.segment(self.msn).is_some(),
+        }
+    }
+}
+
+/// The `_HLS_skip` query parameter value used to request a Playlist Delta Update
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SkipRequest {
+    /// skip older segments
+    Yes,
+    /// skip older segments and older `EXT-X-DATERANGE` tags
+    V2,
+}
+impl SkipRequest {
+    fn for_manifest(manifest: &media_manifest::MediaManifest) -> Option<SkipRequest> {
+        let control = manifest.server_control.as_ref()?;
+        control.can_skip_until?;
+        Some(if control.can_skip_dateranges { SkipRequest::V2 } else { SkipRequest::Yes })
+    }
+
+    fn add_to(&self, url: &mut reqwest::Url) {
+        let value = match self {
+            SkipRequest::Yes => "YES",
+            SkipRequest::V2 => "v2",
+        };
+        url.query_pairs_mut().append_pair("_HLS_skip", value);
+    }
+}
+
+/// After this many Playlist Delta Updates in a row, a full playlist is requested so that the rebuilt
+/// playlists can be checked against what the server actually serves
+const DELTA_UPDATE_VERIFY_INTERVAL: usize = 10;
+
 struct MediaManifestState<S: Snoop> {
     client: http_snoop::Client<S>,
     url: reqwest::Url,
     delay: Option<time::Duration>,
+    blocking_request: Option<BlockingRequest>,
+    skip_request: Option<SkipRequest>,
+    deltas_since_full: usize,
+    /// the text of the last full playlist, from which the next delta update will be rebuilt
+    prev_body: Option<String>,
     prev_last_msn: Option<usize>,
     prev_etag: Option<String>,
     prev_last_modified: Option<String>,
+    /// the media sequence number of the last segment requested, when fetching segments
+    last_fetched_msn: Option<u64>,
+    /// the Media Initialization Section last requested, when fetching segments
+    last_fetched_map: Option<segment::InitRequest>,
+    /// the URL of the segment key last requested, when fetching segments
+    last_fetched_key: Option<reqwest::Url>,
 }
 impl<S: Snoop> MediaManifestState<S> {
     fn new(client: http_snoop::Client<S>, url: reqwest::Url) -> MediaManifestState<S> {
@@ -410,21 +1491,65 @@ impl<S: Snoop> MediaManifestState<S> {
             client,
             url,
             delay: None,
+            blocking_request: None,
+            skip_request: None,
+            deltas_since_full: 0,
+            prev_body: None,
             prev_last_msn: None,
             prev_etag: None,
             prev_last_modified: None,
+            last_fetched_msn: None,
+            last_fetched_map: None,
+            last_fetched_key: None,
         }
     }
 }
 
 const MAX_SEQUENTIAL_PLAYLIST_LOAD_ERROR_COUNT: usize = 100;
 
+/// Options controlling how each media playlist is processed
+#[derive(Debug, Clone, Copy, Default)]
+struct MediaPlaylistOptions {
+    delta_updates: bool,
+    vod: bool,
+    fetch_segments: bool,
+}
+
+/// A media playlist to be processed, with what's known of it from the main playlist, and the state
+/// it shares with the other playlists of the presentation
+struct MediaPlaylistContext<M> {
+    url: reqwest::Url,
+    /// the variant stream, if the playlist was listed by `EXT-X-STREAM-INF`
+    variant: Option<main_manifest::Variant>,
+    /// records the bitrate of downloaded segments
+    bitrate_metric: Option<M>,
+    options: MediaPlaylistOptions,
+    positions: rendition::RenditionPositions,
+    /// present for playlists that are part of the ladder of variant streams checked for alignment
+    alignment: Option<alignment::VariantAlignment>,
+    keys: drm::PlaylistKeys,
+}
+
+/// Waits for the given future, meanwhile making progress with any segment downloads
+async fn with_fetches<F: Future<Output = ()>, T>(fetches: &mut futures::stream::FuturesUnordered<F>, fut: impl Future<Output = T>) -> T {
+    futures::pin_mut!(fut);
+    loop {
+        if fetches.is_empty() {
+            return fut.await;
+        }
+        match future::select(fetches.next(), fut.as_mut()).await {
+            future::Either::Left(_) => continue,
+            future::Either::Right((result, _)) => return result,
+        }
+    }
+}
+
 async fn process_media_manifest<S, M, L>(
     client: http_snoop::Client<S>,
     mut manifest_latency: M,
     mut stream_latency: M,
     mut msn_regression: M,
-    url: reqwest::Url,
+    playlist: MediaPlaylistContext<M>,
     mut log: L
 ) -> Result<(), HlsManifestError>
     where
@@ -432,17 +1557,54 @@ async fn process_media_manifest<S, M, L>(
         M: Metric,
         L: EventSink<Extra=HlsEvent>
 {
+    let MediaPlaylistContext { url, variant, bitrate_metric, options, positions, alignment, keys } = playlist;
     let mut chk = check::MediaPlaylistCheck::new(log.clone(), msn_regression);
     let mut manifest_state = MediaManifestState::new(client, url);
     let mut playlist_sequential_load_error_count = 0;
+    let mut fetches = futures::stream::FuturesUnordered::new();
+    let segment_check = Rc::new(RefCell::new(segment_check::SegmentCheck::new(log.clone(), variant.as_ref(), bitrate_metric)));
     loop {
         if let Some(delay) = manifest_state.delay {
-            tokio::time::delay_for(delay).await;
+            with_fetches(&mut fetches, tokio::time::delay_for(delay)).await;
         }
         // TODO: if loading the manifest errors, we should try to continue polling in many cases,
         //       while currently we just stop processing the particular representation
-        match load_media_manifest(manifest_state.client.clone(), manifest_state.url.clone(), manifest_state.prev_etag.clone(), manifest_state.prev_last_modified.clone(), &mut log).await {
+        let blocking_request = manifest_state.blocking_request.take();
+        let skip_request = manifest_state.skip_request.take();
+        let load = if blocking_request.is_some() || skip_request.is_some() {
+            let mut url = manifest_state.url.clone();
+            if let Some(ref request) = blocking_request {
+                request.add_to(&mut url);
+            }
+            if let Some(ref request) = skip_request {
+                request.add_to(&mut url);
+            }
+            // each request with delivery directives is for a different URL, so cache validators
+            // don't apply
+            load_media_manifest(manifest_state.client.clone(), url, None, None, &mut log)
+        } else {
+            load_media_manifest(manifest_state.client.clone(), manifest_state.url.clone(), manifest_state.prev_etag.clone(), manifest_state.prev_last_modified.clone(), &mut log)
+        };
+        match with_fetches(&mut fetches, load).await {
             Ok(response) => {
+                let is_delta = response.manifest.skip.is_some();
+                let response = if is_delta {
+                    let rebuilt = if chk.delta_update(&response.href, &response.playlist, &response.manifest, skip_request) {
+                        apply_delta_update(&response, manifest_state.prev_body.as_deref(), skip_request, &mut log)
+                    } else {
+                        None
+                    };
+                    match rebuilt {
+                        Some(r) => r,
+                        None => {
+                            // can't check this response, so go back to requesting full playlists
+                            manifest_state.prev_body = None;
+                            continue;
+                        }
+                    }
+                } else {
+                    response
+                };
                 playlist_sequential_load_error_count = 0;
                 manifest_latency.put(response.total_time.as_millis() as u64);
                 let target_duration = response.playlist.target_duration;
@@ -467,8 +1629,38 @@ async fn process_media_manifest<S, M, L>(
                 }
                 let manifest_had_endlist = response.playlist.has_end_list;
                 let playlist_type = response.playlist.playlist_type;
+                let is_vod = options.vod || playlist_type == Some(hls_m3u8::types::PlaylistType::Vod);
+                if is_vod {
+                    vod::check_playlist(&response.href, &response.playlist, &response.manifest, &response.body, &mut log);
+                }
                 manifest_state.prev_last_msn = response.playlist.last_segment().map(|s| s.number());
                 manifest_state.delay = Some(delay);
+                if response.manifest.can_block_reload() {
+                    // rather than sleeping, ask the server to hold the next request until the
+                    // following segment / part is available,
+                    let returned_early = blocking_request.map(|r| !r.reached_by(&response.manifest) ).unwrap_or(false);
+                    manifest_state.blocking_request = Some(BlockingRequest::following(&response.manifest));
+                    manifest_state.delay = if returned_early {
+                        // avoid a tight loop of requests if the server isn't actually blocking
+                        Some(match response.manifest.part_inf {
+                            // PART-TARGET is no more than the target duration in a valid
+                            // playlist; the bound stops an unreasonable value stalling polling
+                            Some(ref part_inf) => time::Duration::from_millis((part_inf.part_target * 500.0) as u64).min(target_duration / 2),
+                            None => target_duration / 2,
+                        })
+                    } else {
+                        None
+                    };
+                }
+                if is_delta {
+                    manifest_state.deltas_since_full += 1;
+                } else {
+                    manifest_state.deltas_since_full = 0;
+                }
+                if options.delta_updates && manifest_state.deltas_since_full < DELTA_UPDATE_VERIFY_INTERVAL {
+                    manifest_state.skip_request = SkipRequest::for_manifest(&response.manifest);
+                }
+                manifest_state.prev_body = Some(response.body.clone());
                 // remember any cache-validators to enable conditional reload of the manifest next time,
                 manifest_state.prev_etag = response.href.info().response.as_ref().unwrap().headers
                     .get(header::ETAG)
@@ -478,8 +1670,52 @@ async fn process_media_manifest<S, M, L>(
                     .get(header::LAST_MODIFIED)
                     .and_then(|v| v.to_str().ok() )
                     .map(ToOwned::to_owned);
-                chk.next_playlist(response.href, response.playlist, response.total_time);
-                if manifest_had_endlist || playlist_type == Some(hls_m3u8::types::PlaylistType::Vod){
+                if options.fetch_segments {
+                    match segment::SegmentRequest::new_segments(&manifest_state.url, &response.manifest, target_duration, manifest_state.last_fetched_msn) {
+                        Ok(segments) => {
+                            for segment in segments {
+                                manifest_state.last_fetched_msn = Some(segment.msn);
+                                if let Some(ref init) = segment.map {
+                                    if Some(init) != manifest_state.last_fetched_map.as_ref() {
+                                        // only fetched again when a new EXT-X-MAP is given
+                                        manifest_state.last_fetched_map = Some(init.clone());
+                                        let fetch_init = segment::fetch_init(manifest_state.client.clone(), init, &mut log);
+                                        if let Some(href) = with_fetches(&mut fetches, fetch_init).await {
+                                            segment_check.borrow_mut().init_fetched(init, &href);
+                                        }
+                                    }
+                                }
+                                if let Some(ref key) = segment.key {
+                                    if Some(&key.url) != manifest_state.last_fetched_key.as_ref() {
+                                        // each key is fetched once, when the key is rotated
+                                        manifest_state.last_fetched_key = Some(key.url.clone());
+                                        let fetch_key = segment::fetch_key(manifest_state.client.clone(), key, &mut log);
+                                        if let Some(href) = with_fetches(&mut fetches, fetch_key).await {
+                                            segment_check.borrow_mut().key_fetched(key, &href);
+                                        }
+                                    }
+                                }
+                                let segment_check = segment_check.clone();
+                                let fetch = segment::fetch(manifest_state.client.clone(), segment, log.clone())
+                                    .map(move |fetched| if let Some((segment, href)) = fetched {
+                                        segment_check.borrow_mut().segment_fetched(&segment, &href)
+                                    });
+                                fetches.push(fetch);
+                            }
+                        },
+                        Err(e) => log.error(HlsManifestError::Url(response.href.clone(), e).into()),
+                    }
+                }
+                chk.rendition_reports(&response.href, &manifest_state.url, &response.manifest, &positions);
+                positions.update(&manifest_state.url, &response.manifest);
+                keys.update(&response.href, &response.manifest, &mut log);
+                let href = response.href.clone();
+                let body_size = response.body.len();
+                chk.next_playlist(response.href, response.playlist, response.manifest, body_size, blocking_request, response.total_time);
+                if let Some(ref alignment) = alignment {
+                    alignment.update(&manifest_state.url, &href, chk.timeline(), &mut log);
+                }
+                if manifest_had_endlist || is_vod {
                     break;
                 }
             },
@@ -497,6 +1733,10 @@ async fn process_media_manifest<S, M, L>(
                     HlsManifestError::HttpTimeout(ref href) => {
                         chk.timeout(href.clone());
                     },
+                    HlsManifestError::MediaManifest(ref href, ref err, span) => {
+                        // the playlist may have been corrected by the time it's next reloaded
+                        log.error(media_manifest_err_to_event(href.clone(), err, span));
+                    },
                     _ => {
                         return Err(e)
                     }
@@ -511,6 +1751,8 @@ async fn process_media_manifest<S, M, L>(
             }
         }
     }
+    // let any downloads of the last segments complete
+    while fetches.next().await.is_some() { }
     Ok(())
 }
 async fn load_media_manifest<S: Snoop, L: EventSink<Extra = HlsEvent>>(
@@ -554,44 +1796,121 @@ async fn load_media_manifest<S: Snoop, L: EventSink<Extra = HlsEvent>>(
             }
             b.build()
         } )
-        .map(|playlist| {
-            MediaPlaylistResponse {
-                href: resp.href(),
-                playlist,
-                total_time,
+        .map(|playlist| (resp.href(), playlist) )
+        .map_err(|e| HlsManifestError::Parse(resp.href(), e) )
+        .and_then(|(href, playlist)| {
+            // hls_m3u8 doesn't give access to the Low-Latency HLS tags, so parse those separately
+            let body = String::from_utf8_lossy(body.as_ref()).into_owned();
+            match media_manifest::MediaManifest::parse(&body) {
+                Ok(manifest) => Ok(MediaPlaylistResponse {
+                    href,
+                    playlist,
+                    manifest,
+                    body,
+                    total_time,
+                }),
+                Err(e) => {
+                    let span = media_manifest::line_span(&body, e.line);
+                    Err(HlsManifestError::MediaManifest(href, e, span))
+                },
             }
         })
-        .map_err(|e| HlsManifestError::Parse(resp.href(), e) )
+}
+
+/// Produces a response holding the full playlist rebuilt from the given Playlist Delta Update and
+/// the text of the last full playlist.  Returns `None` if that's not possible.
+fn apply_delta_update<L: EventSink<Extra = HlsEvent>>(delta: &MediaPlaylistResponse, prev_body: Option<&str>, skip_request: Option<SkipRequest>, log: &mut L) -> Option<MediaPlaylistResponse> {
+    let skipped_segments = delta.manifest.skip.as_ref().map(|s| s.skipped_segments ).unwrap_or(0);
+    let body = prev_body.and_then(|prev_body| {
+        let prev_manifest = media_manifest::MediaManifest::parse(prev_body).ok()?;
+        media_manifest::rebuild_delta_update(prev_body, &prev_manifest, &delta.body, &delta.manifest, skip_request == Some(SkipRequest::V2))
+    });
+    let body = match body {
+        Some(b) => b,
+        None => {
+            log.warning(HlsEvent::DeltaUpdateUnusable {
+                req_id: delta.href.clone(),
+                skipped_segments,
+            });
+            return None;
+        }
+    };
+    // any parse errors were already reported for the original response bodies, so they are not
+    // reported again here
+    let playlist = hls_m3u8::parser::Parser::new(hls_m3u8::parser::Cursor::from(body.as_bytes()))
+        .parse()
+        .and_then(|b| b.build() );
+    let manifest = media_manifest::MediaManifest::parse(&body);
+    match (playlist, manifest) {
+        (Ok(playlist), Ok(mut manifest)) => Some(MediaPlaylistResponse {
+            href: delta.href.clone(),
+            playlist,
+            manifest: {
+                manifest.rebuilt = true;
+                manifest
+            },
+            body,
+            total_time: delta.total_time,
+        }),
+        _ => {
+            log.error(HlsEvent::DeltaUpdateUnusable {
+                req_id: delta.href.clone(),
+                skipped_segments,
+            });
+            None
+        }
+    }
 }
 
 fn parse_err_to_event(req: HttpRef, e: &hls_m3u8::parser::ParseError) -> HlsEvent {
-    match e {
-        ParseError::Incomplete { element_name, at } => unimplemented!(),
-        ParseError::Unexpected { .. } => unimplemented!(),
-        ParseError::Utf8(_, _) => unimplemented!(),
-        ParseError::InvalidNumber => unimplemented!(),
-        ParseError::ExpectedEndOfInput(_) => unimplemented!(),
-        ParseError::Attributes => unimplemented!(),
-        ParseError::Chrono(_) => unimplemented!(),
-        ParseError::ParseFloatError { .. } => unimplemented!(),
-        ParseError::MissingAttribute { .. } => unimplemented!(),
-        ParseError::UnexpectedAttribute { .. } => unimplemented!(),
-        ParseError::Hex { .. } => unimplemented!(),
-        ParseError::MissingTargetDuration => unimplemented!(),
-        ParseError::MissingVersion => unimplemented!(),
-        ParseError::UrlWithoutExtinf { url, at } => HlsEvent::MediaPlaylistWithoutExtinf {
+    let (kind, at) = match e {
+        ParseError::UrlWithoutExtinf { url, at } => return HlsEvent::MediaPlaylistWithoutExtinf {
             req_id: req,
             url: url.to_owned(),
             start: at.start.0,
             end: at.end.0,
         },
-        ParseError::PeekFailed => unreachable!("PeekFailed should never be visible outside of the parser"),  // TODO: remove PeekFailed from public interface
+        ParseError::Incomplete { at, .. } => ("incomplete", Some(at)),
+        ParseError::Unexpected { at, .. } => ("unexpected", Some(at)),
+        ParseError::Utf8(_, _) => ("utf8", None),
+        ParseError::InvalidNumber => ("invalid_number", None),
+        ParseError::ExpectedEndOfInput(_) => ("expected_end_of_input", None),
+        ParseError::Attributes => ("attributes", None),
+        ParseError::Chrono(_) => ("date_time", None),
+        ParseError::ParseFloatError { at, .. } => ("float", Some(at)),
+        ParseError::MissingAttribute { at, .. } => ("missing_attribute", Some(at)),
+        ParseError::UnexpectedAttribute { at, .. } => ("unexpected_attribute", Some(at)),
+        ParseError::Hex { at, .. } => ("hex", Some(at)),
+        ParseError::MissingTargetDuration => ("missing_target_duration", None),
+        ParseError::MissingVersion => ("missing_version", None),
+        // should never be visible outside of the parser, but reporting it is better than panicking
+        ParseError::PeekFailed => ("peek_failed", None),  // TODO: remove PeekFailed from public interface
+    };
+    HlsEvent::PlaylistSyntaxError {
+        req_id: req,
+        kind,
+        message: format!("{:?}", e),
+        start: at.map(|at| at.start.0 ),
+        end: at.map(|at| at.end.0 ),
+    }
+}
+
+fn media_manifest_err_to_event(req: HttpRef, e: &media_manifest::MediaManifestError, span: Option<(usize, usize)>) -> HlsEvent {
+    HlsEvent::PlaylistSyntaxError {
+        req_id: req,
+        kind: e.kind.name(),
+        message: format!("line {}: {:?}", e.line + 1, e.kind),
+        start: span.map(|(start, _)| start ),
+        end: span.map(|(_, end)| end ),
     }
 }
 
 struct MediaPlaylistResponse {
     href: HttpRef,
     playlist: hls_m3u8::parser::MyMediaPlaylist,
+    manifest: media_manifest::MediaManifest,
+    /// the full playlist text, which may have been rebuilt from a Playlist Delta Update
+    body: String,
     total_time: time::Duration,
 }
 
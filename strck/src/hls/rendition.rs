@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+use super::media_manifest::MediaManifest;
+
+/// The last position reached by a rendition's playlist, as seen by strck
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenditionPosition {
+    pub last_msn: u64,
+    pub last_part: Option<u16>,
+}
+
+/// Shared between the tasks processing each media playlist, so that the `EXT-X-RENDITION-REPORT`
+/// tags in one playlist can be checked against what was actually seen in the other renditions
+#[derive(Clone, Default)]
+pub struct RenditionPositions {
+    positions: Rc<RefCell<HashMap<reqwest::Url, RenditionPosition>>>,
+}
+impl RenditionPositions {
+    /// record the position reached by the playlist loaded from the given URL
+    pub fn update(&self, url: &reqwest::Url, manifest: &MediaManifest) {
+        if let Some(last_msn) = manifest.last_msn() {
+            let pos = RenditionPosition {
+                last_msn,
+                last_part: manifest.last_part(),
+            };
+            self.positions.borrow_mut().insert(url.clone(), pos);
+        }
+    }
+
+    /// forget the rendition at the given URL, which is no longer listed in the main playlist
+    pub fn remove(&self, url: &reqwest::Url) {
+        self.positions.borrow_mut().remove(url);
+    }
+
+    /// the position last seen for the rendition with the given URL, if it has been loaded yet
+    pub fn get(&self, url: &reqwest::Url) -> Option<RenditionPosition> {
+        self.positions.borrow().get(url).copied()
+    }
+}
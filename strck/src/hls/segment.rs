@@ -0,0 +1,312 @@
+//! Downloading of the media segments listed in media playlists, checking that each is available
+//! when listed, and is served promptly with a suitable `Content-Type`.
+
+use std::time;
+use reqwest::header;
+use hyper::StatusCode;
+use crate::event_log::EventSink;
+use crate::http_snoop::{self, HttpRef, Snoop};
+use super::{HlsEvent, HlsManifestError};
+use super::media_manifest::{Key, Map, MediaManifest, Seg};
+
+/// How many more times a segment that was not found is requested before it's reported missing
+const SEGMENT_NOT_FOUND_RETRIES: u32 = 3;
+
+/// The longest `EXTINF` duration taken at face value
+const MAX_SEGMENT_DURATION: time::Duration = time::Duration::from_secs(24 * 60 * 60);
+
+/// A segment listed in a media playlist, to be downloaded
+pub struct SegmentRequest {
+    pub url: reqwest::Url,
+    pub msn: u64,
+    /// the `EXTINF` duration of the segment
+    pub duration: time::Duration,
+    /// the `EXT-X-TARGETDURATION` of the playlist listing the segment
+    pub target_duration: time::Duration,
+    /// the first and last byte positions of the segment's `EXT-X-BYTERANGE`, if it has one
+    pub byte_range: Option<(u64, u64)>,
+    /// true if the segment is preceded by `EXT-X-DISCONTINUITY`
+    pub discontinuity: bool,
+    /// true if the playlist has `EXT-X-INDEPENDENT-SEGMENTS`, so that the segment must start with
+    /// a key frame
+    pub independent: bool,
+    /// the Media Initialization Section given by the `EXT-X-MAP` in effect for the segment
+    pub map: Option<InitRequest>,
+    /// the key for decrypting the segment, if it's encrypted with `METHOD=AES-128`
+    pub key: Option<KeyRequest>,
+}
+impl SegmentRequest {
+    /// The requests for the segments of the given playlist that follow `last_msn`, or for only the
+    /// last segment if no segments have been requested yet
+    pub fn new_segments(base: &reqwest::Url, manifest: &MediaManifest, target_duration: time::Duration, last_msn: Option<u64>) -> Result<Vec<SegmentRequest>, url::ParseError> {
+        let first_msn = match (last_msn, manifest.last_msn()) {
+            (Some(last_msn), _) => last_msn + 1,
+            (None, Some(msn)) => msn,
+            (None, None) => return Ok(vec![]),
+        };
+        let independent = manifest.tag_line("EXT-X-INDEPENDENT-SEGMENTS").is_some();
+        manifest.segments.iter()
+            .zip(byte_ranges(&manifest.segments))
+            .filter(|(s, _)| s.msn >= first_msn )
+            .map(|(s, byte_range)| {
+                Ok(SegmentRequest {
+                    url: base.join(&s.uri)?,
+                    msn: s.msn,
+                    duration: extinf_duration(s),
+                    target_duration,
+                    byte_range,
+                    discontinuity: s.tag_line("EXT-X-DISCONTINUITY").is_some(),
+                    independent,
+                    map: match s.map {
+                        Some(ref map) => Some(InitRequest::new(base, map)?),
+                        None => None,
+                    },
+                    key: match s.keys.iter().find(|k| k.method == "AES-128" && k.keyformat() == "identity" ) {
+                        Some(key) => Some(KeyRequest::new(base, key, s.msn)?),
+                        None => None,
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+/// The Media Initialization Section of some segments, to be downloaded
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitRequest {
+    pub url: reqwest::Url,
+    /// the first and last byte positions of the `EXT-X-MAP` `BYTERANGE`, if it has one
+    pub byte_range: Option<(u64, u64)>,
+}
+impl InitRequest {
+    fn new(base: &reqwest::Url, map: &Map) -> Result<InitRequest, url::ParseError> {
+        Ok(InitRequest {
+            url: base.join(&map.uri)?,
+            // zero length ranges are rejected when the playlist is parsed
+            byte_range: map.byte_range.and_then(|r| {
+                let start = r.offset.unwrap_or(0);
+                Some((start, start.checked_add(r.length - 1)?))
+            }),
+        })
+    }
+}
+
+/// The key of an `AES-128` encrypted segment, to be downloaded
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyRequest {
+    pub url: reqwest::Url,
+    /// the initialization vector for the segment, which unless given by the `IV` attribute is the
+    /// segment's media sequence number
+    pub iv: [u8; 16],
+}
+impl KeyRequest {
+    fn new(base: &reqwest::Url, key: &Key, msn: u64) -> Result<KeyRequest, url::ParseError> {
+        // the URI is required for methods other than NONE
+        let uri = key.uri.as_deref().unwrap_or("");
+        Ok(KeyRequest {
+            url: base.join(uri)?,
+            iv: key.iv.unwrap_or(u128::from(msn)).to_be_bytes(),
+        })
+    }
+}
+
+/// The `EXTINF` duration of the segment.  Negative and non-finite values are rejected when the
+/// playlist is parsed, but an absurdly large value is limited here so that the arithmetic on
+/// segment durations that follows can't overflow.
+fn extinf_duration(seg: &Seg) -> time::Duration {
+    time::Duration::from_secs_f64(f64::from(seg.duration.unwrap_or(0.0)).min(MAX_SEGMENT_DURATION.as_secs_f64()))
+}
+
+/// The first and last byte positions of each segment's sub-range, taking the start of a range with
+/// no explicit offset from the end of the previous segment's sub-range of the same resource
+fn byte_ranges(segments: &[Seg]) -> Vec<Option<(u64, u64)>> {
+    // the URI of the previous segment, and the last byte position of its sub-range
+    let mut prev: Option<(&str, u64)> = None;
+    segments.iter()
+        .map(|seg| {
+            let range = seg.byte_range.and_then(|range| {
+                let start = match (range.offset, prev) {
+                    (Some(offset), _) => offset,
+                    (None, Some((uri, prev_last))) if uri == seg.uri => prev_last.checked_add(1)?,
+                    (None, _) => return None,
+                };
+                // zero length ranges are rejected when the playlist is parsed
+                Some((start, start.checked_add(range.length - 1)?))
+            });
+            prev = range.map(|(_, last)| (seg.uri.as_str(), last) );
+            range
+        })
+        .collect()
+}
+
+/// The `Content-Type` values appropriate for a segment, given the extension of its URL, or `None`
+/// if the type of the segment isn't known
+fn expected_content_types(url: &reqwest::Url) -> Option<&'static [&'static str]> {
+    let path = url.path();
+    let ext = &path[path.rfind('.')? + 1..];
+    Some(match ext {
+        "ts" => &["video/mp2t"],
+        "mp4" | "m4s" | "m4v" | "m4a" | "cmfv" | "cmfa" => &["video/mp4", "audio/mp4", "video/iso.segment", "application/mp4"],
+        "aac" => &["audio/aac"],
+        "vtt" | "webvtt" => &["text/vtt"],
+        _ => return None,
+    })
+}
+
+/// Downloads the segment, retrying if it's not found, and returns the segment along with the
+/// successful response
+pub async fn fetch<S: Snoop, L: EventSink<Extra = HlsEvent>>(client: http_snoop::Client<S>, segment: SegmentRequest, mut log: L) -> Option<(SegmentRequest, HttpRef)> {
+    let mut first_not_found: Option<(HttpRef, time::Instant)> = None;
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let mut req = client.get(segment.url.clone());
+        req.content_role("hls_media_segment");
+        if let Some((first, last)) = segment.byte_range {
+            req.header(header::RANGE, format!("bytes={}-{}", first, last));
+        }
+        let resp = match req.send().await {
+            Ok(resp) => resp,
+            Err(e) => {
+                log.error(HlsManifestError::from_err(e).into());
+                return None;
+            }
+        };
+        if resp.status() == StatusCode::NOT_FOUND {
+            if first_not_found.is_none() {
+                first_not_found = Some((resp.href(), time::Instant::now()));
+            }
+            if attempts > SEGMENT_NOT_FOUND_RETRIES {
+                log.error(HlsEvent::SegmentNotFound {
+                    req_id: resp.href(),
+                    msn: segment.msn,
+                    attempts,
+                });
+                return None;
+            }
+            // a segment is expected to be available as soon as it's listed, but give it a chance
+            // to turn up late
+            tokio::time::delay_for(segment.duration / 2).await;
+            continue;
+        }
+        if resp.status().is_client_error() || resp.status().is_server_error() {
+            log.error(HlsEvent::HttpErrorStatus {
+                req_id: resp.href(),
+                status_code: resp.status().as_u16(),
+            });
+            return None;
+        }
+        if let Some((first_req_id, first_time)) = first_not_found {
+            log.warning(HlsEvent::SegmentAvailableLate {
+                req_id: resp.href(),
+                not_found_req_id: first_req_id,
+                msn: segment.msn,
+                delay_millis: first_time.elapsed().as_millis() as u64,
+            });
+        }
+        check_response(&segment, &resp.href(), resp.total_time(), &mut log);
+        return Some((segment, resp.href()));
+    }
+}
+
+/// Downloads the Media Initialization Section, returning the successful response
+pub async fn fetch_init<S: Snoop, L: EventSink<Extra = HlsEvent>>(client: http_snoop::Client<S>, init: &InitRequest, log: &mut L) -> Option<HttpRef> {
+    let mut req = client.get(init.url.clone());
+    req.content_role("hls_init_segment");
+    if let Some((first, last)) = init.byte_range {
+        req.header(header::RANGE, format!("bytes={}-{}", first, last));
+    }
+    let resp = match req.send().await {
+        Ok(resp) => resp,
+        Err(e) => {
+            log.error(HlsManifestError::from_err(e).into());
+            return None;
+        }
+    };
+    if resp.status().is_client_error() || resp.status().is_server_error() {
+        log.error(HlsEvent::HttpErrorStatus {
+            req_id: resp.href(),
+            status_code: resp.status().as_u16(),
+        });
+        return None;
+    }
+    Some(resp.href())
+}
+
+/// Downloads the key of an encrypted segment, returning the successful response
+pub async fn fetch_key<S: Snoop, L: EventSink<Extra = HlsEvent>>(client: http_snoop::Client<S>, key: &KeyRequest, log: &mut L) -> Option<HttpRef> {
+    let mut req = client.get(key.url.clone());
+    req.content_role("hls_key");
+    let resp = match req.send().await {
+        Ok(resp) => resp,
+        Err(e) => {
+            log.error(HlsManifestError::from_err(e).into());
+            return None;
+        }
+    };
+    if resp.status().is_client_error() || resp.status().is_server_error() {
+        log.error(HlsEvent::HttpErrorStatus {
+            req_id: resp.href(),
+            status_code: resp.status().as_u16(),
+        });
+        return None;
+    }
+    Some(resp.href())
+}
+
+fn check_response<L: EventSink<Extra = HlsEvent>>(segment: &SegmentRequest, href: &HttpRef, total_time: time::Duration, log: &mut L) {
+    if let Some(expected) = expected_content_types(&segment.url) {
+        let content_type = href.info().response.as_ref().ok()
+            .and_then(|r| r.headers.get(header::CONTENT_TYPE) )
+            .and_then(|v| v.to_str().ok() )
+            .map(ToOwned::to_owned);
+        // ignore any parameters following the media type
+        let media_type = content_type.as_ref().map(|c| c.split(';').next().unwrap().trim().to_lowercase() );
+        if !media_type.map(|t| expected.contains(&t.as_str()) ).unwrap_or(false) {
+            log.error(HlsEvent::SegmentIncorrectContentType {
+                req_id: href.clone(),
+                msn: segment.msn,
+                content_type,
+                expected,
+            })
+        }
+    }
+    // a download slower than real-time means a player could not keep up with playback
+    if total_time > segment.duration {
+        log.warning(HlsEvent::SlowSegmentDownload {
+            req_id: href.clone(),
+            msn: segment.msn,
+            download_time_millis: total_time.as_millis() as u64,
+            duration_millis: segment.duration.as_millis() as u64,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sub_ranges() {
+        let pl = "#EXTM3U
+#EXT-X-TARGETDURATION:2
+#EXT-X-BYTERANGE:100@0
+#EXTINF:2,
+a.ts
+#EXT-X-BYTERANGE:50
+#EXTINF:2,
+a.ts
+#EXT-X-BYTERANGE:50
+#EXTINF:2,
+b.ts
+#EXT-X-BYTERANGE:10@20
+#EXTINF:2,
+b.ts
+#EXTINF:2,
+c.ts
+";
+        let manifest = MediaManifest::parse(pl).unwrap();
+        assert_eq!(byte_ranges(&manifest.segments), vec![Some((0, 99)), Some((100, 149)), None, Some((20, 29)), None]);
+        assert!(MediaManifest::parse(&pl.replace("100@0", "0@0")).is_err());
+    }
+}
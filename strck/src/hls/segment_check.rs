@@ -0,0 +1,726 @@
+//! Checks of the content of downloaded media segments, and of the continuity of the content from
+//! one segment to the next within a media playlist.
+
+use std::collections::{BTreeMap, HashSet};
+use std::time;
+use aes::Aes128;
+use block_modes::{BlockMode, Cbc};
+use block_modes::block_padding::Pkcs7;
+use crate::event_log::EventSink;
+use crate::http_snoop::HttpRef;
+use crate::metric::Metric;
+use crate::{mp4, ts, video};
+use super::HlsEvent;
+use super::main_manifest::Variant;
+use super::segment::{InitRequest, KeyRequest, SegmentRequest};
+
+/// Presentation timestamps of consecutive segments are allowed to differ from exact continuity by
+/// this much (in 90kHz clock ticks), to allow for the estimation of the duration of the last frame
+/// of a segment
+const TIMESTAMP_TOLERANCE: u64 = ts::TIMESTAMP_RATE / 10;
+
+/// The summed durations of the samples of each track of a fragmented MP4 segment are allowed to
+/// differ from the `EXTINF` duration by this much
+const SEGMENT_DURATION_TOLERANCE: time::Duration = time::Duration::from_millis(100);
+
+/// How many of the most recent segments have their timing and video parameters remembered, which
+/// allows for downloads completing out of order
+const SEGMENT_HISTORY: usize = 10;
+
+/// The presentation time range of a transport stream segment
+struct SegmentTiming {
+    href: HttpRef,
+    /// 90kHz timestamp of the first frame
+    start: u64,
+    /// 90kHz timestamp following the last frame
+    end: u64,
+    /// true if the segment is preceded by `EXT-X-DISCONTINUITY`
+    discontinuity: bool,
+}
+
+/// The decode time ranges of the tracks of a fragmented MP4 segment
+struct FragmentTiming {
+    href: HttpRef,
+    fragments: Vec<mp4::TrackFragment>,
+    discontinuity: bool,
+}
+
+/// How many of the most recent segments have their sizes remembered, from which the peak bitrate
+/// is measured
+const BITRATE_HISTORY: usize = 100;
+
+/// The average bitrate is not compared with `AVERAGE-BANDWIDTH` until at least this much media has
+/// been downloaded, since the bitrate of a few segments may differ a lot from the average
+const AVERAGE_BITRATE_MIN_DURATION: time::Duration = time::Duration::from_secs(30);
+
+/// The measured average bitrate may differ from `AVERAGE-BANDWIDTH` by this proportion
+const AVERAGE_BITRATE_TOLERANCE: f64 = 0.1;
+
+/// How many of the most recently downloaded keys are retained, so that segments still being
+/// downloaded after a key rotation can be decrypted
+const KEY_HISTORY: usize = 4;
+
+/// The length of an `AES-128` key
+const AES_128_KEY_LENGTH: usize = 16;
+
+/// The video parameters of a segment, from its parameter sets
+struct SegmentVideo {
+    href: HttpRef,
+    params: video::ParameterSets,
+    discontinuity: bool,
+}
+
+/// The amount of data in a segment
+struct SegmentSize {
+    href: HttpRef,
+    bytes: u64,
+    duration: time::Duration,
+}
+
+/// Records some detail of segment `msn`, calling `compare(msn, prev, this)` for each pair of
+/// consecutive segments whose details are now both known.  Segments may be downloaded out of
+/// order, so the new segment is compared with both of its neighbours, if they are known.
+fn record_consecutive<T>(history: &mut BTreeMap<u64, T>, msn: u64, this: T, mut compare: impl FnMut(u64, &T, &T)) {
+    if let Some(prev) = msn.checked_sub(1).and_then(|m| history.get(&m) ) {
+        compare(msn, prev, &this);
+    }
+    if let Some(next) = history.get(&(msn + 1)) {
+        compare(msn + 1, &this, next);
+    }
+    history.insert(msn, this);
+    while history.len() > SEGMENT_HISTORY {
+        let oldest = *history.keys().next().unwrap();
+        history.remove(&oldest);
+    }
+}
+
+/// Checks the segments downloaded from a single media playlist
+pub struct SegmentCheck<L: EventSink<Extra = HlsEvent>, M: Metric> {
+    log: L,
+    /// receives the bitrate of each segment of a variant stream
+    bitrate_metric: Option<M>,
+    /// the `CODECS` attribute of the variant stream, if the media playlist is one
+    codecs: Option<String>,
+    /// the `RESOLUTION` attribute of the variant stream
+    resolution: Option<String>,
+    /// the `BANDWIDTH` and `AVERAGE-BANDWIDTH` attributes of the variant stream
+    bandwidth: Option<u64>,
+    average_bandwidth: Option<u64>,
+    /// true if the variant stream's audio is in a separate rendition, in which case the bitrate of
+    /// this playlist's segments alone is expected to fall short of the bandwidth attributes
+    separate_audio: bool,
+    /// the sizes of recent segments, and the total size and duration of all segments
+    sizes: BTreeMap<u64, SegmentSize>,
+    total_bytes: u64,
+    total_duration: time::Duration,
+    /// the highest peak bitrate reported as exceeding `bandwidth`
+    reported_peak_bitrate: u64,
+    /// true while the average bitrate is reported as not matching `average_bandwidth`
+    average_bitrate_mismatched: bool,
+    timing: BTreeMap<u64, SegmentTiming>,
+    fragment_timing: BTreeMap<u64, FragmentTiming>,
+    video: BTreeMap<u64, SegmentVideo>,
+    /// the last Media Initialization Section downloaded
+    init: Option<(InitRequest, mp4::InitSegment)>,
+    /// the most recently downloaded keys, oldest first
+    keys: Vec<(reqwest::Url, [u8; AES_128_KEY_LENGTH])>,
+    /// the `(pid, stream_type)` of PMT entries already reported as not matching `codecs`
+    reported_stream_types: HashSet<(u16, u8)>,
+    /// the codecs parameters and resolutions of sequence parameter sets already reported as not
+    /// matching the variant stream's attributes
+    reported_codecs: HashSet<String>,
+    reported_resolutions: HashSet<String>,
+}
+impl<L: EventSink<Extra = HlsEvent>, M: Metric> SegmentCheck<L, M> {
+    pub fn new(log: L, variant: Option<&Variant>, bitrate_metric: Option<M>) -> SegmentCheck<L, M> {
+        SegmentCheck {
+            log,
+            bitrate_metric,
+            codecs: variant.and_then(|v| v.codecs.clone() ),
+            resolution: variant.and_then(|v| v.resolution.clone() ),
+            bandwidth: variant.and_then(|v| v.bandwidth ),
+            average_bandwidth: variant.and_then(|v| v.average_bandwidth ),
+            separate_audio: variant.map(|v| v.audio.is_some() ).unwrap_or(false),
+            sizes: BTreeMap::new(),
+            total_bytes: 0,
+            total_duration: time::Duration::from_secs(0),
+            reported_peak_bitrate: 0,
+            average_bitrate_mismatched: false,
+            timing: BTreeMap::new(),
+            fragment_timing: BTreeMap::new(),
+            video: BTreeMap::new(),
+            init: None,
+            keys: vec![],
+            reported_stream_types: HashSet::new(),
+            reported_codecs: HashSet::new(),
+            reported_resolutions: HashSet::new(),
+        }
+    }
+
+    pub fn segment_fetched(&mut self, segment: &SegmentRequest, href: &HttpRef) {
+        let info = href.info();
+        let data = match info.response.as_ref().ok().and_then(|r| r.body.as_ref().ok() ) {
+            Some(body) => &body.data,
+            None => return,
+        };
+        self.check_bitrate(segment, href, data.len() as u64);
+        let decrypted;
+        let data = match segment.key {
+            Some(ref key) => match self.decrypt(segment, key, href, data) {
+                Some(d) => {
+                    decrypted = d;
+                    &decrypted[..]
+                },
+                // the content can't be checked without the key
+                None => return,
+            },
+            None => &data[..],
+        };
+        if is_transport_stream(&segment.url, data) {
+            self.check_transport_stream(segment, href, data);
+        } else if segment.map.is_some() {
+            self.check_fragmented_mp4(segment, href, data);
+        }
+    }
+
+    /// Retains a newly downloaded key, to be used in decrypting the segments to which it applies
+    pub fn key_fetched(&mut self, key: &KeyRequest, href: &HttpRef) {
+        let info = href.info();
+        let data = match info.response.as_ref().ok().and_then(|r| r.body.as_ref().ok() ) {
+            Some(body) => &body.data,
+            None => return,
+        };
+        if data.len() != AES_128_KEY_LENGTH {
+            self.log.error(HlsEvent::KeyWrongLength {
+                req_id: href.clone(),
+                length: data.len(),
+            });
+            return;
+        }
+        let mut value = [0; AES_128_KEY_LENGTH];
+        value.copy_from_slice(&data[..]);
+        self.keys.retain(|(url, _)| url != &key.url );
+        self.keys.push((key.url.clone(), value));
+        if self.keys.len() > KEY_HISTORY {
+            self.keys.remove(0);
+        }
+    }
+
+    /// Decrypts an `AES-128` segment, returning `None` if its key is not available or the segment
+    /// fails to decrypt
+    fn decrypt(&mut self, segment: &SegmentRequest, key: &KeyRequest, href: &HttpRef, data: &[u8]) -> Option<Vec<u8>> {
+        let value = self.keys.iter().find(|(url, _)| url == &key.url ).map(|(_, v)| v )?;
+        let cipher = Cbc::<Aes128, Pkcs7>::new_var(value, &key.iv).unwrap();
+        match cipher.decrypt_vec(data) {
+            Ok(decrypted) => Some(decrypted),
+            Err(_) => {
+                self.log.error(HlsEvent::SegmentDecryptionFailed {
+                    req_id: href.clone(),
+                    msn: segment.msn,
+                    uri: segment.url.to_string(),
+                });
+                None
+            }
+        }
+    }
+
+    /// Parses a newly downloaded Media Initialization Section, to be used in checking the segments
+    /// to which it applies
+    pub fn init_fetched(&mut self, init: &InitRequest, href: &HttpRef) {
+        let info = href.info();
+        let data = match info.response.as_ref().ok().and_then(|r| r.body.as_ref().ok() ) {
+            Some(body) => &body.data,
+            None => return,
+        };
+        if is_transport_stream(&init.url, data) {
+            // nothing to check until transport stream segments are parsed with it
+            return;
+        }
+        match mp4::parse_init(data) {
+            Ok(parsed) => {
+                self.check_init_codecs(href, &parsed);
+                self.init = Some((init.clone(), parsed));
+            },
+            Err(e) => {
+                self.init = None;
+                self.log.error(HlsEvent::Mp4InitSegmentInvalid {
+                    req_id: href.clone(),
+                    message: format!("{:?}", e),
+                })
+            }
+        }
+    }
+
+    /// The codec of each track of the Media Initialization Section must be declared by the variant's
+    /// `CODECS` attribute, including the profile and level, where these are known
+    fn check_init_codecs(&mut self, href: &HttpRef, init: &mp4::InitSegment) {
+        let codecs = match self.codecs {
+            Some(ref c) => c.clone(),
+            None => return,
+        };
+        let declared: Vec<String> = codecs.split(',').map(|c| c.trim().to_lowercase() ).collect();
+        for track in &init.tracks {
+            let codec = match track.codec {
+                Some(ref c) => c,
+                None => continue,
+            };
+            let sample_entry = codec.split('.').next().unwrap().to_lowercase();
+            if sample_entry == "encv" || sample_entry == "enca" {
+                // the codec of encrypted tracks is not examined
+                continue;
+            }
+            let matched = if codec.contains('.') {
+                declared.contains(&codec.to_lowercase())
+            } else {
+                declared.iter().any(|d| d.split('.').next().unwrap() == sample_entry )
+            };
+            if !matched {
+                self.log.error(HlsEvent::InitSegmentCodecsMismatch {
+                    req_id: href.clone(),
+                    track_id: track.track_id,
+                    codec: codec.clone(),
+                    codecs: codecs.clone(),
+                })
+            }
+        }
+    }
+
+    fn check_fragmented_mp4(&mut self, segment: &SegmentRequest, href: &HttpRef, data: &[u8]) {
+        // a segment downloaded after the playlist moved on to a new EXT-X-MAP can't be checked
+        let init = match self.init {
+            Some((ref init_request, ref init)) if Some(init_request) == segment.map.as_ref() => init,
+            _ => return,
+        };
+        let media = match mp4::parse_media(data, init) {
+            Ok(m) => m,
+            Err(e) => {
+                self.log.error(HlsEvent::Mp4SegmentInvalid {
+                    req_id: href.clone(),
+                    msn: segment.msn,
+                    message: format!("{:?}", e),
+                });
+                return;
+            }
+        };
+        for frag in &media.fragments {
+            let timescale = match init.track(frag.track_id) {
+                Some(track) if track.timescale > 0 => u64::from(track.timescale),
+                _ => continue,
+            };
+            // a duration too long to express in milliseconds isn't compared
+            let duration = match frag.duration.checked_mul(1000) {
+                Some(d) => time::Duration::from_millis(d / timescale),
+                None => continue,
+            };
+            let diff = if duration > segment.duration { duration - segment.duration } else { segment.duration - duration };
+            if diff > SEGMENT_DURATION_TOLERANCE {
+                self.log.error(HlsEvent::Mp4DurationMismatch {
+                    req_id: href.clone(),
+                    msn: segment.msn,
+                    track_id: frag.track_id,
+                    duration_millis: duration.as_millis() as u64,
+                    extinf_millis: segment.duration.as_millis() as u64,
+                })
+            }
+        }
+        if segment.independent {
+            if let Some(false) = fmp4_starts_with_keyframe(init, &media, data) {
+                self.log.error(HlsEvent::SegmentNotIndependent {
+                    req_id: href.clone(),
+                    msn: segment.msn,
+                    uri: segment.url.to_string(),
+                })
+            }
+        }
+        if let Some(params) = mp4::video_parameter_sets(init, &media, data) {
+            // the codecs of the Media Initialization Section as a whole are checked when it's
+            // downloaded
+            self.check_resolution(segment, href, &params.sps);
+            self.segment_video(segment, href, params);
+        }
+        let this = FragmentTiming {
+            href: href.clone(),
+            fragments: media.fragments,
+            discontinuity: segment.discontinuity,
+        };
+        let log = &mut self.log;
+        record_consecutive(&mut self.fragment_timing, segment.msn, this, |msn, prev, this| {
+            if this.discontinuity {
+                return;
+            }
+            for frag in &this.fragments {
+                let prev_frag = prev.fragments.iter().find(|f| f.track_id == frag.track_id );
+                if let (Some(prev_frag), Some(decode_time)) = (prev_frag, frag.base_media_decode_time) {
+                    // not compared if the end of the previous fragment is beyond the largest possible decode time
+                    let expected = prev_frag.base_media_decode_time.and_then(|t| t.checked_add(prev_frag.duration) );
+                    if let Some(expected) = expected {
+                        if decode_time != expected {
+                            log.error(HlsEvent::Mp4DecodeTimeDiscontinuity {
+                                req_id: this.href.clone(),
+                                prev_req_id: prev.href.clone(),
+                                msn,
+                                track_id: frag.track_id,
+                                expected_decode_time: expected,
+                                decode_time,
+                            })
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    fn check_transport_stream(&mut self, segment: &SegmentRequest, href: &HttpRef, data: &[u8]) {
+        let ts = match ts::demux(data) {
+            Ok(ts) => ts,
+            Err(e) => {
+                self.log.error(HlsEvent::TsSegmentInvalid {
+                    req_id: href.clone(),
+                    msn: segment.msn,
+                    message: format!("{:?}", e),
+                });
+                return;
+            }
+        };
+        if !ts.starts_with_pat_pmt {
+            // a player can't start decoding at this segment without first seeing these tables
+            self.log.error(HlsEvent::TsMissingPatPmt {
+                req_id: href.clone(),
+                msn: segment.msn,
+            })
+        }
+        // report only the first continuity error of each PID, since one problem often upsets many
+        // subsequent packets
+        let mut reported_pids = vec![];
+        for err in &ts.continuity_errors {
+            if reported_pids.contains(&err.pid) {
+                continue;
+            }
+            reported_pids.push(err.pid);
+            self.log.error(HlsEvent::TsContinuityError {
+                req_id: href.clone(),
+                msn: segment.msn,
+                pid: err.pid,
+                packet: err.packet,
+                expected: err.expected,
+                actual: err.actual,
+                error_count: ts.continuity_errors.iter().filter(|e| e.pid == err.pid ).count(),
+            })
+        }
+        for reg in &ts.pcr_regressions {
+            self.log.error(HlsEvent::TsPcrRegression {
+                req_id: href.clone(),
+                msn: segment.msn,
+                pid: reg.pid,
+                packet: reg.packet,
+                last_pcr: reg.last_pcr,
+                pcr: reg.pcr,
+            })
+        }
+        self.check_stream_types(segment, href, &ts);
+        if segment.independent {
+            if let Some(false) = ts_starts_with_keyframe(&ts) {
+                self.log.error(HlsEvent::SegmentNotIndependent {
+                    req_id: href.clone(),
+                    msn: segment.msn,
+                    uri: segment.url.to_string(),
+                })
+            }
+        }
+        if let Some(params) = ts_parameter_sets(&ts) {
+            self.check_video_codecs(segment, href, &params.sps);
+            self.check_resolution(segment, href, &params.sps);
+            self.segment_video(segment, href, params);
+        }
+        if let Some(t) = ts.reference_timestamps() {
+            if let (Some(start), Some(end)) = (t.start(), t.end()) {
+                self.segment_timing(segment.msn, SegmentTiming {
+                    href: href.clone(),
+                    start,
+                    end,
+                    discontinuity: segment.discontinuity,
+                });
+            }
+        }
+    }
+
+    /// Each elementary stream of a recognised type listed in the PMT must be declared by the
+    /// variant's `CODECS` attribute.  Codecs without a matching PMT entry are not reported, since
+    /// they may be carried by an associated rendition.
+    fn check_stream_types(&mut self, segment: &SegmentRequest, href: &HttpRef, ts: &ts::TsSegment) {
+        let codecs = match self.codecs {
+            Some(ref c) => c.clone(),
+            None => return,
+        };
+        let declared: Vec<&str> = codecs.split(',')
+            .map(|c| c.trim().split('.').next().unwrap() )
+            .collect();
+        for stream in &ts.streams {
+            let expected = match codecs_for_stream_type(stream.stream_type) {
+                Some(e) => e,
+                None => continue,
+            };
+            if !expected.iter().any(|e| declared.contains(e) ) && self.reported_stream_types.insert((stream.pid, stream.stream_type)) {
+                self.log.error(HlsEvent::TsStreamTypeNotInCodecs {
+                    req_id: href.clone(),
+                    msn: segment.msn,
+                    pid: stream.pid,
+                    stream_type: stream.stream_type,
+                    codecs: codecs.clone(),
+                })
+            }
+        }
+    }
+
+    /// The peak bitrate, measured over any run of consecutive segments lasting between 0.5 and 1.5
+    /// times the target duration, must not exceed the variant stream's `BANDWIDTH`, and the
+    /// average bitrate should be close to its `AVERAGE-BANDWIDTH`
+    fn check_bitrate(&mut self, segment: &SegmentRequest, href: &HttpRef, bytes: u64) {
+        if segment.duration.as_millis() == 0 {
+            return;
+        }
+        if let Some(ref mut metric) = self.bitrate_metric {
+            metric.put(bitrate(bytes, segment.duration));
+        }
+        self.sizes.insert(segment.msn, SegmentSize { href: href.clone(), bytes, duration: segment.duration });
+        while self.sizes.len() > BITRATE_HISTORY {
+            let oldest = *self.sizes.keys().next().unwrap();
+            self.sizes.remove(&oldest);
+        }
+        self.total_bytes += bytes;
+        self.total_duration += segment.duration;
+        if let Some(bandwidth) = self.bandwidth {
+            if let Some((first_msn, last_msn, peak)) = self.peak_bitrate(segment.msn, segment.target_duration) {
+                if peak > bandwidth && peak > self.reported_peak_bitrate {
+                    self.reported_peak_bitrate = peak;
+                    self.log.error(HlsEvent::PeakBitrateExceedsBandwidth {
+                        req_id: href.clone(),
+                        first_req_id: self.sizes[&first_msn].href.clone(),
+                        first_msn,
+                        last_msn,
+                        bitrate: peak,
+                        bandwidth,
+                    })
+                }
+            }
+        }
+        if let Some(average_bandwidth) = self.average_bandwidth {
+            if self.total_duration < AVERAGE_BITRATE_MIN_DURATION {
+                return;
+            }
+            let average = bitrate(self.total_bytes, self.total_duration);
+            let tolerance = average_bandwidth as f64 * AVERAGE_BITRATE_TOLERANCE;
+            let mismatched = if average < average_bandwidth {
+                !self.separate_audio && (average_bandwidth - average) as f64 > tolerance
+            } else {
+                (average - average_bandwidth) as f64 > tolerance
+            };
+            if mismatched && !self.average_bitrate_mismatched {
+                self.log.warning(HlsEvent::AverageBitrateMismatch {
+                    req_id: href.clone(),
+                    msn: segment.msn,
+                    bitrate: average,
+                    average_bandwidth,
+                    duration_millis: self.total_duration.as_millis() as u64,
+                })
+            }
+            self.average_bitrate_mismatched = mismatched;
+        }
+    }
+
+    /// The first and last MSN of the run of consecutive segments with the highest bitrate, out of
+    /// those that include segment `msn` and last between 0.5 and 1.5 times the target duration,
+    /// along with that bitrate
+    fn peak_bitrate(&self, msn: u64, target_duration: time::Duration) -> Option<(u64, u64, u64)> {
+        let (min, max) = (target_duration / 2, target_duration * 3 / 2);
+        let mut peak: Option<(u64, u64, u64)> = None;
+        // runs start no earlier than the preceding segments that together last max
+        let mut first = msn;
+        let mut preceding = time::Duration::from_secs(0);
+        while let Some(prev) = first.checked_sub(1).and_then(|m| self.sizes.get(&m) ) {
+            preceding += prev.duration;
+            if preceding > max {
+                break;
+            }
+            first -= 1;
+        }
+        for start in first..=msn {
+            let (mut bytes, mut duration) = (0, time::Duration::from_secs(0));
+            let mut end = start;
+            while let Some(size) = self.sizes.get(&end) {
+                if duration + size.duration > max {
+                    break;
+                }
+                bytes += size.bytes;
+                duration += size.duration;
+                if end >= msn && duration >= min {
+                    let rate = bitrate(bytes, duration);
+                    if peak.map(|(_, _, p)| rate > p ).unwrap_or(true) {
+                        peak = Some((start, end, rate));
+                    }
+                }
+                end += 1;
+            }
+        }
+        peak
+    }
+
+    /// The profile and level of the video must match those given for the codec in the variant
+    /// stream's `CODECS` attribute
+    fn check_video_codecs(&mut self, segment: &SegmentRequest, href: &HttpRef, sequence: &video::SequenceInfo) {
+        let codecs = match self.codecs {
+            Some(ref c) => c.clone(),
+            None => return,
+        };
+        let matches: Vec<bool> = codecs.split(',').filter_map(|c| sequence.matches_codecs(c) ).collect();
+        // a missing codec is reported by check_stream_types()
+        if !matches.is_empty() && !matches.contains(&true) && self.reported_codecs.insert(sequence.codecs.clone()) {
+            self.log.error(HlsEvent::VideoCodecsMismatch {
+                req_id: href.clone(),
+                msn: segment.msn,
+                codec: sequence.codecs.clone(),
+                codecs,
+            })
+        }
+    }
+
+    /// The picture size of the video must match the variant stream's `RESOLUTION` attribute
+    fn check_resolution(&mut self, segment: &SegmentRequest, href: &HttpRef, sequence: &video::SequenceInfo) {
+        let declared = match self.resolution.as_ref().and_then(|r| parse_resolution(r) ) {
+            Some(r) => r,
+            None => return,
+        };
+        if declared != (sequence.width, sequence.height) && self.reported_resolutions.insert(sequence.resolution()) {
+            self.log.error(HlsEvent::VideoResolutionMismatch {
+                req_id: href.clone(),
+                msn: segment.msn,
+                resolution: sequence.resolution(),
+                declared: self.resolution.clone().unwrap(),
+            })
+        }
+    }
+
+    /// Unless separated by `EXT-X-DISCONTINUITY`, consecutive segments should have the same video
+    /// resolution, profile and level
+    fn segment_video(&mut self, segment: &SegmentRequest, href: &HttpRef, params: video::ParameterSets) {
+        let this = SegmentVideo {
+            href: href.clone(),
+            params,
+            discontinuity: segment.discontinuity,
+        };
+        let log = &mut self.log;
+        record_consecutive(&mut self.video, segment.msn, this, |msn, prev, this| {
+            if this.discontinuity {
+                return;
+            }
+            let (p, t) = (&prev.params.sps, &this.params.sps);
+            if this.params.sequence_changed(&prev.params) {
+                log.error(HlsEvent::VideoParametersChanged {
+                    req_id: this.href.clone(),
+                    prev_req_id: prev.href.clone(),
+                    msn,
+                    last_codec: p.codecs.clone(),
+                    this_codec: t.codecs.clone(),
+                    last_resolution: p.resolution(),
+                    this_resolution: t.resolution(),
+                })
+            }
+            for pps_id in this.params.changed_pictures(&prev.params) {
+                log.warning(HlsEvent::VideoPictureParametersChanged {
+                    req_id: this.href.clone(),
+                    prev_req_id: prev.href.clone(),
+                    msn,
+                    pps_id,
+                })
+            }
+        });
+    }
+
+    /// Unless separated by `EXT-X-DISCONTINUITY`, each segment's first presentation timestamp
+    /// should follow on from the end of the previous segment
+    fn segment_timing(&mut self, msn: u64, this: SegmentTiming) {
+        let log = &mut self.log;
+        record_consecutive(&mut self.timing, msn, this, |msn, prev, this| {
+            if !this.discontinuity {
+                if let Some(event) = timestamp_discontinuity(msn, prev, this) {
+                    log.error(event);
+                }
+            }
+        });
+    }
+}
+
+fn timestamp_discontinuity(msn: u64, prev: &SegmentTiming, this: &SegmentTiming) -> Option<HlsEvent> {
+    let jump = ts::timestamp_diff(this.start, prev.end);
+    if jump.unsigned_abs() <= TIMESTAMP_TOLERANCE {
+        return None;
+    }
+    Some(HlsEvent::TsTimestampDiscontinuity {
+        req_id: this.href.clone(),
+        prev_req_id: prev.href.clone(),
+        msn,
+        expected_pts: prev.end,
+        pts: this.start,
+        jump_millis: jump * 1000 / ts::TIMESTAMP_RATE as i64,
+    })
+}
+
+/// The bitrate in bits per second of the given amount of data over the given time
+fn bitrate(bytes: u64, duration: time::Duration) -> u64 {
+    (bytes as f64 * 8.0 / duration.as_secs_f64()) as u64
+}
+
+fn ts_video_codec(ts: &ts::TsSegment) -> Option<video::VideoCodec> {
+    match ts.video_stream()?.stream_type {
+        0x1b | 0xdb => Some(video::VideoCodec::Avc),
+        0x24 => Some(video::VideoCodec::Hevc),
+        _ => None,
+    }
+}
+
+/// The parameter sets carried with the first frame of the segment's video stream
+fn ts_parameter_sets(ts: &ts::TsSegment) -> Option<video::ParameterSets> {
+    video::find_parameter_sets(ts_video_codec(ts)?, video::nal_units(ts.first_video_pes.as_ref()?))?.ok()
+}
+
+/// Whether the segment's video stream starts with a key frame, or `None` if this can't be known
+fn ts_starts_with_keyframe(ts: &ts::TsSegment) -> Option<bool> {
+    video::starts_with_keyframe(ts_video_codec(ts)?, video::nal_units(ts.first_video_pes.as_ref()?))
+}
+
+/// Whether the first sample of the segment's video track is a key frame, or `None` if this can't
+/// be known
+fn fmp4_starts_with_keyframe(init: &mp4::InitSegment, media: &mp4::MediaSegment, data: &[u8]) -> Option<bool> {
+    let track = init.video_track()?;
+    let nals = mp4::first_sample_nal_units(track, media, data)?;
+    video::starts_with_keyframe(track.parameter_sets.as_ref()?.sps.codec, nals)
+}
+
+/// The width and height given by a `RESOLUTION` attribute value
+fn parse_resolution(resolution: &str) -> Option<(u32, u32)> {
+    let mut parts = resolution.splitn(2, 'x');
+    let width = parts.next()?.trim().parse().ok()?;
+    let height = parts.next()?.trim().parse().ok()?;
+    Some((width, height))
+}
+
+/// Segments with a `.ts` extension, or data that looks like a sequence of TS packets
+fn is_transport_stream(url: &reqwest::Url, data: &[u8]) -> bool {
+    url.path().ends_with(".ts") || (data.first() == Some(&0x47) && data.len() % ts::PACKET_SIZE == 0)
+}
+
+/// The `CODECS` sample entry types (the part before the first `.`) corresponding to a PMT
+/// `stream_type`, or `None` for types which don't correspond to a codec (e.g. timed metadata)
+fn codecs_for_stream_type(stream_type: u8) -> Option<&'static [&'static str]> {
+    Some(match stream_type {
+        // H.264, and H.264 with SAMPLE-AES
+        0x1b | 0xdb => &["avc1", "avc3"],
+        0x24 => &["hvc1", "hev1"],
+        // MPEG-1/2 audio, ADTS and LATM AAC, and AAC with SAMPLE-AES
+        0x03 | 0x04 | 0x0f | 0x11 | 0xcf => &["mp4a"],
+        // AC-3 and E-AC-3, either in the clear or with SAMPLE-AES
+        0x81 | 0xc1 => &["ac-3"],
+        0x87 | 0xc2 => &["ec-3"],
+        _ => return None,
+    })
+}
@@ -1,9 +1,19 @@
 use std::fmt;
+use std::time;
 use hls_m3u8::parser::MyMediaSegment;
 
+/// Seed for hashing segment URIs, so that a changed URI can be spotted without keeping the URI
+const URI_HASH_SEED: u64 = 0x6A2F5D3C1E0B4977;
+
+/// The segments that have been seen so far in a media playlist, retained after they are removed
+/// from the top of the playlist until `remove_older_than()` is called
 #[derive(Default, Debug)]
 pub struct Timeline {
     sequences: Vec<Sequence>,
+    /// _media sequence number_ of the last segment appended, even if since removed
+    last_msn: Option<usize>,
+    /// media time at the end of the last segment appended
+    end_time: time::Duration,
 }
 impl Timeline {
     /// remove timeline content older than the given _media sequence number_
@@ -16,23 +26,120 @@ impl Timeline {
         }
     }
 
-    pub fn append_new_segments<'a>(&mut self, segments: impl Iterator<Item=MyMediaSegment<'a>>) {
-        for s in segments {
-            if self.sequences.is_empty() || s.has_discontinuity() {
-                // segment starts a new sequence,
-                let seq = Sequence {
-                    first_msn: s.number(),
-                    last_msn: s.number(),
-                };
-                self.sequences.push(seq)
-            } else {
-                let last = self.sequences.last_mut().unwrap();  // we checked is_empty() above
-                if last.last_msn + 1 != s.number() {
-                    //println!("Unexpected MSN got={} expected={}\n    {:?}", s.number(), last.last_msn + 1, s);
+    /// Appends segments that follow those already in the timeline.  If the first new segment does
+    /// not directly follow the last segment appended previously, the segments in between were never
+    /// seen, and the gap is returned.
+    pub fn append_new_segments(&mut self, segments: impl Iterator<Item=TimelineSegment>) -> Option<MsnGap> {
+        let mut gap = None;
+        for mut s in segments {
+            if let Some(last_msn) = self.last_msn {
+                if last_msn + 1 != s.msn && gap.is_none() {
+                    gap = Some(MsnGap {
+                        last_msn,
+                        next_msn: s.msn,
+                    });
+                }
+            }
+            s.start = self.end_time;
+            self.end_time += s.duration;
+            self.last_msn = Some(s.msn);
+            match self.sequences.last_mut() {
+                Some(last) if !s.discontinuity && last.last_msn < s.msn => {
+                    last.last_msn = s.msn;
+                    last.segments.push(s);
+                },
+                _ => {
+                    // segment starts a new sequence,
+                    self.sequences.push(Sequence {
+                        first_msn: s.msn,
+                        last_msn: s.msn,
+                        segments: vec![s],
+                    })
+                }
+            }
+        }
+        gap
+    }
+
+    /// the _media sequence number_ of the latest segment appended to the timeline
+    pub fn last_msn(&self) -> Option<usize> {
+        self.last_msn
+    }
+
+    pub fn segment(&self, msn: usize) -> Option<&TimelineSegment> {
+        self.sequences.iter()
+            .find(|s| s.first_msn <= msn && msn <= s.last_msn )
+            .and_then(|s| s.segments.iter().find(|seg| seg.msn == msn ) )
+    }
+
+    pub fn segments(&self) -> impl Iterator<Item = &TimelineSegment> {
+        self.sequences.iter().flat_map(|s| s.segments.iter() )
+    }
+
+    /// the segment covering the given media time (see `TimelineSegment::start`)
+    pub fn segment_at_media_time(&self, time: time::Duration) -> Option<&TimelineSegment> {
+        self.segments().find(|s| s.start <= time && time < s.start + s.duration )
+    }
+
+    /// The segment covering the given wall-clock time, according to the `EXT-X-PROGRAM-DATE-TIME`
+    /// of the segment or, failing that, of an earlier segment in the same sequence
+    pub fn segment_at_date_time(&self, time: chrono::DateTime<chrono::FixedOffset>) -> Option<&TimelineSegment> {
+        for seq in &self.sequences {
+            let mut date_time = None;
+            for seg in &seq.segments {
+                if seg.program_date_time.is_some() {
+                    date_time = seg.program_date_time;
+                }
+                if let Some(start) = date_time {
+                    let end = start + chrono::Duration::from_std(seg.duration).ok()?;
+                    if start <= time && time < end {
+                        return Some(seg);
+                    }
+                    date_time = Some(end);
                 }
-                last.last_msn = s.number();
             }
         }
+        None
+    }
+}
+
+/// Segments that were removed from the playlist before they were seen
+#[derive(Debug, PartialEq)]
+pub struct MsnGap {
+    /// the last segment seen before the gap
+    pub last_msn: usize,
+    /// the first segment seen after the gap
+    pub next_msn: usize,
+}
+
+/// The details of a single media segment that are retained in the `Timeline`
+#[derive(Debug, Clone)]
+pub struct TimelineSegment {
+    pub msn: usize,
+    /// the value of the segment's `EXTINF` tag
+    pub duration: time::Duration,
+    /// the media time at which the segment starts; the sum of the durations of the segments
+    /// appended to the timeline before this one (not counting any `MsnGap`)
+    pub start: time::Duration,
+    /// the value of any `EXT-X-PROGRAM-DATE-TIME` tag given for the segment (not a value
+    /// calculated from the tags of earlier segments)
+    pub program_date_time: Option<chrono::DateTime<chrono::FixedOffset>>,
+    pub uri_hash: u64,
+    /// true if the segment is preceded by `EXT-X-DISCONTINUITY`
+    pub discontinuity: bool,
+    pub discontinuity_sequence: u64,
+}
+impl TimelineSegment {
+    pub fn new(s: &MyMediaSegment<'_>, discontinuity_sequence: u64) -> TimelineSegment {
+        TimelineSegment {
+            msn: s.number(),
+            duration: s.duration().duration(),
+            start: time::Duration::from_secs(0),
+            program_date_time: s.program_date_time().map(|p| p.date_time ),
+            uri_hash: wyhash::wyhash(s.uri().as_bytes(), URI_HASH_SEED),
+            discontinuity: s.has_discontinuity(),
+            discontinuity_sequence,
+        }
     }
 }
 
@@ -42,6 +149,7 @@ impl Timeline {
 struct Sequence {
     first_msn: usize,
     last_msn: usize,
+    segments: Vec<TimelineSegment>,
 }
 impl Sequence {
     /// remove timeline content older than the given _media sequence number_
@@ -49,6 +157,7 @@ impl Sequence {
         if self.first_msn < msn {
             self.first_msn = msn;
         }
+        self.segments.retain(|s| s.msn >= msn );
         self.assert_invariants();
     }
 
@@ -65,7 +174,6 @@ impl fmt::Debug for Sequence {
 #[cfg(test)]
 mod test {
     use super::*;
-    use hls_m3u8::parser::MyMediaPlaylist;
 
     #[test]
     fn remove() {
@@ -80,12 +188,46 @@ foo";
         let parser = hls_m3u8::parser::Parser::new(hls_m3u8::parser::Cursor::from(&pl[..]));
         let playlist = parser.parse().unwrap().build().unwrap();
         let mut timeline = Timeline::default();
-        timeline.append_new_segments(playlist.segments());
+        timeline.append_new_segments(playlist.segments().map(|s| TimelineSegment::new(&s, 0) ));
         assert_eq!(timeline.sequences.len(), 1);
         assert_eq!(timeline.sequences[0].first_msn, 0);
         assert_eq!(timeline.sequences[0].last_msn, 1);
         timeline.remove_older_than(1);
         assert_eq!(timeline.sequences.len(), 1);
         assert_eq!(timeline.sequences[0].first_msn, 1);
+        assert!(timeline.segment(0).is_none());
+        assert_eq!(timeline.segment(1).unwrap().duration, std::time::Duration::from_secs(1));
+        assert_eq!(timeline.last_msn(), Some(1));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn gap_and_queries() {
+        let pl =
+            b"#EXTM3U
+#EXT-X-VERSION:3
+#EXT-X-TARGETDURATION:4
+#EXT-X-MEDIA-SEQUENCE:5
+#EXT-X-PROGRAM-DATE-TIME:2021-05-01T08:00:00Z
+#EXTINF:4,
+a
+#EXTINF:4,
+b
+#EXT-X-DISCONTINUITY
+#EXT-X-PROGRAM-DATE-TIME:2021-05-01T09:00:00Z
+#EXTINF:4,
+c";
+        let parser = hls_m3u8::parser::Parser::new(hls_m3u8::parser::Cursor::from(&pl[..]));
+        let playlist = parser.parse().unwrap().build().unwrap();
+        let mut timeline = Timeline::default();
+        let segs: Vec<_> = playlist.segments().map(|s| TimelineSegment::new(&s, 0) ).collect();
+        assert_eq!(timeline.append_new_segments(segs[..1].iter().cloned()), None);
+        // segment 6 is never seen,
+        assert_eq!(timeline.append_new_segments(segs[2..].iter().cloned()), Some(MsnGap { last_msn: 5, next_msn: 7 }));
+        assert_eq!(timeline.sequences.len(), 2);
+        assert_eq!(timeline.segment_at_media_time(std::time::Duration::from_secs(5)).unwrap().msn, 7);
+        let t = chrono::DateTime::parse_from_rfc3339("2021-05-01T09:00:03Z").unwrap();
+        assert_eq!(timeline.segment_at_date_time(t).unwrap().msn, 7);
+        let t = chrono::DateTime::parse_from_rfc3339("2021-05-01T08:00:05Z").unwrap();
+        assert!(timeline.segment_at_date_time(t).is_none());
+    }
+}
@@ -0,0 +1,218 @@
+//! Single-pass checks of complete VOD playlists, which are loaded once rather than polled.
+
+use std::collections::HashSet;
+use crate::event_log::EventSink;
+use crate::http_snoop::HttpRef;
+use hls_m3u8::parser::MyMediaPlaylist;
+use super::{HlsEvent, ManifestRef};
+use super::media_manifest::{self, MediaManifest};
+
+/// Checks a complete VOD playlist in a single pass, rather than watching it change over successive
+/// reloads as for a live playlist.  Each problem found is logged as it is found, and finally a
+/// `VodPlaylistReport` event summarises the whole playlist.
+pub fn check_playlist<L: EventSink<Extra = HlsEvent>>(href: &HttpRef, playlist: &MyMediaPlaylist, manifest: &MediaManifest, body: &str, log: &mut L) {
+    let mut chk = VodCheck {
+        href,
+        manifest,
+        log,
+        problem_count: 0,
+    };
+    chk.end_list(playlist);
+    chk.target_duration(playlist);
+    chk.byte_ranges();
+    chk.version(body);
+    let total_duration_millis = manifest.segments.iter()
+        .filter_map(|s| s.duration )
+        .map(|d| (d * 1000.0).round() as u64 )
+        .sum();
+    let problem_count = chk.problem_count;
+    log.info(HlsEvent::VodPlaylistReport {
+        req_id: href.clone(),
+        segment_count: manifest.segments.len(),
+        total_duration_millis,
+        target_duration_millis: playlist.target_duration.as_millis() as u64,
+        has_end_list: playlist.has_end_list,
+        problem_count,
+    });
+}
+
+struct VodCheck<'a, L: EventSink<Extra = HlsEvent>> {
+    href: &'a HttpRef,
+    manifest: &'a MediaManifest,
+    log: &'a mut L,
+    problem_count: usize,
+}
+impl<'a, L: EventSink<Extra = HlsEvent>> VodCheck<'a, L> {
+    fn error(&mut self, event: HlsEvent) {
+        self.problem_count += 1;
+        self.log.error(event)
+    }
+
+    fn warning(&mut self, event: HlsEvent) {
+        self.problem_count += 1;
+        self.log.warning(event)
+    }
+
+    /// refers to the given zero-based line index of the playlist
+    fn at(&self, line: Option<usize>) -> ManifestRef {
+        ManifestRef {
+            req_id: self.href.clone(),
+            // lines of a rebuilt playlist don't correspond to the response body
+            line: if self.manifest.rebuilt { None } else { line.map(|l| l + 1 ) },
+        }
+    }
+
+    fn end_list(&mut self, playlist: &MyMediaPlaylist) {
+        if !playlist.has_end_list {
+            self.error(HlsEvent::VodMissingEndList {
+                req_id: self.href.clone(),
+            })
+        }
+    }
+
+    /// "The EXTINF duration of each Media Segment in the Playlist file, when rounded to the nearest
+    /// integer, MUST be less than or equal to the target duration"
+    fn target_duration(&mut self, playlist: &MyMediaPlaylist) {
+        let target_duration = playlist.target_duration.as_secs();
+        let manifest = self.manifest;
+        for seg in &manifest.segments {
+            if let Some(duration) = seg.duration {
+                if duration.round() as u64 > target_duration {
+                    let at = self.at(Some(seg.tag_line("EXTINF").unwrap_or(seg.line)));
+                    self.error(HlsEvent::ExtinfExceedsTargetDuration {
+                        at,
+                        msn: seg.msn,
+                        duration_millis: (duration * 1000.0).round() as u64,
+                        target_duration_millis: target_duration * 1000,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Sub-ranges of a resource given by `EXT-X-BYTERANGE` are expected to follow on from the
+    /// previous segment's sub-range of the same resource, and a sub-range without an explicit
+    /// offset is only valid if the previous segment was a sub-range of the same resource.
+    fn byte_ranges(&mut self) {
+        // the URI of the previous segment, and the offset following its sub-range
+        let mut prev: Option<(&str, u64)> = None;
+        let manifest = self.manifest;
+        for seg in &manifest.segments {
+            let range = match seg.byte_range {
+                Some(range) => range,
+                None => {
+                    prev = None;
+                    continue;
+                }
+            };
+            let line = seg.tag_line("EXT-X-BYTERANGE");
+            let expected_offset = match prev {
+                Some((uri, end)) if uri == seg.uri => Some(end),
+                _ => None,
+            };
+            let offset = match (range.offset, expected_offset) {
+                (Some(offset), Some(expected_offset)) => {
+                    if offset != expected_offset {
+                        let at = self.at(line);
+                        self.warning(HlsEvent::ByteRangeNotContiguous {
+                            at,
+                            msn: seg.msn,
+                            expected_offset,
+                            offset,
+                        })
+                    }
+                    offset
+                },
+                (Some(offset), None) => offset,
+                (None, Some(expected_offset)) => expected_offset,
+                (None, None) => {
+                    let at = self.at(line);
+                    self.error(HlsEvent::ByteRangeOffsetMissing {
+                        at,
+                        msn: seg.msn,
+                    });
+                    // assume the sub-range is from the start of the resource, and carry on
+                    0
+                },
+            };
+            match offset.checked_add(range.length) {
+                Some(end) => prev = Some((&seg.uri, end)),
+                None => {
+                    let at = self.at(line);
+                    self.error(HlsEvent::ByteRangeInvalid {
+                        at,
+                        msn: seg.msn,
+                        offset,
+                        length: range.length,
+                    });
+                    prev = None;
+                },
+            }
+        }
+    }
+
+    /// Each feature used by the playlist that needs a later protocol version than declared by
+    /// `EXT-X-VERSION` (or version 1, if absent) is reported once, at its first use
+    fn version(&mut self, body: &str) {
+        let version = self.manifest.version.unwrap_or(1);
+        let i_frames_only = self.manifest.tag_line("EXT-X-I-FRAMES-ONLY").is_some();
+        let mut reported = HashSet::new();
+        for (number, line) in body.lines().enumerate() {
+            for (feature, required_version) in required_versions(line.trim(), i_frames_only) {
+                if required_version > version && reported.insert(feature) {
+                    let at = self.at(Some(number));
+                    self.error(HlsEvent::VersionTooLow {
+                        at,
+                        version,
+                        required_version,
+                        feature,
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// The protocol features used by the given playlist line, with the `EXT-X-VERSION` each requires
+fn required_versions(line: &str, i_frames_only: bool) -> Vec<(&'static str, u32)> {
+    let mut result = vec![];
+    if let Some(value) = tag_value(line, "EXTINF") {
+        let duration = value.split(',').next().unwrap_or("");
+        if duration.contains('.') {
+            result.push(("EXTINF floating-point duration", 3));
+        }
+    } else if let Some(value) = tag_value(line, "EXT-X-KEY") {
+        if let Ok(attrs) = media_manifest::attributes(value) {
+            for (name, _) in attrs {
+                match name {
+                    "IV" => result.push(("EXT-X-KEY IV attribute", 2)),
+                    "KEYFORMAT" => result.push(("EXT-X-KEY KEYFORMAT attribute", 5)),
+                    "KEYFORMATVERSIONS" => result.push(("EXT-X-KEY KEYFORMATVERSIONS attribute", 5)),
+                    _ => (),
+                }
+            }
+        }
+    } else if tag_value(line, "EXT-X-BYTERANGE").is_some() {
+        result.push(("EXT-X-BYTERANGE", 4));
+    } else if line == "#EXT-X-I-FRAMES-ONLY" {
+        result.push(("EXT-X-I-FRAMES-ONLY", 4));
+    } else if tag_value(line, "EXT-X-MAP").is_some() {
+        if i_frames_only {
+            result.push(("EXT-X-MAP", 5));
+        } else {
+            result.push(("EXT-X-MAP without EXT-X-I-FRAMES-ONLY", 6));
+        }
+    } else if tag_value(line, "EXT-X-DEFINE").is_some() {
+        result.push(("EXT-X-DEFINE", 8));
+    }
+    result
+}
+
+/// the text following `#<name>:` if the line is the given tag
+fn tag_value<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    if line.starts_with('#') && line[1..].starts_with(name) && line[1 + name.len()..].starts_with(':') {
+        Some(&line[name.len() + 2..])
+    } else {
+        None
+    }
+}
@@ -2,6 +2,10 @@ pub mod hls;
 pub mod http_snoop;
 pub mod event_log;
 pub mod metric;
+pub mod mp4;
+pub mod scte35;
+pub mod ts;
+pub mod video;
 
 #[cfg(test)]
 mod tests {
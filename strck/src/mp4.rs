@@ -0,0 +1,510 @@
+//! Minimal ISO Base Media File Format parser, extracting what's needed to check fragmented MP4
+//! segments: the tracks described by an initialisation segment, and the decode time and sample
+//! durations of each track fragment of a media segment.
+
+use crate::video;
+use std::convert::TryFrom;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mp4Error {
+    /// the box with the given type extends past the end of its container
+    Truncated(String),
+    /// a required box was not found
+    MissingBox(&'static str),
+    /// the given number of bytes follow the box that was expected to end the data
+    TrailingData(usize),
+    /// the sum of the values of the named field exceeds the largest possible value
+    Overflow(&'static str),
+}
+
+type FourCC = [u8; 4];
+
+fn fourcc_str(fourcc: &FourCC) -> String {
+    String::from_utf8_lossy(fourcc).into_owned()
+}
+
+/// A box within some data, with `data` holding the box payload following the header
+struct Mp4Box<'a> {
+    box_type: FourCC,
+    header_size: usize,
+    data: &'a [u8],
+}
+impl<'a> Mp4Box<'a> {
+    /// the position of the start of the box within `container`, which must hold the box
+    fn offset_in(&self, container: &[u8]) -> usize {
+        self.data.as_ptr() as usize - container.as_ptr() as usize - self.header_size
+    }
+
+    /// the payload following the version and flags fields of a 'full box'
+    fn full_box(&self) -> Result<(u8, u32, &'a [u8]), Mp4Error> {
+        let header = self.data.get(..4).ok_or_else(|| Mp4Error::Truncated(fourcc_str(&self.box_type)) )?;
+        let flags = (u32::from(header[1]) << 16) | (u32::from(header[2]) << 8) | u32::from(header[3]);
+        Ok((header[0], flags, &self.data[4..]))
+    }
+
+    fn child(&self, box_type: &'static str) -> Result<Mp4Box<'a>, Mp4Error> {
+        boxes(self.data)
+            .find(|b| b.as_ref().map(|b| b.box_type == box_type.as_bytes() ).unwrap_or(true) )
+            .unwrap_or(Err(Mp4Error::MissingBox(box_type)))
+    }
+
+    fn children(&self, box_type: &'static str) -> impl Iterator<Item = Result<Mp4Box<'a>, Mp4Error>> {
+        boxes(self.data)
+            .filter(move |b| b.as_ref().map(|b| b.box_type == box_type.as_bytes() ).unwrap_or(true) )
+    }
+}
+
+/// The sequence of boxes in the given data, ending with an error if a box header is not valid
+fn boxes(mut data: &[u8]) -> impl Iterator<Item = Result<Mp4Box<'_>, Mp4Error>> {
+    std::iter::from_fn(move || {
+        if data.is_empty() {
+            return None;
+        }
+        let result = next_box(data);
+        match result {
+            Ok((b, rest)) => {
+                data = rest;
+                Some(Ok(b))
+            },
+            Err(e) => {
+                data = &[];
+                Some(Err(e))
+            },
+        }
+    })
+}
+
+fn next_box(data: &[u8]) -> Result<(Mp4Box<'_>, &[u8]), Mp4Error> {
+    let truncated = || Mp4Error::Truncated(fourcc_str(&box_type_of(data)));
+    if data.len() < 8 {
+        return Err(truncated());
+    }
+    let box_type = box_type_of(data);
+    let (size, header_size) = match u32_at(data, 0)? {
+        0 => (data.len() as u64, 8),
+        1 => (u64_at(data, 8)?, 16),
+        size => (u64::from(size), 8),
+    };
+    if size < header_size as u64 || size > data.len() as u64 {
+        return Err(truncated());
+    }
+    let size = size as usize;
+    Ok((Mp4Box { box_type, header_size, data: &data[header_size..size] }, &data[size..]))
+}
+
+fn box_type_of(data: &[u8]) -> FourCC {
+    let mut box_type = [b'?'; 4];
+    for (i, b) in data.iter().skip(4).take(4).enumerate() {
+        box_type[i] = *b;
+    }
+    box_type
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, Mp4Error> {
+    let b = data.get(offset..offset + 4).ok_or_else(|| Mp4Error::Truncated(String::new()) )?;
+    Ok((u32::from(b[0]) << 24) | (u32::from(b[1]) << 16) | (u32::from(b[2]) << 8) | u32::from(b[3]))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Result<u64, Mp4Error> {
+    Ok((u64::from(u32_at(data, offset)?) << 32) | u64::from(u32_at(data, offset + 4)?))
+}
+
+/// A track described by the `moov` box of an initialisation segment
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub track_id: u32,
+    /// the `mdhd` timescale, in ticks per second
+    pub timescale: u32,
+    /// the `hdlr` handler type, e.g. `vide` or `soun`
+    pub handler_type: String,
+    /// the type of the first sample entry in `stsd`, e.g. `avc1` or `mp4a`
+    pub sample_entry: Option<String>,
+    /// the RFC 6381 codecs parameter for the sample entry, e.g. `avc1.64001F`, where the sample
+    /// entry's decoder configuration is understood, or otherwise just the sample entry type
+    pub codec: Option<String>,
+    /// the parameter sets of an `avcC` or `hvcC` decoder configuration, if present
+    pub parameter_sets: Option<video::ParameterSets>,
+    /// the size of the length field preceding each NAL unit of a sample, given by an `avcC` or
+    /// `hvcC` decoder configuration
+    pub nal_length_size: Option<usize>,
+    /// the `trex` default duration of the samples of each fragment
+    pub default_sample_duration: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitSegment {
+    pub tracks: Vec<Track>,
+}
+impl InitSegment {
+    pub fn track(&self, track_id: u32) -> Option<&Track> {
+        self.tracks.iter().find(|t| t.track_id == track_id )
+    }
+
+    /// The first track with an H.264 or HEVC decoder configuration
+    pub fn video_track(&self) -> Option<&Track> {
+        self.tracks.iter().find(|t| t.parameter_sets.is_some() )
+    }
+}
+
+pub fn parse_init(data: &[u8]) -> Result<InitSegment, Mp4Error> {
+    let moov = boxes(data)
+        .find(|b| b.as_ref().map(|b| &b.box_type == b"moov" ).unwrap_or(true) )
+        .unwrap_or(Err(Mp4Error::MissingBox("moov")))?;
+    let mut trex_defaults = vec![];
+    if let Ok(mvex) = moov.child("mvex") {
+        for trex in mvex.children("trex") {
+            let (_, _, data) = trex?.full_box()?;
+            trex_defaults.push((u32_at(data, 0)?, u32_at(data, 8)?));
+        }
+    }
+    let mut tracks = vec![];
+    for trak in moov.children("trak") {
+        let trak = trak?;
+        let (version, _, tkhd) = trak.child("tkhd")?.full_box()?;
+        let track_id = u32_at(tkhd, if version == 1 { 16 } else { 8 })?;
+        let mdia = trak.child("mdia")?;
+        let (version, _, mdhd) = mdia.child("mdhd")?.full_box()?;
+        let timescale = u32_at(mdhd, if version == 1 { 16 } else { 8 })?;
+        let (_, _, hdlr) = mdia.child("hdlr")?.full_box()?;
+        // the handler_type follows a 4 byte pre_defined field
+        let handler_type = String::from_utf8_lossy(hdlr.get(4..8).unwrap_or(b"????")).into_owned();
+        let entry = mdia.child("minf")
+            .and_then(|minf| minf.child("stbl") )
+            .and_then(|stbl| stbl.child("stsd") )
+            .and_then(|stsd| stsd.full_box() )
+            .ok()
+            .and_then(|(_, _, stsd)| stsd.get(4..) )
+            .and_then(|entries| boxes(entries).next() )
+            .and_then(|entry| entry.ok() );
+        let sample_entry = entry.as_ref().map(|e| fourcc_str(&e.box_type) );
+        let parameter_sets = entry.as_ref().and_then(|e| parameter_sets(e) );
+        let nal_length_size = entry.as_ref().and_then(|e| nal_length_size(e) );
+        let codec = entry.as_ref().map(|e| codec(e, parameter_sets.as_ref().map(|p| &p.sps )) );
+        tracks.push(Track {
+            track_id,
+            timescale,
+            handler_type,
+            sample_entry,
+            codec,
+            parameter_sets,
+            nal_length_size,
+            default_sample_duration: trex_defaults.iter().find(|(id, _)| *id == track_id ).map(|(_, d)| *d ),
+        });
+    }
+    Ok(InitSegment { tracks })
+}
+
+/// Size of the fields of a VisualSampleEntry preceding any child boxes
+const VISUAL_SAMPLE_ENTRY_SIZE: usize = 78;
+
+fn codec(entry: &Mp4Box<'_>, sequence: Option<&video::SequenceInfo>) -> String {
+    let sample_entry = fourcc_str(&entry.box_type);
+    if let Some(seq) = sequence.filter(|s| s.codec == video::VideoCodec::Hevc ) {
+        // the sequence parameter set gives the profile, tier and level, and the constraint flags
+        return format!("{}{}", sample_entry, &seq.codecs["hvc1".len()..]);
+    }
+    if &entry.box_type == b"avc1" || &entry.box_type == b"avc3" {
+        // configurationVersion is followed by the profile, constraint flags and level
+        if let Some(config) = visual_child(entry, b"avcC").as_ref().and_then(|b| b.data.get(1..4) ) {
+            return format!("{}.{:02X}{:02X}{:02X}", sample_entry, config[0], config[1], config[2]);
+        }
+    }
+    sample_entry
+}
+
+fn parameter_sets(entry: &Mp4Box<'_>) -> Option<video::ParameterSets> {
+    match &entry.box_type {
+        b"avc1" | b"avc3" => video::parse_avc_decoder_config(visual_child(entry, b"avcC")?.data).ok(),
+        b"hvc1" | b"hev1" => video::parse_hevc_decoder_config(visual_child(entry, b"hvcC")?.data).ok(),
+        _ => None,
+    }
+}
+
+fn nal_length_size(entry: &Mp4Box<'_>) -> Option<usize> {
+    // the lengthSizeMinusOne field is in the low bits of the byte at the given position
+    let length_size_minus_one = match &entry.box_type {
+        b"avc1" | b"avc3" => *visual_child(entry, b"avcC")?.data.get(4)?,
+        b"hvc1" | b"hev1" => *visual_child(entry, b"hvcC")?.data.get(21)?,
+        _ => return None,
+    };
+    Some(usize::from(length_size_minus_one & 0b11) + 1)
+}
+
+/// The child box of the given type following the fields of a VisualSampleEntry
+fn visual_child<'a>(entry: &Mp4Box<'a>, box_type: &FourCC) -> Option<Mp4Box<'a>> {
+    entry.data.get(VISUAL_SAMPLE_ENTRY_SIZE..)
+        .and_then(|children| {
+            boxes(children).filter_map(|b| b.ok() ).find(|b| &b.box_type == box_type )
+        })
+}
+
+/// The timing of the samples of one track within a media segment, combining all the segment's
+/// `traf` boxes for the track
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackFragment {
+    pub track_id: u32,
+    /// the `tfdt` baseMediaDecodeTime of the first fragment, in the track's timescale
+    pub base_media_decode_time: Option<u64>,
+    pub sample_count: u32,
+    /// the sum of the durations of the samples, in the track's timescale
+    pub duration: u64,
+    /// the position of the data of the first sample within the segment, if given by the `trun`
+    pub first_sample_offset: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MediaSegment {
+    pub fragments: Vec<TrackFragment>,
+}
+
+/// Parses the `moof` boxes of a media segment, taking default sample durations from the given
+/// initialisation segment where the fragment doesn't specify them
+pub fn parse_media(data: &[u8], init: &InitSegment) -> Result<MediaSegment, Mp4Error> {
+    let mut segment = MediaSegment::default();
+    let mut moof_count = 0;
+    for b in boxes(data) {
+        let b = b?;
+        if &b.box_type != b"moof" {
+            continue;
+        }
+        moof_count += 1;
+        let moof_offset = b.offset_in(data);
+        for traf in b.children("traf") {
+            let traf = traf?;
+            let (_, tfhd_flags, tfhd) = traf.child("tfhd")?.full_box()?;
+            let track_id = u32_at(tfhd, 0)?;
+            // data offsets are relative to the base_data_offset if given, or else to the moof
+            let base_data_offset = if tfhd_flags & 0x01 != 0 { u64_at(tfhd, 4)? as usize } else { moof_offset };
+            // skip base_data_offset and sample_description_index, if present
+            let mut offset = 4;
+            if tfhd_flags & 0x01 != 0 { offset += 8; }
+            if tfhd_flags & 0x02 != 0 { offset += 4; }
+            let default_duration = if tfhd_flags & 0x08 != 0 {
+                Some(u32_at(tfhd, offset)?)
+            } else {
+                init.track(track_id).and_then(|t| t.default_sample_duration )
+            };
+            let base_media_decode_time = match traf.child("tfdt") {
+                Ok(tfdt) => {
+                    let (version, _, tfdt) = tfdt.full_box()?;
+                    Some(if version == 1 { u64_at(tfdt, 0)? } else { u64::from(u32_at(tfdt, 0)?) })
+                },
+                Err(_) => None,
+            };
+            let mut sample_count = 0u32;
+            let mut duration = 0u64;
+            let mut first_sample_offset = None;
+            for trun in traf.children("trun") {
+                let trun = parse_trun(&trun?, default_duration)?;
+                if sample_count == 0 {
+                    first_sample_offset = trun.data_offset
+                        .and_then(|o| (base_data_offset as i64).checked_add(i64::from(o)) )
+                        .and_then(|o| usize::try_from(o).ok() );
+                }
+                sample_count = sample_count.checked_add(trun.sample_count).ok_or(Mp4Error::Overflow("sample_count"))?;
+                duration = duration.checked_add(trun.duration).ok_or(Mp4Error::Overflow("sample_duration"))?;
+            }
+            match segment.fragments.iter_mut().find(|f| f.track_id == track_id ) {
+                Some(f) => {
+                    f.sample_count = f.sample_count.checked_add(sample_count).ok_or(Mp4Error::Overflow("sample_count"))?;
+                    f.duration = f.duration.checked_add(duration).ok_or(Mp4Error::Overflow("sample_duration"))?;
+                },
+                None => segment.fragments.push(TrackFragment {
+                    track_id,
+                    base_media_decode_time,
+                    sample_count,
+                    duration,
+                    first_sample_offset,
+                }),
+            }
+        }
+    }
+    if moof_count == 0 {
+        return Err(Mp4Error::MissingBox("moof"));
+    }
+    Ok(segment)
+}
+
+struct TrackRun {
+    sample_count: u32,
+    /// the sum of the durations of the samples
+    duration: u64,
+    data_offset: Option<i32>,
+}
+
+fn parse_trun(trun: &Mp4Box<'_>, default_duration: Option<u32>) -> Result<TrackRun, Mp4Error> {
+    let (_, flags, data) = trun.full_box()?;
+    let sample_count = u32_at(data, 0)?;
+    let mut offset = 4;
+    let mut data_offset = None;
+    if flags & 0x001 != 0 {
+        data_offset = Some(u32_at(data, offset)? as i32);
+        offset += 4;
+    }
+    if flags & 0x004 != 0 { offset += 4; }  // first_sample_flags
+    let duration_present = flags & 0x100 != 0;
+    let sample_size = 4 * [0x100, 0x200, 0x400, 0x800].iter().filter(|f| flags & *f != 0 ).count();
+    let duration = if duration_present {
+        let mut duration = 0u64;
+        for i in 0..sample_count as usize {
+            duration = duration.checked_add(u64::from(u32_at(data, offset + i * sample_size)?)).ok_or(Mp4Error::Overflow("sample_duration"))?;
+        }
+        duration
+    } else {
+        u64::from(default_duration.unwrap_or(0)) * u64::from(sample_count)
+    };
+    Ok(TrackRun { sample_count, duration, data_offset })
+}
+
+/// The NAL units of the first sample of the given track within a media segment
+pub fn first_sample_nal_units<'a>(track: &Track, media: &MediaSegment, data: &'a [u8]) -> Option<impl Iterator<Item = &'a [u8]>> {
+    let frag = media.fragments.iter().find(|f| f.track_id == track.track_id )?;
+    let sample = data.get(frag.first_sample_offset?..)?;
+    Some(video::length_prefixed_nal_units(sample, track.nal_length_size?))
+}
+
+/// The parameter sets in effect for the video track of a media segment: those carried in-band
+/// with the first sample if there are any (as for `avc3` and `hev1` sample entries), or otherwise
+/// those of the track's decoder configuration
+pub fn video_parameter_sets(init: &InitSegment, media: &MediaSegment, data: &[u8]) -> Option<video::ParameterSets> {
+    let track = init.video_track()?;
+    let config = track.parameter_sets.as_ref()?;
+    first_sample_nal_units(track, media, data)
+        .and_then(|nals| video::find_parameter_sets(config.sps.codec, nals) )
+        .and_then(|p| p.ok() )
+        .or_else(|| Some(config.clone()) )
+}
+
+/// A `pssh` box, identifying the DRM system whose data it carries
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProtectionSystemHeader {
+    pub system_id: [u8; 16],
+    /// the `KID`s listed by a version 1 box (version 0 boxes don't list any)
+    pub key_ids: Vec<[u8; 16]>,
+    pub data: Vec<u8>,
+}
+
+/// true if the data begins with the header of a `pssh` box
+pub fn is_pssh(data: &[u8]) -> bool {
+    data.len() >= 8 && &box_type_of(data) == b"pssh"
+}
+
+/// Parses the `pssh` box held in the given data, which must hold nothing else
+pub fn parse_pssh(data: &[u8]) -> Result<ProtectionSystemHeader, Mp4Error> {
+    let (pssh, rest) = next_box(data)?;
+    if &pssh.box_type != b"pssh" {
+        return Err(Mp4Error::MissingBox("pssh"));
+    }
+    if !rest.is_empty() {
+        return Err(Mp4Error::TrailingData(rest.len()));
+    }
+    let truncated = || Mp4Error::Truncated("pssh".to_string());
+    let (version, _flags, payload) = pssh.full_box()?;
+    let mut system_id = [0; 16];
+    system_id.copy_from_slice(payload.get(..16).ok_or_else(truncated)?);
+    let mut pos = 16;
+    let mut key_ids = vec![];
+    if version > 0 {
+        let count = u32_at(payload, pos)?;
+        pos += 4;
+        for _ in 0..count {
+            let mut kid = [0; 16];
+            kid.copy_from_slice(payload.get(pos..pos + 16).ok_or_else(truncated)?);
+            key_ids.push(kid);
+            pos += 16;
+        }
+    }
+    let data_size = u32_at(payload, pos)? as usize;
+    pos += 4;
+    let data = payload.get(pos..pos + data_size).ok_or_else(truncated)?.to_vec();
+    Ok(ProtectionSystemHeader { system_id, key_ids, data })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mp4_box(box_type: &str, payload: &[u8]) -> Vec<u8> {
+        let mut result = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        result.extend_from_slice(box_type.as_bytes());
+        result.extend_from_slice(payload);
+        result
+    }
+
+    fn full_box(box_type: &str, version: u8, flags: u32, payload: &[u8]) -> Vec<u8> {
+        let mut data = flags.to_be_bytes().to_vec();
+        data[0] = version;
+        data.extend_from_slice(payload);
+        mp4_box(box_type, &data)
+    }
+
+    fn init() -> Vec<u8> {
+        let tkhd = full_box("tkhd", 0, 0, &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0]);
+        let mdhd = full_box("mdhd", 0, 0, &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x3a, 0x98, 0, 0, 0, 0]);
+        let hdlr = full_box("hdlr", 0, 0, b"\0\0\0\0vide");
+        let avcc = mp4_box("avcC", &[1, 0x64, 0x00, 0x1f, 0xff]);
+        let avc1 = mp4_box("avc1", &[&[0; VISUAL_SAMPLE_ENTRY_SIZE][..], &avcc].concat());
+        let stsd = full_box("stsd", 0, 0, &[&[0, 0, 0, 1][..], &avc1].concat());
+        let stbl = mp4_box("stbl", &stsd);
+        let minf = mp4_box("minf", &stbl);
+        let mdia = mp4_box("mdia", &[mdhd, hdlr, minf].concat());
+        let trak = mp4_box("trak", &[tkhd, mdia].concat());
+        let trex = full_box("trex", 0, 0, &[0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0x02, 0x00, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let mvex = mp4_box("mvex", &trex);
+        [mp4_box("ftyp", b"iso6"), mp4_box("moov", &[trak, mvex].concat())].concat()
+    }
+
+    #[test]
+    fn init_segment() {
+        let init = parse_init(&init()).unwrap();
+        assert_eq!(init.tracks, vec![Track {
+            track_id: 1,
+            timescale: 15000,
+            handler_type: "vide".to_string(),
+            sample_entry: Some("avc1".to_string()),
+            codec: Some("avc1.64001F".to_string()),
+            parameter_sets: None,
+            nal_length_size: Some(4),
+            default_sample_duration: Some(512),
+        }]);
+    }
+
+    #[test]
+    fn media_segment() {
+        let init = parse_init(&init()).unwrap();
+        let tfhd = full_box("tfhd", 0, 0, &[0, 0, 0, 1]);
+        let tfdt = full_box("tfdt", 1, 0, &[0, 0, 0, 1, 0, 0, 0, 0]);
+        // two samples with durations given, and three using the trex default
+        let trun1 = full_box("trun", 0, 0x301, &[0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 9, 0, 0, 1, 0, 0, 0, 0, 9]);
+        let trun2 = full_box("trun", 0, 0, &[0, 0, 0, 3]);
+        let traf = mp4_box("traf", &[tfhd, tfdt, trun1, trun2].concat());
+        let data = [mp4_box("moof", &traf), mp4_box("mdat", &[])].concat();
+        let seg = parse_media(&data, &init).unwrap();
+        assert_eq!(seg.fragments, vec![TrackFragment {
+            track_id: 1,
+            base_media_decode_time: Some(1 << 32),
+            sample_count: 5,
+            duration: 2 * 256 + 3 * 512,
+            first_sample_offset: Some(0),
+        }]);
+        assert_eq!(parse_media(&mp4_box("mdat", &[]), &init), Err(Mp4Error::MissingBox("moof")));
+        // the sample counts of the two runs add up to more than a u32 can hold
+        let tfhd = full_box("tfhd", 0, 0, &[0, 0, 0, 1]);
+        let trun = full_box("trun", 0, 0, &[0xff, 0xff, 0xff, 0xff]);
+        let traf = mp4_box("traf", &[tfhd, trun.clone(), trun].concat());
+        assert_eq!(parse_media(&mp4_box("moof", &traf), &init), Err(Mp4Error::Overflow("sample_count")));
+    }
+
+    #[test]
+    fn pssh() {
+        let system_id = [0xed, 0xef, 0x8b, 0xa9, 0x79, 0xd6, 0x4a, 0xce, 0xa3, 0xc8, 0x27, 0xdc, 0xd5, 0x1d, 0x21, 0xed];
+        let v0 = full_box("pssh", 0, 0, &[&system_id[..], &[0, 0, 0, 2, 8, 1]].concat());
+        assert!(is_pssh(&v0));
+        assert_eq!(parse_pssh(&v0), Ok(ProtectionSystemHeader { system_id, key_ids: vec![], data: vec![8, 1] }));
+        let v1 = full_box("pssh", 1, 0, &[&system_id[..], &[0, 0, 0, 1], &[7; 16], &[0, 0, 0, 0]].concat());
+        assert_eq!(parse_pssh(&v1).unwrap().key_ids, vec![[7; 16]]);
+        assert_eq!(parse_pssh(&v1[..v1.len() - 1]), Err(Mp4Error::Truncated("pssh".to_string())));
+        assert_eq!(parse_pssh(&mp4_box("free", &[])), Err(Mp4Error::MissingBox("pssh")));
+        assert!(!is_pssh(b"<WRMHEADER"));
+    }
+}
@@ -0,0 +1,427 @@
+//! Decoding of the SCTE-35 `splice_info_section` structures carried by the `SCTE35-CMD`,
+//! `SCTE35-OUT` and `SCTE35-IN` attributes of `EXT-X-DATERANGE` tags, covering the `splice_insert`
+//! and `time_signal` commands and segmentation descriptors.  The decoded structures are included
+//! in the events reporting problems with them.
+
+use serde_derive::Serialize;
+
+/// The rate of the PTS and duration values, in ticks per second
+pub const TIMESTAMP_RATE: u64 = 90_000;
+
+/// The `table_id` of a `splice_info_section`
+const TABLE_ID: u8 = 0xfc;
+
+/// The `splice_command_length` of sections from encoders that don't give the length
+const UNKNOWN_COMMAND_LENGTH: usize = 0xfff;
+
+const SEGMENTATION_DESCRIPTOR_TAG: u8 = 0x02;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scte35Error {
+    /// the attribute value is not a hexadecimal-sequence
+    BadHexadecimal,
+    /// the data ended before all the fields of the section were read
+    Truncated,
+    /// the data doesn't start with the `table_id` of a `splice_info_section`
+    BadTableId(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SpliceInfoSection {
+    pub protocol_version: u8,
+    pub encrypted_packet: bool,
+    pub pts_adjustment: u64,
+    pub tier: u16,
+    pub splice_command: SpliceCommand,
+    pub descriptors: Vec<SpliceDescriptor>,
+    pub crc_32: u32,
+    /// false if `crc_32` doesn't match the CRC calculated over the section
+    pub crc_valid: bool,
+}
+impl SpliceInfoSection {
+    /// the `splice_event_id` of a `splice_insert`, or otherwise the `segmentation_event_id` of the
+    /// first segmentation descriptor, ignoring cancelled events
+    pub fn event_id(&self) -> Option<u32> {
+        match self.splice_command {
+            SpliceCommand::SpliceInsert(ref insert) => {
+                if insert.splice_event_cancel_indicator { None } else { Some(insert.splice_event_id) }
+            },
+            _ => self.segmentation_descriptors()
+                .find(|d| !d.segmentation_event_cancel_indicator )
+                .map(|d| d.segmentation_event_id ),
+        }
+    }
+
+    /// the `break_duration` of a `splice_insert`, or otherwise the `segmentation_duration` of the
+    /// first segmentation descriptor giving one, in 90kHz ticks
+    pub fn duration(&self) -> Option<u64> {
+        match self.splice_command {
+            SpliceCommand::SpliceInsert(ref insert) => insert.break_duration.as_ref().map(|b| b.duration ),
+            _ => self.segmentation_descriptors().find_map(|d| d.segmentation_duration ),
+        }
+    }
+
+    /// the `out_of_network_indicator` of a `splice_insert`
+    pub fn out_of_network(&self) -> Option<bool> {
+        match self.splice_command {
+            SpliceCommand::SpliceInsert(ref insert) if !insert.splice_event_cancel_indicator => Some(insert.out_of_network_indicator),
+            _ => None,
+        }
+    }
+
+    fn segmentation_descriptors(&self) -> impl Iterator<Item = &SegmentationDescriptor> {
+        self.descriptors.iter().filter_map(|d| match d {
+            SpliceDescriptor::Segmentation(s) => Some(s),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type")]
+pub enum SpliceCommand {
+    SpliceNull,
+    SpliceSchedule,
+    SpliceInsert(SpliceInsert),
+    TimeSignal { pts_time: Option<u64> },
+    BandwidthReservation,
+    PrivateCommand { identifier: u32 },
+    /// the command of an encrypted section, which is not decoded
+    Encrypted { splice_command_type: u8 },
+    Unknown { splice_command_type: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SpliceInsert {
+    pub splice_event_id: u32,
+    pub splice_event_cancel_indicator: bool,
+    pub out_of_network_indicator: bool,
+    pub splice_immediate_flag: bool,
+    /// the splice time of a program splice, if not immediate
+    pub pts_time: Option<u64>,
+    pub break_duration: Option<BreakDuration>,
+    pub unique_program_id: u16,
+    pub avail_num: u8,
+    pub avails_expected: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BreakDuration {
+    pub auto_return: bool,
+    pub duration: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "type")]
+pub enum SpliceDescriptor {
+    Segmentation(SegmentationDescriptor),
+    Other { splice_descriptor_tag: u8, identifier: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SegmentationDescriptor {
+    pub segmentation_event_id: u32,
+    pub segmentation_event_cancel_indicator: bool,
+    pub segmentation_duration: Option<u64>,
+    pub segmentation_upid_type: u8,
+    /// the UPID bytes, in hexadecimal
+    pub segmentation_upid: String,
+    pub segmentation_type_id: u8,
+    pub segment_num: u8,
+    pub segments_expected: u8,
+}
+
+/// Reads the byte-aligned fields of a section
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], Scte35Error> {
+        let b = self.data.get(self.pos..self.pos + count).ok_or(Scte35Error::Truncated)?;
+        self.pos += count;
+        Ok(b)
+    }
+
+    fn u8(&mut self) -> Result<u8, Scte35Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// a big-endian value of the given number of bytes
+    fn uint(&mut self, count: usize) -> Result<u64, Scte35Error> {
+        Ok(self.bytes(count)?.iter().fold(0, |v, b| (v << 8) | u64::from(*b) ))
+    }
+
+    /// a 33 bit timestamp, following 7 bits that are returned separately
+    fn timestamp(&mut self) -> Result<(u8, u64), Scte35Error> {
+        let value = self.uint(5)?;
+        Ok(((value >> 33) as u8, value & 0x1_ffff_ffff))
+    }
+
+    /// a `splice_time()` structure, giving the `pts_time` if `time_specified_flag` is set
+    fn splice_time(&mut self) -> Result<Option<u64>, Scte35Error> {
+        let flags = self.data.get(self.pos).ok_or(Scte35Error::Truncated)?;
+        if flags & 0x80 != 0 {
+            Ok(Some(self.timestamp()?.1))
+        } else {
+            self.pos += 1;
+            Ok(None)
+        }
+    }
+}
+
+/// Decodes a `hexadecimal-sequence` attribute value, such as that of `SCTE35-OUT`
+pub fn parse_hex(value: &str) -> Result<Vec<u8>, Scte35Error> {
+    let digits = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X") ).ok_or(Scte35Error::BadHexadecimal)?;
+    // decoded bytewise, since the value may not be ASCII
+    digits.as_bytes().chunks(2)
+        .map(|pair| hex_byte(pair).ok_or(Scte35Error::BadHexadecimal) )
+        .collect()
+}
+
+/// The byte given by a pair of hexadecimal digits
+fn hex_byte(pair: &[u8]) -> Option<u8> {
+    let digit = |b: u8| char::from(b).to_digit(16);
+    match *pair {
+        [high, low] => Some((digit(high)? << 4 | digit(low)?) as u8),
+        _ => None,
+    }
+}
+
+/// CRC-32/MPEG-2, as used by MPEG-2 sections
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for b in data {
+        crc ^= u32::from(*b) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04c1_1db7 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+pub fn parse_splice_info_section(data: &[u8]) -> Result<SpliceInfoSection, Scte35Error> {
+    let mut r = Reader::new(data);
+    let table_id = r.u8()?;
+    if table_id != TABLE_ID {
+        return Err(Scte35Error::BadTableId(table_id));
+    }
+    let section_length = (r.uint(2)? & 0xfff) as usize;
+    let section = data.get(..3 + section_length).ok_or(Scte35Error::Truncated)?;
+    if section_length < 4 {
+        return Err(Scte35Error::Truncated);
+    }
+    let mut r = Reader { data: &section[..section.len() - 4], pos: 3 };
+    let protocol_version = r.u8()?;
+    let (flags, pts_adjustment) = r.timestamp()?;
+    let encrypted_packet = flags & 0x40 != 0;
+    let _cw_index = r.u8()?;
+    let tier_and_length = r.uint(3)?;
+    let tier = (tier_and_length >> 12) as u16;
+    let splice_command_length = (tier_and_length & 0xfff) as usize;
+    let splice_command_type = r.u8()?;
+    let command_start = r.pos;
+    let (splice_command, descriptors) = if encrypted_packet {
+        (SpliceCommand::Encrypted { splice_command_type }, vec![])
+    } else {
+        let command = splice_command(&mut r, splice_command_type)?;
+        if splice_command_length != UNKNOWN_COMMAND_LENGTH {
+            r.pos = command_start + splice_command_length;
+        }
+        let descriptor_loop_length = r.uint(2)? as usize;
+        (command, splice_descriptors(r.bytes(descriptor_loop_length)?)?)
+    };
+    let crc_32 = u32::from_be_bytes([section[section.len() - 4], section[section.len() - 3], section[section.len() - 2], section[section.len() - 1]]);
+    Ok(SpliceInfoSection {
+        protocol_version,
+        encrypted_packet,
+        pts_adjustment,
+        tier,
+        splice_command,
+        descriptors,
+        crc_32,
+        crc_valid: crc32(&section[..section.len() - 4]) == crc_32,
+    })
+}
+
+fn splice_command(r: &mut Reader<'_>, splice_command_type: u8) -> Result<SpliceCommand, Scte35Error> {
+    Ok(match splice_command_type {
+        0x00 => SpliceCommand::SpliceNull,
+        0x04 => SpliceCommand::SpliceSchedule,
+        0x05 => SpliceCommand::SpliceInsert(splice_insert(r)?),
+        0x06 => SpliceCommand::TimeSignal { pts_time: r.splice_time()? },
+        0x07 => SpliceCommand::BandwidthReservation,
+        0xff => SpliceCommand::PrivateCommand { identifier: r.uint(4)? as u32 },
+        _ => SpliceCommand::Unknown { splice_command_type },
+    })
+}
+
+fn splice_insert(r: &mut Reader<'_>) -> Result<SpliceInsert, Scte35Error> {
+    let splice_event_id = r.uint(4)? as u32;
+    let splice_event_cancel_indicator = r.u8()? & 0x80 != 0;
+    let mut insert = SpliceInsert {
+        splice_event_id,
+        splice_event_cancel_indicator,
+        out_of_network_indicator: false,
+        splice_immediate_flag: false,
+        pts_time: None,
+        break_duration: None,
+        unique_program_id: 0,
+        avail_num: 0,
+        avails_expected: 0,
+    };
+    if splice_event_cancel_indicator {
+        return Ok(insert);
+    }
+    let flags = r.u8()?;
+    insert.out_of_network_indicator = flags & 0x80 != 0;
+    let program_splice_flag = flags & 0x40 != 0;
+    let duration_flag = flags & 0x20 != 0;
+    insert.splice_immediate_flag = flags & 0x10 != 0;
+    if program_splice_flag {
+        if !insert.splice_immediate_flag {
+            insert.pts_time = r.splice_time()?;
+        }
+    } else {
+        let component_count = r.u8()?;
+        for _ in 0..component_count {
+            let _component_tag = r.u8()?;
+            if !insert.splice_immediate_flag {
+                r.splice_time()?;
+            }
+        }
+    }
+    if duration_flag {
+        let (flags, duration) = r.timestamp()?;
+        insert.break_duration = Some(BreakDuration {
+            auto_return: flags & 0x40 != 0,
+            duration,
+        });
+    }
+    insert.unique_program_id = r.uint(2)? as u16;
+    insert.avail_num = r.u8()?;
+    insert.avails_expected = r.u8()?;
+    Ok(insert)
+}
+
+fn splice_descriptors(data: &[u8]) -> Result<Vec<SpliceDescriptor>, Scte35Error> {
+    let mut r = Reader::new(data);
+    let mut descriptors = vec![];
+    while r.pos < data.len() {
+        let splice_descriptor_tag = r.u8()?;
+        let length = r.u8()? as usize;
+        let mut d = Reader::new(r.bytes(length)?);
+        let identifier = d.uint(4)? as u32;
+        descriptors.push(if splice_descriptor_tag == SEGMENTATION_DESCRIPTOR_TAG {
+            SpliceDescriptor::Segmentation(segmentation_descriptor(&mut d)?)
+        } else {
+            SpliceDescriptor::Other { splice_descriptor_tag, identifier }
+        });
+    }
+    Ok(descriptors)
+}
+
+fn segmentation_descriptor(r: &mut Reader<'_>) -> Result<SegmentationDescriptor, Scte35Error> {
+    let segmentation_event_id = r.uint(4)? as u32;
+    let segmentation_event_cancel_indicator = r.u8()? & 0x80 != 0;
+    let mut descriptor = SegmentationDescriptor {
+        segmentation_event_id,
+        segmentation_event_cancel_indicator,
+        segmentation_duration: None,
+        segmentation_upid_type: 0,
+        segmentation_upid: String::new(),
+        segmentation_type_id: 0,
+        segment_num: 0,
+        segments_expected: 0,
+    };
+    if segmentation_event_cancel_indicator {
+        return Ok(descriptor);
+    }
+    let flags = r.u8()?;
+    let program_segmentation_flag = flags & 0x80 != 0;
+    let segmentation_duration_flag = flags & 0x40 != 0;
+    if !program_segmentation_flag {
+        let component_count = r.u8()? as usize;
+        // component_tag and pts_offset
+        r.bytes(component_count * 6)?;
+    }
+    if segmentation_duration_flag {
+        descriptor.segmentation_duration = Some(r.uint(5)?);
+    }
+    descriptor.segmentation_upid_type = r.u8()?;
+    let upid_length = r.u8()? as usize;
+    descriptor.segmentation_upid = r.bytes(upid_length)?.iter().map(|b| format!("{:02x}", b) ).collect();
+    descriptor.segmentation_type_id = r.u8()?;
+    descriptor.segment_num = r.u8()?;
+    descriptor.segments_expected = r.u8()?;
+    Ok(descriptor)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splice_insert() {
+        // sample 14.2 of SCTE-35
+        let data = parse_hex("0xFC302F000000000000FFFFF014054800008F7FEFFE7369C02EFE0052CCF500000000000A0008435545490000013562DBA30A").unwrap();
+        let section = parse_splice_info_section(&data).unwrap();
+        assert!(section.crc_valid);
+        assert_eq!(section.splice_command, SpliceCommand::SpliceInsert(SpliceInsert {
+            splice_event_id: 0x4800008f,
+            splice_event_cancel_indicator: false,
+            out_of_network_indicator: true,
+            splice_immediate_flag: false,
+            pts_time: Some(0x07369c02e),
+            break_duration: Some(BreakDuration { auto_return: true, duration: 0x052ccf5 }),
+            unique_program_id: 0,
+            avail_num: 0,
+            avails_expected: 0,
+        }));
+        assert_eq!(section.descriptors, vec![SpliceDescriptor::Other { splice_descriptor_tag: 0, identifier: 0x43554549 }]);
+        assert_eq!((section.event_id(), section.duration(), section.out_of_network()), (Some(0x4800008f), Some(0x052ccf5), Some(true)));
+
+        let mut corrupt = data.clone();
+        corrupt[20] ^= 1;
+        assert!(!parse_splice_info_section(&corrupt).unwrap().crc_valid);
+        assert_eq!(parse_splice_info_section(&data[..20]), Err(Scte35Error::Truncated));
+        assert_eq!(parse_hex("FC30"), Err(Scte35Error::BadHexadecimal));
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(parse_hex("0Xfc30"), Ok(vec![0xfc, 0x30]));
+        assert_eq!(parse_hex("0x"), Ok(vec![]));
+        assert_eq!(parse_hex("0xFC3"), Err(Scte35Error::BadHexadecimal));
+        assert_eq!(parse_hex("0x+1"), Err(Scte35Error::BadHexadecimal));
+        // 'é' is 2 bytes in UTF-8, so a pair of bytes can end mid-character
+        assert_eq!(parse_hex("0xaé1"), Err(Scte35Error::BadHexadecimal));
+        assert_eq!(parse_hex("0xé1"), Err(Scte35Error::BadHexadecimal));
+    }
+
+    #[test]
+    fn time_signal() {
+        // sample 14.1 of SCTE-35
+        let data = parse_hex("0xFC3034000000000000FFFFF00506FE72BD0050001E021C435545494800008E7FCF0001A599B00808000000002CA0A18A3402009AC9D17E").unwrap();
+        let section = parse_splice_info_section(&data).unwrap();
+        assert!(section.crc_valid);
+        assert_eq!(section.splice_command, SpliceCommand::TimeSignal { pts_time: Some(0x072bd0050) });
+        assert_eq!(section.descriptors, vec![SpliceDescriptor::Segmentation(SegmentationDescriptor {
+            segmentation_event_id: 0x4800008e,
+            segmentation_event_cancel_indicator: false,
+            segmentation_duration: Some(0x0001a599b0),
+            segmentation_upid_type: 0x08,
+            segmentation_upid: "000000002ca0a18a".to_string(),
+            segmentation_type_id: 0x34,
+            segment_num: 2,
+            segments_expected: 0,
+        })]);
+        assert_eq!((section.event_id(), section.duration(), section.out_of_network()), (Some(0x4800008e), Some(0x0001a599b0), None));
+    }
+}
@@ -0,0 +1,457 @@
+//! Minimal MPEG-2 Transport Stream demultiplexer, extracting just what's needed to check media
+//! segments: the PAT and PMT, continuity counters, PCRs and the PTS of each PES packet.  PSI
+//! sections are assumed to fit in a single TS packet, which is the case for the PAT / PMT of
+//! typical streams.
+
+pub const PACKET_SIZE: usize = 188;
+const SYNC_BYTE: u8 = 0x47;
+const PAT_PID: u16 = 0;
+const NULL_PID: u16 = 0x1fff;
+const TABLE_ID_PAT: u8 = 0x00;
+const TABLE_ID_PMT: u8 = 0x02;
+
+/// PTS and DTS values are 33 bit counts of a 90kHz clock
+pub const TIMESTAMP_MODULUS: u64 = 1 << 33;
+pub const TIMESTAMP_RATE: u64 = 90_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TsError {
+    /// the data is not a whole number of TS packets
+    BadLength(usize),
+    /// the packet with the given index doesn't start with the sync byte
+    LostSync { packet: usize },
+}
+
+/// An elementary stream listed in the PMT
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ElementaryStream {
+    pub pid: u16,
+    pub stream_type: u8,
+}
+impl ElementaryStream {
+    pub fn is_video(&self) -> bool {
+        match self.stream_type {
+            // MPEG-2 video, H.264, HEVC and SAMPLE-AES H.264
+            0x02 | 0x1b | 0x24 | 0xdb => true,
+            _ => false,
+        }
+    }
+}
+
+/// A continuity counter value that doesn't follow on from the previous packet with the same PID
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContinuityError {
+    pub pid: u16,
+    /// index of the packet within the segment
+    pub packet: usize,
+    pub expected: u8,
+    pub actual: u8,
+}
+
+/// A PCR value lower than the previous PCR on the same PID, without the discontinuity_indicator
+#[derive(Debug, Clone, PartialEq)]
+pub struct PcrRegression {
+    pub pid: u16,
+    pub packet: usize,
+    /// 27MHz clock values
+    pub last_pcr: u64,
+    pub pcr: u64,
+}
+
+/// The presentation timestamps of the PES packets of one elementary stream, in the order they
+/// appear
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamTimestamps {
+    pub pid: u16,
+    pub pts: Vec<u64>,
+}
+impl StreamTimestamps {
+    /// the timestamps in presentation order, 'unwrapped' so as to be relative to the first, in case
+    /// the 33 bit counter wrapped around during the segment.  The values are offset by
+    /// `TIMESTAMP_MODULUS` so that those preceding the first (e.g. of B-frames) are never negative.
+    fn sorted(&self) -> Vec<u64> {
+        let first = match self.pts.first() {
+            Some(p) => (*p % TIMESTAMP_MODULUS) as i64 + TIMESTAMP_MODULUS as i64,
+            None => return vec![],
+        };
+        let mut result: Vec<u64> = self.pts.iter()
+            .map(|p| (first + timestamp_diff(*p, first as u64)) as u64 )
+            .collect();
+        result.sort();
+        result
+    }
+
+    /// the duration of one PES packet of the stream, taken as the smallest difference between
+    /// timestamps
+    pub fn frame_duration(&self) -> Option<u64> {
+        self.sorted().windows(2)
+            .map(|w| w[1] - w[0] )
+            .filter(|d| *d > 0 )
+            .min()
+    }
+
+    /// the earliest presentation timestamp
+    pub fn start(&self) -> Option<u64> {
+        self.sorted().first().map(|p| p % TIMESTAMP_MODULUS )
+    }
+
+    /// the presentation timestamp following the last PES packet, assuming its duration is the same
+    /// as that of the others
+    pub fn end(&self) -> Option<u64> {
+        let last = *self.sorted().last()?;
+        Some((last + self.frame_duration().unwrap_or(0)) % TIMESTAMP_MODULUS)
+    }
+}
+
+/// The difference `a - b` between two 33 bit timestamps, allowing for wrap-around
+pub fn timestamp_diff(a: u64, b: u64) -> i64 {
+    let diff = (a.wrapping_sub(b)) % TIMESTAMP_MODULUS;
+    if diff >= TIMESTAMP_MODULUS / 2 {
+        diff as i64 - TIMESTAMP_MODULUS as i64
+    } else {
+        diff as i64
+    }
+}
+
+/// What was found in a Transport Stream segment
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TsSegment {
+    /// true if the first packet carries the PAT, and the PMT is found before any other packets
+    pub starts_with_pat_pmt: bool,
+    /// the elementary streams listed in the first PMT
+    pub streams: Vec<ElementaryStream>,
+    pub continuity_errors: Vec<ContinuityError>,
+    pub pcr_regressions: Vec<PcrRegression>,
+    /// the timestamps of each of the `streams` that had PES packets with a PTS
+    pub timestamps: Vec<StreamTimestamps>,
+    /// the elementary stream data of the first PES packet of the first video stream, which is
+    /// taken to end at the end of the segment if no other PES packet follows it
+    pub first_video_pes: Option<Vec<u8>>,
+}
+impl TsSegment {
+    /// The first video stream listed in the PMT
+    pub fn video_stream(&self) -> Option<&ElementaryStream> {
+        self.streams.iter().find(|s| s.is_video() )
+    }
+
+    /// The timestamps of the stream to use when comparing the timing of segments; the first video
+    /// stream if there is one, or else the first stream with timestamps
+    pub fn reference_timestamps(&self) -> Option<&StreamTimestamps> {
+        let video_pid = self.video_stream().map(|s| s.pid );
+        video_pid.and_then(|pid| self.timestamps.iter().find(|t| t.pid == pid ) )
+            .or_else(|| self.timestamps.first() )
+    }
+}
+
+struct Packet<'a> {
+    pusi: bool,
+    pid: u16,
+    continuity_counter: u8,
+    discontinuity_indicator: bool,
+    pcr: Option<u64>,
+    payload: Option<&'a [u8]>,
+}
+impl<'a> Packet<'a> {
+    fn parse(buf: &'a [u8]) -> Packet<'a> {
+        let pusi = buf[1] & 0x40 != 0;
+        let pid = (u16::from(buf[1] & 0x1f) << 8) | u16::from(buf[2]);
+        let adaptation_field_control = (buf[3] >> 4) & 0b11;
+        let continuity_counter = buf[3] & 0x0f;
+        let mut discontinuity_indicator = false;
+        let mut pcr = None;
+        let mut payload_start = 4;
+        if adaptation_field_control & 0b10 != 0 {
+            let len = buf[4] as usize;
+            if len > 0 {
+                let flags = buf[5];
+                discontinuity_indicator = flags & 0x80 != 0;
+                if flags & 0x10 != 0 && len >= 7 {
+                    let base = (u64::from(buf[6]) << 25)
+                        | (u64::from(buf[7]) << 17)
+                        | (u64::from(buf[8]) << 9)
+                        | (u64::from(buf[9]) << 1)
+                        | (u64::from(buf[10]) >> 7);
+                    let ext = (u64::from(buf[10] & 1) << 8) | u64::from(buf[11]);
+                    pcr = Some(base * 300 + ext);
+                }
+            }
+            payload_start = 5 + len;
+        }
+        let payload = if adaptation_field_control & 0b01 != 0 && payload_start < PACKET_SIZE {
+            Some(&buf[payload_start..])
+        } else {
+            None
+        };
+        Packet {
+            pusi,
+            pid,
+            continuity_counter,
+            discontinuity_indicator,
+            pcr,
+            payload,
+        }
+    }
+}
+
+/// The body of the PSI section starting in the given payload, following the 8 byte section header,
+/// and excluding the CRC
+fn psi_section(payload: &[u8], table_id: u8) -> Option<&[u8]> {
+    let pointer = *payload.first()? as usize;
+    let section = payload.get(1 + pointer..)?;
+    if *section.first()? != table_id {
+        return None;
+    }
+    let section_length = ((usize::from(*section.get(1)?) & 0x0f) << 8) | usize::from(*section.get(2)?);
+    // the 5 bytes following section_length are included in its count, as is the 4 byte CRC
+    section.get(8..(3 + section_length).checked_sub(4)?)
+}
+
+/// The PID of the PMT of the first program listed in the PAT
+fn parse_pat(payload: &[u8]) -> Option<u16> {
+    let body = psi_section(payload, TABLE_ID_PAT)?;
+    body.chunks_exact(4)
+        .find(|p| p[0] != 0 || p[1] != 0 )  // program_number 0 gives the network PID
+        .map(|p| (u16::from(p[2] & 0x1f) << 8) | u16::from(p[3]) )
+}
+
+fn parse_pmt(payload: &[u8]) -> Option<Vec<ElementaryStream>> {
+    let body = psi_section(payload, TABLE_ID_PMT)?;
+    let program_info_length = ((usize::from(*body.get(2)?) & 0x0f) << 8) | usize::from(*body.get(3)?);
+    let mut rest = body.get(4 + program_info_length..)?;
+    let mut streams = vec![];
+    while rest.len() >= 5 {
+        let es_info_length = ((usize::from(rest[3]) & 0x0f) << 8) | usize::from(rest[4]);
+        streams.push(ElementaryStream {
+            stream_type: rest[0],
+            pid: (u16::from(rest[1] & 0x1f) << 8) | u16::from(rest[2]),
+        });
+        rest = rest.get(5 + es_info_length..)?;
+    }
+    Some(streams)
+}
+
+/// The data following the header of the PES packet starting in the given payload
+fn pes_data(payload: &[u8]) -> Option<&[u8]> {
+    if payload.len() < 9 || payload[0..3] != [0, 0, 1] {
+        return None;
+    }
+    payload.get(9 + payload[8] as usize..)
+}
+
+/// The PTS from the header of the PES packet starting in the given payload
+fn parse_pes_pts(payload: &[u8]) -> Option<u64> {
+    if payload.len() < 14 || payload[0..3] != [0, 0, 1] {
+        return None;
+    }
+    let pts_dts_flags = payload[7] >> 6;
+    if pts_dts_flags & 0b10 == 0 {
+        return None;
+    }
+    let p = &payload[9..14];
+    Some((u64::from(p[0] >> 1 & 0b111) << 30)
+        | (u64::from(p[1]) << 22)
+        | (u64::from(p[2] >> 1) << 15)
+        | (u64::from(p[3]) << 7)
+        | u64::from(p[4] >> 1))
+}
+
+pub fn demux(data: &[u8]) -> Result<TsSegment, TsError> {
+    if data.len() % PACKET_SIZE != 0 {
+        return Err(TsError::BadLength(data.len()));
+    }
+    let mut segment = TsSegment::default();
+    let mut pmt_pid = None;
+    let mut pmt_seen = false;
+    let mut other_before_pmt = false;
+    let mut last_cc: [Option<LastContinuity>; 0x2000] = [None; 0x2000];
+    let mut last_pcr: Vec<(u16, u64)> = vec![];
+    // the data of the first video PES packet, while it's still being collected
+    let mut video_pes: Option<Vec<u8>> = None;
+    for (index, buf) in data.chunks_exact(PACKET_SIZE).enumerate() {
+        if buf[0] != SYNC_BYTE {
+            return Err(TsError::LostSync { packet: index });
+        }
+        let pkt = Packet::parse(buf);
+        if index == 0 {
+            segment.starts_with_pat_pmt = pkt.pid == PAT_PID && pkt.pusi;
+        }
+        if pkt.pid == NULL_PID {
+            continue;
+        }
+        check_continuity(&mut segment, &mut last_cc, index, &pkt);
+        if let Some(pcr) = pkt.pcr {
+            match last_pcr.iter_mut().find(|(pid, _)| *pid == pkt.pid ) {
+                Some((_, last)) => {
+                    if pcr < *last && !pkt.discontinuity_indicator {
+                        segment.pcr_regressions.push(PcrRegression {
+                            pid: pkt.pid,
+                            packet: index,
+                            last_pcr: *last,
+                            pcr,
+                        });
+                    }
+                    *last = pcr;
+                },
+                None => last_pcr.push((pkt.pid, pcr)),
+            }
+        }
+        let video_pid = segment.video_stream().map(|s| s.pid );
+        if Some(pkt.pid) == video_pid && segment.first_video_pes.is_none() {
+            match (pkt.pusi, pkt.payload, video_pes.as_mut()) {
+                // the next packet's start marks the end of the first
+                (true, _, Some(_)) => segment.first_video_pes = video_pes.take(),
+                (true, Some(payload), None) => video_pes = pes_data(payload).map(|d| d.to_vec() ),
+                (false, Some(payload), Some(data)) => data.extend_from_slice(payload),
+                _ => (),
+            }
+        }
+        let payload = match (pkt.pusi, pkt.payload) {
+            (true, Some(payload)) => payload,
+            _ => {
+                if !pmt_seen && pkt.pid != PAT_PID && Some(pkt.pid) != pmt_pid {
+                    other_before_pmt = true;
+                }
+                continue;
+            }
+        };
+        if pkt.pid == PAT_PID {
+            if pmt_pid.is_none() {
+                pmt_pid = parse_pat(payload);
+            }
+        } else if Some(pkt.pid) == pmt_pid {
+            if !pmt_seen {
+                if let Some(streams) = parse_pmt(payload) {
+                    segment.streams = streams;
+                    pmt_seen = true;
+                }
+            }
+        } else {
+            if !pmt_seen {
+                other_before_pmt = true;
+            }
+            if segment.streams.iter().any(|s| s.pid == pkt.pid ) {
+                if let Some(pts) = parse_pes_pts(payload) {
+                    match segment.timestamps.iter_mut().find(|t| t.pid == pkt.pid ) {
+                        Some(t) => t.pts.push(pts),
+                        None => segment.timestamps.push(StreamTimestamps { pid: pkt.pid, pts: vec![pts] }),
+                    }
+                }
+            }
+        }
+    }
+    segment.starts_with_pat_pmt = segment.starts_with_pat_pmt && pmt_seen && !other_before_pmt;
+    if segment.first_video_pes.is_none() {
+        segment.first_video_pes = video_pes;
+    }
+    Ok(segment)
+}
+
+/// The continuity counter of the last packet of a PID, and whether that packet was a duplicate
+#[derive(Clone, Copy)]
+struct LastContinuity {
+    counter: u8,
+    duplicate: bool,
+}
+
+/// "The continuity_counter shall not be incremented when the adaptation_field_control of the
+/// packet equals '00' or '10'", and otherwise increments by one, except that a packet may be
+/// duplicated once
+fn check_continuity(segment: &mut TsSegment, last_cc: &mut [Option<LastContinuity>], index: usize, pkt: &Packet<'_>) {
+    let last = last_cc[pkt.pid as usize];
+    let last = match last {
+        Some(l) if !pkt.discontinuity_indicator => l,
+        _ => {
+            last_cc[pkt.pid as usize] = Some(LastContinuity { counter: pkt.continuity_counter, duplicate: false });
+            return;
+        },
+    };
+    // a packet with a payload may be sent twice, but no more than that
+    let duplicate = pkt.payload.is_some() && pkt.continuity_counter == last.counter && !last.duplicate;
+    last_cc[pkt.pid as usize] = Some(LastContinuity { counter: pkt.continuity_counter, duplicate });
+    let expected = if pkt.payload.is_some() { (last.counter + 1) & 0x0f } else { last.counter };
+    if pkt.continuity_counter != expected && !duplicate {
+        segment.continuity_errors.push(ContinuityError {
+            pid: pkt.pid,
+            packet: index,
+            expected,
+            actual: pkt.continuity_counter,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn packet(pid: u16, pusi: bool, cc: u8, payload: &[u8]) -> Vec<u8> {
+        let mut buf = vec![SYNC_BYTE, (if pusi { 0x40 } else { 0 }) | (pid >> 8) as u8, pid as u8, 0x10 | cc];
+        buf.extend_from_slice(payload);
+        buf.resize(PACKET_SIZE, 0xff);
+        buf
+    }
+
+    fn pes(pts: u64) -> Vec<u8> {
+        vec![
+            0, 0, 1, 0xe0, 0, 0, 0x80, 0x80, 5,
+            0x21 | ((pts >> 29) & 0x0e) as u8,
+            (pts >> 22) as u8,
+            0x01 | ((pts >> 14) & 0xfe) as u8,
+            (pts >> 7) as u8,
+            0x01 | ((pts << 1) & 0xfe) as u8,
+        ]
+    }
+
+    fn segment(first_pts: u64, ccs: &[u8]) -> Vec<u8> {
+        // PAT giving PMT PID 0x100, and PMT giving H.264 on PID 0x101
+        let pat = [0, 0x00, 0xb0, 13, 0, 1, 0xc1, 0, 0, 0, 1, 0xe1, 0x00, 0, 0, 0, 0];
+        let pmt = [0, 0x02, 0xb0, 18, 0, 1, 0xc1, 0, 0, 0xe1, 0x01, 0xf0, 0, 0x1b, 0xe1, 0x01, 0xf0, 0, 0, 0, 0, 0];
+        let mut data = packet(0, true, 0, &pat);
+        data.extend(packet(0x100, true, 0, &pmt));
+        for (i, cc) in ccs.iter().enumerate() {
+            data.extend(packet(0x101, true, *cc, &pes(first_pts + i as u64 * 3600)));
+        }
+        data
+    }
+
+    #[test]
+    fn pat_pmt_and_timestamps() {
+        let seg = demux(&segment(TIMESTAMP_MODULUS - 3600, &[0, 1, 2])).unwrap();
+        assert!(seg.starts_with_pat_pmt);
+        assert_eq!(seg.streams, vec![ElementaryStream { pid: 0x101, stream_type: 0x1b }]);
+        assert!(seg.continuity_errors.is_empty());
+        // the PES header is followed by the packet's padding
+        assert_eq!(seg.first_video_pes, Some(vec![0xff; PACKET_SIZE - 4 - 14]));
+        let ts = seg.reference_timestamps().unwrap();
+        assert_eq!(ts.frame_duration(), Some(3600));
+        assert_eq!(ts.start(), Some(TIMESTAMP_MODULUS - 3600));
+        // the timestamps wrapped around during the segment
+        assert_eq!(ts.end(), Some(2 * 3600));
+    }
+
+    #[test]
+    fn b_frame_before_rollover() {
+        // the second frame is presented before the first, which follows the wrap-around of the
+        // 33 bit counter
+        let ts = StreamTimestamps { pid: 0x101, pts: vec![1000, TIMESTAMP_MODULUS - 2600, 4600] };
+        assert_eq!(ts.start(), Some(TIMESTAMP_MODULUS - 2600));
+        assert_eq!(ts.frame_duration(), Some(3600));
+        assert_eq!(ts.end(), Some(8200));
+    }
+
+    #[test]
+    fn continuity() {
+        let seg = demux(&segment(0, &[0, 1, 1, 3])).unwrap();
+        assert_eq!(seg.continuity_errors, vec![ContinuityError { pid: 0x101, packet: 5, expected: 2, actual: 3 }]);
+        // only one duplicate packet is allowed
+        let seg = demux(&segment(0, &[0, 1, 1, 1, 2])).unwrap();
+        assert_eq!(seg.continuity_errors, vec![ContinuityError { pid: 0x101, packet: 5, expected: 2, actual: 1 }]);
+    }
+
+    #[test]
+    fn missing_pat() {
+        let data = segment(0, &[0]);
+        let seg = demux(&data[PACKET_SIZE..]).unwrap();
+        assert!(!seg.starts_with_pat_pmt);
+        assert_eq!(demux(&data[1..]), Err(TsError::BadLength(data.len() - 1)));
+    }
+}
@@ -0,0 +1,655 @@
+//! Decoding of the H.264 and HEVC parameter sets, giving the profile, level and picture size of a
+//! video stream.  Parameter sets are found either in Annex B byte streams (as carried in MPEG-TS
+//! PES packets), in the NAL units of MP4 samples, or in the `avcC` / `hvcC` decoder configuration
+//! records of MP4 sample entries.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamSetError {
+    /// the data ended before all the fields of interest were read
+    Truncated,
+    /// a decoder configuration record doesn't hold a sequence parameter set
+    NoSps,
+    /// an Exp-Golomb coded value was out of range, or the named value computed from it overflowed
+    BadValue(&'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VideoCodec {
+    Avc,
+    Hevc,
+}
+
+/// The properties of a sequence parameter set that are declared in HLS playlists and DASH
+/// manifests
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequenceInfo {
+    pub codec: VideoCodec,
+    /// `seq_parameter_set_id`
+    pub id: u32,
+    /// `profile_idc` (or `general_profile_idc` for HEVC)
+    pub profile: u8,
+    /// `level_idc` (or `general_level_idc` for HEVC)
+    pub level: u8,
+    /// the RFC 6381 codecs parameter for the stream, e.g. `avc1.64001F` or `hvc1.1.6.L93.B0`
+    pub codecs: String,
+    /// picture dimensions after cropping
+    pub width: u32,
+    pub height: u32,
+}
+impl SequenceInfo {
+    /// Whether the profile and level match those given by an entry of a `CODECS` attribute, or
+    /// `None` if the entry is not for the same codec
+    pub fn matches_codecs(&self, entry: &str) -> Option<bool> {
+        let mut parts = entry.trim().split('.');
+        let sample_entry = parts.next()?;
+        match self.codec {
+            VideoCodec::Avc => {
+                if sample_entry != "avc1" && sample_entry != "avc3" {
+                    return None;
+                }
+                let ppcc_ll = parts.next().unwrap_or("");
+                let profile = ppcc_ll.get(0..2).and_then(|p| u8::from_str_radix(p, 16).ok() );
+                let level = ppcc_ll.get(4..6).and_then(|l| u8::from_str_radix(l, 16).ok() );
+                Some(profile == Some(self.profile) && level == Some(self.level))
+            },
+            VideoCodec::Hevc => {
+                if sample_entry != "hvc1" && sample_entry != "hev1" {
+                    return None;
+                }
+                // the profile may be prefixed by a letter giving general_profile_space
+                let profile = parts.next()
+                    .map(|p| p.trim_start_matches(|c: char| c.is_ascii_alphabetic() ) )
+                    .and_then(|p| p.parse::<u8>().ok() );
+                // the tier and level follows the compatibility flags
+                let level = parts.nth(1)
+                    .and_then(|l| l.get(1..) )
+                    .and_then(|l| l.parse::<u8>().ok() );
+                Some(profile == Some(self.profile) && level == Some(self.level))
+            },
+        }
+    }
+
+    /// The picture size in the format of the `RESOLUTION` attribute
+    pub fn resolution(&self) -> String {
+        format!("{}x{}", self.width, self.height)
+    }
+}
+
+/// A picture parameter set.  Only the IDs are decoded, but the RBSP is kept so that changes to the
+/// other fields can be detected.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PictureInfo {
+    /// `pic_parameter_set_id`
+    pub id: u32,
+    /// the `seq_parameter_set_id` of the sequence parameter set the picture parameter set refers to
+    pub sps_id: u32,
+    pub rbsp: Vec<u8>,
+}
+
+/// The parameter sets in effect for a stream: the first sequence parameter set found, and each of
+/// the picture parameter sets
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterSets {
+    pub sps: SequenceInfo,
+    pub pps: Vec<PictureInfo>,
+}
+impl ParameterSets {
+    /// Whether the resolution, profile or level differ from those of the given parameter sets
+    pub fn sequence_changed(&self, prev: &ParameterSets) -> bool {
+        let (p, t) = (&prev.sps, &self.sps);
+        (p.width, p.height, p.profile, p.level) != (t.width, t.height, t.profile, t.level)
+    }
+
+    /// The IDs of the picture parameter sets whose content differs from that of the picture
+    /// parameter set with the same ID in the given parameter sets
+    pub fn changed_pictures(&self, prev: &ParameterSets) -> Vec<u32> {
+        self.pps.iter()
+            .filter(|this| prev.pps.iter().any(|p| p.id == this.id && p != *this ) )
+            .map(|this| this.id )
+            .collect()
+    }
+}
+
+/// Reads the bits of an RBSP, most significant first, including Exp-Golomb coded values
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader { data, pos: 0 }
+    }
+
+    fn read_bit(&mut self) -> Result<bool, ParamSetError> {
+        let byte = self.data.get(self.pos / 8).ok_or(ParamSetError::Truncated)?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Ok(bit == 1)
+    }
+
+    fn read_bits(&mut self, count: u32) -> Result<u32, ParamSetError> {
+        let mut value = 0;
+        for _ in 0..count {
+            value = (value << 1) | self.read_bit()? as u32;
+        }
+        Ok(value)
+    }
+
+    fn skip(&mut self, count: usize) -> Result<(), ParamSetError> {
+        if (self.pos + count + 7) / 8 > self.data.len() {
+            return Err(ParamSetError::Truncated);
+        }
+        self.pos += count;
+        Ok(())
+    }
+
+    fn read_ue(&mut self, name: &'static str) -> Result<u32, ParamSetError> {
+        let mut leading_zeros = 0;
+        while !self.read_bit()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return Err(ParamSetError::BadValue(name));
+            }
+        }
+        Ok((1u64 << leading_zeros) as u32 - 1 + self.read_bits(leading_zeros)?)
+    }
+
+    fn read_se(&mut self, name: &'static str) -> Result<i32, ParamSetError> {
+        let value = self.read_ue(name)?;
+        Ok(if value % 2 == 1 { (value / 2 + 1) as i32 } else { -((value / 2) as i32) })
+    }
+}
+
+/// The NAL unit payload, with emulation prevention bytes (`0x03` following `0x00 0x00`) removed
+fn rbsp(nal: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for b in nal {
+        if zeros >= 2 && *b == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if *b == 0 { zeros + 1 } else { 0 };
+        result.push(*b);
+    }
+    result
+}
+
+/// The NAL units of an Annex B byte stream, each being delimited by a start code
+pub fn nal_units(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut rest = data;
+    std::iter::from_fn(move || {
+        let start = find_start_code(rest)? + 3;
+        rest = &rest[start..];
+        let end = find_start_code(rest).unwrap_or(rest.len());
+        let nal = &rest[..end];
+        rest = &rest[end..];
+        // any trailing zero belongs to the following 4 byte start code
+        let len = nal.iter().rposition(|b| *b != 0 ).map(|p| p + 1 ).unwrap_or(0);
+        Some(&nal[..len])
+    })
+}
+
+fn find_start_code(data: &[u8]) -> Option<usize> {
+    data.windows(3).position(|w| w == [0, 0, 1] )
+}
+
+/// The NAL units of a sample in an MP4 file, each preceded by its length in a field of the given
+/// size (from the decoder configuration record's `lengthSizeMinusOne`)
+pub fn length_prefixed_nal_units(data: &[u8], length_size: usize) -> impl Iterator<Item = &[u8]> {
+    let mut rest = data;
+    std::iter::from_fn(move || {
+        let len = rest.get(..length_size)?.iter().fold(0, |len, b| (len << 8) | usize::from(*b) );
+        let nal = rest.get(length_size..length_size + len)?;
+        rest = &rest[length_size + len..];
+        Some(nal)
+    })
+}
+
+/// The `nal_unit_type` of an H.264 NAL unit
+pub fn avc_nal_type(nal: &[u8]) -> Option<u8> {
+    nal.first().map(|h| h & 0x1f )
+}
+
+/// The `nal_unit_type` of an HEVC NAL unit
+pub fn hevc_nal_type(nal: &[u8]) -> Option<u8> {
+    nal.first().map(|h| (h >> 1) & 0x3f )
+}
+
+pub const AVC_NAL_SPS: u8 = 7;
+pub const AVC_NAL_PPS: u8 = 8;
+pub const HEVC_NAL_SPS: u8 = 33;
+pub const HEVC_NAL_PPS: u8 = 34;
+const AVC_NAL_SEI: u8 = 6;
+const AVC_NAL_IDR: u8 = 5;
+const AVC_SEI_RECOVERY_POINT: u32 = 6;
+
+/// Whether the first coded picture in the given NAL units is one from which decoding can start,
+/// or `None` if there are no coded slices.  For H.264 that is an IDR picture, or a picture
+/// preceded by a recovery point SEI message (as used by streams with open GOPs), and for HEVC an
+/// IRAP picture (IDR, CRA or BLA).
+pub fn starts_with_keyframe<'a>(codec: VideoCodec, mut nals: impl Iterator<Item = &'a [u8]>) -> Option<bool> {
+    match codec {
+        VideoCodec::Avc => {
+            let mut recovery_point = false;
+            for nal in nals {
+                match avc_nal_type(nal)? {
+                    AVC_NAL_SEI => recovery_point |= sei_payload_types(&rbsp(&nal[1..])).contains(&AVC_SEI_RECOVERY_POINT),
+                    AVC_NAL_IDR => return Some(true),
+                    1..=4 => return Some(recovery_point),
+                    _ => (),
+                }
+            }
+            None
+        },
+        VideoCodec::Hevc => {
+            // VCL NAL unit types are all below 32
+            let first_slice = nals.find(|n| hevc_nal_type(n).map(|t| t < 32 ).unwrap_or(false) )?;
+            hevc_nal_type(first_slice).map(|t| (16..=21).contains(&t) )
+        },
+    }
+}
+
+/// The `payloadType` of each message in an SEI RBSP
+fn sei_payload_types(mut data: &[u8]) -> Vec<u32> {
+    let mut types = vec![];
+    // the RBSP ends with the stop bit
+    while data.len() > 1 {
+        let (payload_type, rest) = match sei_value(data) {
+            Some(v) => v,
+            None => break,
+        };
+        let (payload_size, rest) = match sei_value(rest) {
+            Some(v) => v,
+            None => break,
+        };
+        types.push(payload_type);
+        data = match rest.get(payload_size as usize..) {
+            Some(rest) => rest,
+            None => break,
+        };
+    }
+    types
+}
+
+/// A `payloadType` or `payloadSize` value, coded as a run of `0xff` bytes that are summed with the
+/// byte that follows
+fn sei_value(data: &[u8]) -> Option<(u32, &[u8])> {
+    let ff_count = data.iter().take_while(|b| **b == 0xff ).count();
+    let last = *data.get(ff_count)?;
+    Some((ff_count as u32 * 255 + u32::from(last), &data[ff_count + 1..]))
+}
+
+/// Decodes the parameter sets preceding the first coded slice in the given NAL units, or `None` if
+/// there's no sequence parameter set
+pub fn find_parameter_sets<'a>(codec: VideoCodec, nals: impl Iterator<Item = &'a [u8]>) -> Option<Result<ParameterSets, ParamSetError>> {
+    let mut sps = None;
+    let mut pps = vec![];
+    for nal in nals {
+        match codec {
+            VideoCodec::Avc => match avc_nal_type(nal) {
+                Some(AVC_NAL_SPS) if sps.is_none() => sps = Some(parse_avc_sps(nal)),
+                Some(AVC_NAL_PPS) => pps.push(parse_avc_pps(nal)),
+                Some(1..=5) => break,
+                _ => (),
+            },
+            VideoCodec::Hevc => match hevc_nal_type(nal) {
+                Some(HEVC_NAL_SPS) if sps.is_none() => sps = Some(parse_hevc_sps(nal)),
+                Some(HEVC_NAL_PPS) => pps.push(parse_hevc_pps(nal)),
+                Some(t) if t < 32 => break,
+                _ => (),
+            },
+        }
+    }
+    Some(sps?.and_then(|sps| {
+        Ok(ParameterSets {
+            sps,
+            pps: pps.into_iter().collect::<Result<_, _>>()?,
+        })
+    }))
+}
+
+/// A value computed from the fields of a parameter set, which is invalid if the computation
+/// overflowed
+fn computed(value: Option<u32>, name: &'static str) -> Result<u32, ParamSetError> {
+    value.ok_or(ParamSetError::BadValue(name))
+}
+
+/// Decodes an H.264 sequence parameter set NAL unit, including its NAL header byte
+pub fn parse_avc_sps(nal: &[u8]) -> Result<SequenceInfo, ParamSetError> {
+    let data = rbsp(nal.get(1..).ok_or(ParamSetError::Truncated)?);
+    let mut r = BitReader::new(&data);
+    let profile = r.read_bits(8)? as u8;
+    let constraint_flags = r.read_bits(8)? as u8;
+    let level = r.read_bits(8)? as u8;
+    let id = r.read_ue("seq_parameter_set_id")?;
+    let mut chroma_format_idc = 1;
+    let mut separate_colour_plane = false;
+    if [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135].contains(&profile) {
+        chroma_format_idc = r.read_ue("chroma_format_idc")?;
+        if chroma_format_idc == 3 {
+            separate_colour_plane = r.read_bit()?;
+        }
+        r.read_ue("bit_depth_luma_minus8")?;
+        r.read_ue("bit_depth_chroma_minus8")?;
+        r.read_bit()?;  // qpprime_y_zero_transform_bypass_flag
+        if r.read_bit()? {
+            // seq_scaling_matrix_present_flag
+            let count = if chroma_format_idc == 3 { 12 } else { 8 };
+            for i in 0..count {
+                if r.read_bit()? {
+                    skip_scaling_list(&mut r, if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+    r.read_ue("log2_max_frame_num_minus4")?;
+    match r.read_ue("pic_order_cnt_type")? {
+        0 => {
+            r.read_ue("log2_max_pic_order_cnt_lsb_minus4")?;
+        },
+        1 => {
+            r.read_bit()?;  // delta_pic_order_always_zero_flag
+            r.read_se("offset_for_non_ref_pic")?;
+            r.read_se("offset_for_top_to_bottom_field")?;
+            for _ in 0..r.read_ue("num_ref_frames_in_pic_order_cnt_cycle")? {
+                r.read_se("offset_for_ref_frame")?;
+            }
+        },
+        _ => (),
+    }
+    r.read_ue("max_num_ref_frames")?;
+    r.read_bit()?;  // gaps_in_frame_num_value_allowed_flag
+    let width_in_mbs = computed(r.read_ue("pic_width_in_mbs_minus1")?.checked_add(1), "width")?;
+    let height_in_map_units = computed(r.read_ue("pic_height_in_map_units_minus1")?.checked_add(1), "height")?;
+    let frame_mbs_only = r.read_bit()?;
+    if !frame_mbs_only {
+        r.read_bit()?;  // mb_adaptive_frame_field_flag
+    }
+    r.read_bit()?;  // direct_8x8_inference_flag
+    let (mut crop_x, mut crop_y) = (0, 0);
+    if r.read_bit()? {
+        // frame_cropping_flag
+        crop_x = computed(r.read_ue("frame_crop_left_offset")?.checked_add(r.read_ue("frame_crop_right_offset")?), "crop_x")?;
+        crop_y = computed(r.read_ue("frame_crop_top_offset")?.checked_add(r.read_ue("frame_crop_bottom_offset")?), "crop_y")?;
+    }
+    let field_factor = if frame_mbs_only { 1 } else { 2 };
+    let chroma_array_type = if separate_colour_plane { 0 } else { chroma_format_idc };
+    let (crop_unit_x, crop_unit_y) = match chroma_array_type {
+        0 => (1, field_factor),
+        1 => (2, 2 * field_factor),
+        2 => (2, field_factor),
+        _ => (1, field_factor),
+    };
+    let width = computed(width_in_mbs.checked_mul(16), "width")?;
+    let height = computed(height_in_map_units.checked_mul(16 * field_factor), "height")?;
+    Ok(SequenceInfo {
+        codec: VideoCodec::Avc,
+        id,
+        profile,
+        level,
+        codecs: format!("avc1.{:02X}{:02X}{:02X}", profile, constraint_flags, level),
+        width: width.saturating_sub(computed(crop_x.checked_mul(crop_unit_x), "crop_x")?),
+        height: height.saturating_sub(computed(crop_y.checked_mul(crop_unit_y), "crop_y")?),
+    })
+}
+
+/// Decodes an H.264 picture parameter set NAL unit, including its NAL header byte
+pub fn parse_avc_pps(nal: &[u8]) -> Result<PictureInfo, ParamSetError> {
+    parse_pps(rbsp(nal.get(1..).ok_or(ParamSetError::Truncated)?))
+}
+
+/// Decodes an HEVC picture parameter set NAL unit, including its 2 byte NAL header
+pub fn parse_hevc_pps(nal: &[u8]) -> Result<PictureInfo, ParamSetError> {
+    parse_pps(rbsp(nal.get(2..).ok_or(ParamSetError::Truncated)?))
+}
+
+/// Both H.264 and HEVC picture parameter sets start with the PPS and SPS IDs
+fn parse_pps(rbsp: Vec<u8>) -> Result<PictureInfo, ParamSetError> {
+    let mut r = BitReader::new(&rbsp);
+    let id = r.read_ue("pic_parameter_set_id")?;
+    let sps_id = r.read_ue("seq_parameter_set_id")?;
+    Ok(PictureInfo { id, sps_id, rbsp })
+}
+
+fn skip_scaling_list(r: &mut BitReader<'_>, size: usize) -> Result<(), ParamSetError> {
+    let mut last_scale = 8;
+    let mut next_scale = 8;
+    for _ in 0..size {
+        if next_scale != 0 {
+            let delta = r.read_se("delta_scale")?;
+            next_scale = (i64::from(last_scale) + i64::from(delta)).rem_euclid(256) as i32;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Ok(())
+}
+
+/// Decodes an HEVC sequence parameter set NAL unit, including its 2 byte NAL header
+pub fn parse_hevc_sps(nal: &[u8]) -> Result<SequenceInfo, ParamSetError> {
+    let data = rbsp(nal.get(2..).ok_or(ParamSetError::Truncated)?);
+    let mut r = BitReader::new(&data);
+    r.read_bits(4)?;  // sps_video_parameter_set_id
+    let max_sub_layers_minus1 = r.read_bits(3)? as usize;
+    r.read_bit()?;  // sps_temporal_id_nesting_flag
+    // profile_tier_level()
+    let profile_space = r.read_bits(2)?;
+    let tier = r.read_bit()?;
+    let profile = r.read_bits(5)? as u8;
+    let compatibility_flags = r.read_bits(32)?;
+    let mut constraint_flags = [0u8; 6];
+    for b in constraint_flags.iter_mut() {
+        *b = r.read_bits(8)? as u8;
+    }
+    let level = r.read_bits(8)? as u8;
+    let mut sub_layers = vec![];
+    for _ in 0..max_sub_layers_minus1 {
+        sub_layers.push((r.read_bit()?, r.read_bit()?));
+    }
+    if max_sub_layers_minus1 > 0 {
+        r.skip(2 * (8 - max_sub_layers_minus1))?;
+    }
+    for (profile_present, level_present) in sub_layers {
+        if profile_present {
+            r.skip(88)?;
+        }
+        if level_present {
+            r.skip(8)?;
+        }
+    }
+    let id = r.read_ue("sps_seq_parameter_set_id")?;
+    let chroma_format_idc = r.read_ue("chroma_format_idc")?;
+    if chroma_format_idc == 3 {
+        r.read_bit()?;  // separate_colour_plane_flag
+    }
+    let width = r.read_ue("pic_width_in_luma_samples")?;
+    let height = r.read_ue("pic_height_in_luma_samples")?;
+    let (mut crop_x, mut crop_y) = (0, 0);
+    if r.read_bit()? {
+        // conformance_window_flag
+        crop_x = computed(r.read_ue("conf_win_left_offset")?.checked_add(r.read_ue("conf_win_right_offset")?), "crop_x")?;
+        crop_y = computed(r.read_ue("conf_win_top_offset")?.checked_add(r.read_ue("conf_win_bottom_offset")?), "crop_y")?;
+    }
+    let (sub_width, sub_height) = match chroma_format_idc {
+        1 => (2, 2),
+        2 => (2, 1),
+        _ => (1, 1),
+    };
+    Ok(SequenceInfo {
+        codec: VideoCodec::Hevc,
+        id,
+        profile,
+        level,
+        codecs: hevc_codecs(profile_space, tier, profile, compatibility_flags, &constraint_flags, level),
+        width: width.saturating_sub(computed(crop_x.checked_mul(sub_width), "crop_x")?),
+        height: height.saturating_sub(computed(crop_y.checked_mul(sub_height), "crop_y")?),
+    })
+}
+
+/// The codecs parameter for HEVC, as described in ISO/IEC 14496-15 Annex E
+fn hevc_codecs(profile_space: u32, tier: bool, profile: u8, compatibility_flags: u32, constraint_flags: &[u8], level: u8) -> String {
+    let space = match profile_space {
+        1 => "A",
+        2 => "B",
+        3 => "C",
+        _ => "",
+    };
+    let mut codecs = format!(
+        "hvc1.{}{}.{:X}.{}{}",
+        space,
+        profile,
+        compatibility_flags.reverse_bits(),
+        if tier { 'H' } else { 'L' },
+        level
+    );
+    // trailing zero bytes of the constraint flags are omitted
+    let len = constraint_flags.iter().rposition(|b| *b != 0 ).map(|p| p + 1 ).unwrap_or(0);
+    for b in &constraint_flags[..len] {
+        codecs.push_str(&format!(".{:X}", b));
+    }
+    codecs
+}
+
+/// Splits a NAL unit preceded by its 16 bit length from the start of the given data
+fn config_nal(data: &[u8]) -> Result<(&[u8], &[u8]), ParamSetError> {
+    let len_bytes = data.get(..2).ok_or(ParamSetError::Truncated)?;
+    let len = usize::from(len_bytes[0]) << 8 | usize::from(len_bytes[1]);
+    let nal = data.get(2..2 + len).ok_or(ParamSetError::Truncated)?;
+    Ok((nal, &data[2 + len..]))
+}
+
+/// Decodes the first sequence parameter set, and the picture parameter sets, held by an `avcC`
+/// AVCDecoderConfigurationRecord
+pub fn parse_avc_decoder_config(avcc: &[u8]) -> Result<ParameterSets, ParamSetError> {
+    let sps_count = avcc.get(5).ok_or(ParamSetError::Truncated)? & 0x1f;
+    let mut rest = &avcc[6..];
+    let mut sps = None;
+    for _ in 0..sps_count {
+        let (nal, r) = config_nal(rest)?;
+        if sps.is_none() {
+            sps = Some(parse_avc_sps(nal)?);
+        }
+        rest = r;
+    }
+    let sps = sps.ok_or(ParamSetError::NoSps)?;
+    let pps_count = *rest.first().ok_or(ParamSetError::Truncated)?;
+    rest = &rest[1..];
+    let mut pps = vec![];
+    for _ in 0..pps_count {
+        let (nal, r) = config_nal(rest)?;
+        pps.push(parse_avc_pps(nal)?);
+        rest = r;
+    }
+    Ok(ParameterSets { sps, pps })
+}
+
+/// Decodes the first sequence parameter set, and the picture parameter sets, held by an `hvcC`
+/// HEVCDecoderConfigurationRecord
+pub fn parse_hevc_decoder_config(hvcc: &[u8]) -> Result<ParameterSets, ParamSetError> {
+    let array_count = *hvcc.get(22).ok_or(ParamSetError::Truncated)?;
+    let mut rest = &hvcc[23..];
+    let mut sps = None;
+    let mut pps = vec![];
+    for _ in 0..array_count {
+        let header = rest.get(..3).ok_or(ParamSetError::Truncated)?;
+        let nal_type = header[0] & 0x3f;
+        let nal_count = usize::from(header[1]) << 8 | usize::from(header[2]);
+        rest = &rest[3..];
+        for _ in 0..nal_count {
+            let (nal, r) = config_nal(rest)?;
+            match nal_type {
+                HEVC_NAL_SPS if sps.is_none() => sps = Some(parse_hevc_sps(nal)?),
+                HEVC_NAL_PPS => pps.push(parse_hevc_pps(nal)?),
+                _ => (),
+            }
+            rest = r;
+        }
+    }
+    Ok(ParameterSets {
+        sps: sps.ok_or(ParamSetError::NoSps)?,
+        pps,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // 1920x1080 High profile, level 3.1, cropped from 1088 lines
+    const AVC_SPS: &[u8] = &[0x67, 0x64, 0x00, 0x1f, 0xac, 0xd9, 0x40, 0x78, 0x02, 0x27, 0xe5, 0x40];
+
+    // 1280x720 Main profile, level 3.1
+    const HEVC_SPS: &[u8] = &[
+        0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0xb0, 0x00, 0x00, 0x03, 0x00, 0x00,
+        0x03, 0x00, 0x5d, 0xa0, 0x02, 0x80, 0x80, 0x2d, 0x16, 0x58,
+    ];
+
+    #[test]
+    fn avc_sps() {
+        let data = [&[0, 0, 0, 1, 0x09, 0xf0, 0, 0, 1][..], AVC_SPS, &[0, 0, 1, 0x68, 0xeb]].concat();
+        let params = find_parameter_sets(VideoCodec::Avc, nal_units(&data)).unwrap().unwrap();
+        assert_eq!(params.pps, vec![PictureInfo { id: 0, sps_id: 0, rbsp: vec![0xeb] }]);
+        let info = params.sps;
+        assert_eq!(info.codecs, "avc1.64001F");
+        assert_eq!(info.resolution(), "1920x1080");
+        assert_eq!(info.matches_codecs("avc1.64001f"), Some(true));
+        assert_eq!(info.matches_codecs("avc1.4D401F"), Some(false));
+        assert_eq!(info.matches_codecs("mp4a.40.2"), None);
+    }
+
+    #[test]
+    fn avc_sps_overflow() {
+        // pic_width_in_mbs_minus1 is 2^29, making the width in samples overflow
+        let sps = [0x67, 0x42, 0x00, 0x1f, 0xda, 0x00, 0x00, 0x03, 0x00, 0x02, 0x00, 0x00, 0x03, 0x00, 0x1e, 0x40];
+        assert_eq!(parse_avc_sps(&sps), Err(ParamSetError::BadValue("width")));
+    }
+
+    #[test]
+    fn parameter_set_changes() {
+        let params = |pps: &[u8]| ParameterSets {
+            sps: parse_avc_sps(AVC_SPS).unwrap(),
+            pps: vec![parse_avc_pps(pps).unwrap()],
+        };
+        let first = params(&[0x68, 0xeb]);
+        assert!(!first.sequence_changed(&first));
+        assert_eq!(params(&[0x68, 0xce, 0x3c, 0x80]).changed_pictures(&first), vec![0]);
+        // a picture parameter set with a new ID doesn't replace the first
+        assert!(params(&[0x68, 0x5f]).changed_pictures(&first).is_empty());
+    }
+
+    #[test]
+    fn hevc_sps() {
+        let info = parse_hevc_sps(HEVC_SPS).unwrap();
+        assert_eq!(info.codecs, "hvc1.1.6.L93.B0");
+        assert_eq!(info.resolution(), "1280x720");
+        assert_eq!(info.matches_codecs("hev1.1.6.L93.90"), Some(true));
+        assert_eq!(info.matches_codecs("hvc1.2.4.L93.B0"), Some(false));
+    }
+
+    #[test]
+    fn keyframes() {
+        let start = |nals: &[&[u8]]| {
+            let data: Vec<u8> = nals.iter().flat_map(|n| [&[0, 0, 1][..], n].concat() ).collect();
+            starts_with_keyframe(VideoCodec::Avc, nal_units(&data))
+        };
+        assert_eq!(start(&[&[0x09, 0xf0], AVC_SPS, &[0x68, 0xeb], &[0x65, 0x88]]), Some(true));
+        assert_eq!(start(&[&[0x09, 0xf0], &[0x41, 0x9a]]), Some(false));
+        // a user data SEI message, followed by a recovery point
+        assert_eq!(start(&[&[0x06, 0x05, 0x01, 0xaa, 0x06, 0x02, 0x00, 0x00, 0x80], &[0x41, 0x9a]]), Some(true));
+        assert_eq!(start(&[&[0x09, 0xf0]]), None);
+        let sample = [0, 0, 0, 2, 0x40, 0x01, 0, 0, 0, 2, 0x26, 0x01];
+        assert_eq!(starts_with_keyframe(VideoCodec::Hevc, length_prefixed_nal_units(&sample, 4)), Some(true));
+        let sample = [0, 2, 0x02, 0x01];
+        assert_eq!(starts_with_keyframe(VideoCodec::Hevc, length_prefixed_nal_units(&sample, 2)), Some(false));
+    }
+
+    #[test]
+    fn avc_decoder_config() {
+        let avcc = [&[1, 0x64, 0x00, 0x1f, 0xff, 0xe1, 0, AVC_SPS.len() as u8][..], AVC_SPS, &[1, 0, 2, 0x68, 0xeb]].concat();
+        let params = parse_avc_decoder_config(&avcc).unwrap();
+        assert_eq!(params.sps.resolution(), "1920x1080");
+        assert_eq!(params.pps.len(), 1);
+    }
+}
@@ -29,23 +29,958 @@ async fn daterange_changed_attrs() {
     assert!(evt.is_some());
 }
 
+#[tokio::test]
+async fn part_durations_mismatch() {
+    let server = MockServer::start();
+    mock_playlists(&server, "part_durations_mismatch", &["main.m3u8", "video.m3u8"]);
+    let logger = TestLog::default();
+    let proc = create_test_client(&server, &logger);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::PartDurationsMismatchExtinf { msn: 21, .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::PartTargetDurationExceeded { msn: 22, part: 0, .. }) );
+    assert!(evt.is_some());
+}
+
+#[tokio::test]
+async fn blocking_reload_returned_early() {
+    let server = MockServer::start();
+    // registered first so that it takes precedence over the plain "/video.m3u8" mock below
+    server.mock(|when, then| {
+        when.path("/video.m3u8")
+            .query_param("_HLS_msn", "11");
+        then.status(200)
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/blocking_reload_returned_early/video_blocking.m3u8");
+    });
+    mock_playlists(&server, "blocking_reload_returned_early", &["main.m3u8", "video.m3u8"]);
+    let logger = TestLog::default();
+    let proc = create_test_client(&server, &logger);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::BlockingReloadReturnedEarly { requested_msn: 11, requested_part: None, .. }) );
+    assert!(evt.is_some());
+}
+
+#[tokio::test]
+async fn delta_update_skip_boundary_exceeded() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.path("/video.m3u8")
+            .query_param("_HLS_skip", "YES");
+        then.status(200)
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/delta_update_skip_boundary_exceeded/video_delta.m3u8");
+    });
+    mock_playlists(&server, "delta_update_skip_boundary_exceeded", &["main.m3u8", "video.m3u8"]);
+    let logger = TestLog::default();
+    let mut proc = create_test_client(&server, &logger);
+    proc.delta_updates(true);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::DeltaUpdateSkipBoundaryExceeded { skipped_segments: 5, remaining_duration_millis: 5000, .. }) );
+    assert!(evt.is_some());
+}
+
+#[tokio::test]
+async fn rendition_report_ahead() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.path("/video.m3u8")
+            .query_param("_HLS_msn", "11");
+        // give the audio playlist time to be loaded before this report on it is checked
+        then.status(200)
+            .delay(std::time::Duration::from_millis(200))
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/rendition_report_ahead/video_blocking.m3u8");
+    });
+    mock_playlists(&server, "rendition_report_ahead", &["main.m3u8", "video.m3u8", "audio.m3u8"]);
+    let logger = TestLog::default();
+    let proc = create_test_client(&server, &logger);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::RenditionReportAhead { report_msn: 20, seen_msn: 12, .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::RenditionReportBehind { .. }) );
+    assert!(evt.is_none());
+}
+
+#[tokio::test]
+async fn variant_misalignment() {
+    let server = MockServer::start();
+    mock_playlists(&server, "variant_misalignment", &["main.m3u8", "video_lo.m3u8", "video_hi.m3u8"]);
+    let logger = TestLog::default();
+    let proc = create_test_client(&server, &logger);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::VariantSegmentDurationMismatch { msn: 11, .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::VariantDiscontinuityMisaligned { msn: 12, .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::VariantProgramDateTimeMismatch { msn: 12, .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::VariantSegmentDurationMismatch { msn: 10, .. }) );
+    assert!(evt.is_none());
+}
+
+#[tokio::test]
+async fn program_date_time_changed() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.path("/video.m3u8")
+            .query_param("_HLS_msn", "12");
+        then.status(200)
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/program_date_time_changed/video_blocking.m3u8");
+    });
+    mock_playlists(&server, "program_date_time_changed", &["main.m3u8", "video.m3u8"]);
+    let logger = TestLog::default();
+    let proc = create_test_client(&server, &logger);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::ProgramDateTimeChanged { msn: 10, .. }) );
+    if let Some(strck::hls::HlsEvent::ProgramDateTimeChanged { delta, .. }) = evt {
+        // the lines holding the EXT-X-PROGRAM-DATE-TIME tag in each playlist
+        assert_eq!(delta.before().line(), Some(7));
+        assert_eq!(delta.after().line(), Some(7));
+    } else {
+        panic!("expected ProgramDateTimeChanged event");
+    }
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::ProgramDateTimeDrift { msn: 12, drift_millis: 4000, .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::MissingProgramDateTimeAfterDiscontinuity { msn: 13, .. }) );
+    assert!(evt.is_some());
+}
+
+#[tokio::test]
+async fn msn_gap() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.path("/video.m3u8")
+            .query_param("_HLS_msn", "12");
+        then.status(200)
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/msn_gap/video_blocking.m3u8");
+    });
+    mock_playlists(&server, "msn_gap", &["main.m3u8", "video.m3u8"]);
+    let logger = TestLog::default();
+    let proc = create_test_client(&server, &logger);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::MsnGap { last_msn: 11, next_msn: 14, missed_count: 2, .. }) );
+    assert!(evt.is_some());
+}
+
+#[tokio::test]
+async fn empty_media_playlist() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.path("/video.m3u8")
+            .query_param("_HLS_msn", "10");
+        then.status(200)
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/empty_media_playlist/video_blocking.m3u8");
+    });
+    mock_playlists(&server, "empty_media_playlist", &["main.m3u8", "video.m3u8"]);
+    let logger = TestLog::default();
+    let proc = create_test_client(&server, &logger);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::EmptyMediaPlaylist { .. }) );
+    assert!(evt.is_some());
+    // polling continued until segments were listed,
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::End { .. }) );
+    assert!(evt.is_some());
+}
+
+#[tokio::test]
+async fn missing_target_duration() {
+    let server = MockServer::start();
+    mock_playlists(&server, "missing_target_duration", &["main.m3u8", "video.m3u8"]);
+    let logger = TestLog::default();
+    let proc = create_test_client(&server, &logger);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::PlaylistSyntaxError { kind: "missing_target_duration", .. }) );
+    assert!(evt.is_some());
+}
+
+#[tokio::test]
+async fn event_playlist_segments_removed() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.path("/video.m3u8")
+            .query_param("_HLS_msn", "2");
+        then.status(200)
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/event_playlist_segments_removed/video_blocking.m3u8");
+    });
+    mock_playlists(&server, "event_playlist_segments_removed", &["main.m3u8", "video.m3u8"]);
+    let logger = TestLog::default();
+    let proc = create_test_client(&server, &logger);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::EventPlaylistMediaSequenceChanged { last_media_sequence: 0, this_media_sequence: 1, .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::EventPlaylistSegmentsRemoved { removed_count: 1, .. }) );
+    assert!(evt.is_some());
+}
+
+#[tokio::test]
+async fn vod_playlist() {
+    let server = MockServer::start();
+    mock_playlists(&server, "vod_playlist", &["main.m3u8", "video.m3u8"]);
+    let logger = TestLog::default();
+    let proc = create_test_client(&server, &logger);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::VodMissingEndList { .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::ExtinfExceedsTargetDuration { msn: 1, duration_millis: 2600, .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::ByteRangeNotContiguous { msn: 1, expected_offset: 1000, offset: 1200, .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::ByteRangeOffsetMissing { msn: 2, .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::ByteRangeInvalid { msn: 3, offset: u64::MAX, length: 10, .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::VersionTooLow { required_version: 4, .. }) );
+    if let Some(strck::hls::HlsEvent::VersionTooLow { at, .. }) = evt {
+        assert_eq!(at.line(), Some(8));
+    } else {
+        panic!("no VersionTooLow event");
+    }
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::VodPlaylistReport { segment_count: 4, total_duration_millis: 8600, problem_count: 6, .. }) );
+    assert!(evt.is_some());
+}
+
+#[tokio::test]
+async fn main_manifest_changed() {
+    let server = MockServer::start();
+    for video in &["video.m3u8", "video2.m3u8", "video3.m3u8"] {
+        server.mock(|when, then| {
+            when.path(format!("/{}", video))
+                .query_param("_HLS_msn", "11");
+            then.status(200)
+                .header("Content-Type", "application/vnd.apple.mpegurl")
+                .delay(std::time::Duration::from_millis(1500))
+                .body_from_file("tests/negative_tests/main_manifest_changed/video_blocking.m3u8");
+        });
+        server.mock(|when, then| {
+            when.path(format!("/{}", video));
+            then.status(200)
+                .header("Content-Type", "application/vnd.apple.mpegurl")
+                .body_from_file("tests/negative_tests/main_manifest_changed/video.m3u8");
+        });
+    }
+    let mut main = server.mock(|when, then| {
+        when.path("/main.m3u8");
+        then.status(200)
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/main_manifest_changed/main.m3u8");
+    });
+    let logger = TestLog::default();
+    let mut proc = create_test_client(&server, &logger);
+    proc.main_reload_interval(Some(std::time::Duration::from_millis(500)));
+    let change_main = async {
+        tokio::time::delay_for(std::time::Duration::from_millis(250)).await;
+        main.delete();
+        server.mock(|when, then| {
+            when.path("/main.m3u8");
+            then.status(200)
+                .header("Content-Type", "application/vnd.apple.mpegurl")
+                .body_from_file("tests/negative_tests/main_manifest_changed/main_changed.m3u8");
+        });
+    };
+    let (res, _) = futures::join!(proc.start(), change_main);
+    res.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::VariantBandwidthChanged { last_bandwidth: Some(202000), this_bandwidth: Some(300000), .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::VariantStreamRemoved { uri, .. } if uri == "video2.m3u8") );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::VariantStreamAdded { uri, .. } if uri == "video3.m3u8") );
+    assert!(evt.is_some());
+    // processing of the added variant was started, and of the removed variant was stopped
+    let ended: Vec<_> = events
+        .iter()
+        .filter_map(|e| match e {
+            strck::hls::HlsEvent::End { req_id } => Some(req_id.info().url.path().to_string()),
+            _ => None,
+        })
+        .collect();
+    assert!(ended.contains(&"/video3.m3u8".to_string()));
+    assert!(!ended.contains(&"/video2.m3u8".to_string()));
+}
+
+#[tokio::test]
+async fn main_manifest_problems() {
+    let server = MockServer::start();
+    for media in &["video.m3u8", "audio.m3u8", "audio_fr.m3u8", "subs.m3u8", "iframe.m3u8"] {
+        server.mock(|when, then| {
+            when.path(format!("/{}", media));
+            then.status(200)
+                .header("Content-Type", "application/vnd.apple.mpegurl")
+                .body_from_file("tests/negative_tests/main_manifest_problems/media.m3u8");
+        });
+    }
+    mock_playlists(&server, "main_manifest_problems", &["main.m3u8"]);
+    let logger = TestLog::default();
+    let proc = create_test_client(&server, &logger);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::UnresolvedGroupId { media_type: "AUDIO", group_id, .. } if group_id == "missing") );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::RenditionGroupMultipleDefaults { group_id, default_count: 2, .. } if group_id == "aud") );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::RenditionGroupWithoutDefault { group_id, .. } if group_id == "subs") );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::VariantMissingCodecs { at, .. } if at.line() == Some(8)) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::AverageBandwidthExceedsBandwidth { bandwidth: 202000, average_bandwidth: 300000, .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::IFrameStreamWithoutVariant { uri, .. } if uri == "iframe.m3u8") );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::DuplicateUri { at, first_line: 7, .. } if at.line() == Some(9)) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::ClosedCaptionsInconsistent { closed_captions: None, .. }) );
+    assert!(evt.is_some());
+}
+
+#[tokio::test]
+async fn segment_fetching() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.path("/video.m3u8")
+            .query_param("_HLS_msn", "11");
+        then.status(200)
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/segment_fetching/video_blocking.m3u8");
+    });
+    mock_playlists(&server, "segment_fetching", &["main.m3u8", "video.m3u8"]);
+    server.mock(|when, then| {
+        when.path("/10.ts");
+        then.status(200)
+            .header("Content-Type", "text/plain")
+            .body("segment");
+    });
+    server.mock(|when, then| {
+        when.path("/11.ts");
+        then.status(404);
+    });
+    server.mock(|when, then| {
+        when.path("/12.ts");
+        then.status(200)
+            .header("Content-Type", "video/mp2t")
+            .delay(std::time::Duration::from_millis(300))
+            .body("segment");
+    });
+    let mut not_yet_available = server.mock(|when, then| {
+        when.path("/13.ts");
+        then.status(404);
+    });
+    let logger = TestLog::default();
+    let mut proc = create_test_client(&server, &logger);
+    proc.fetch_segments(true);
+    let make_available = async {
+        tokio::time::delay_for(std::time::Duration::from_millis(150)).await;
+        not_yet_available.delete();
+        server.mock(|when, then| {
+            when.path("/13.ts");
+            then.status(200)
+                .header("Content-Type", "video/mp2t")
+                .body("segment");
+        });
+    };
+    let (res, _) = futures::join!(proc.start(), make_available);
+    res.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::SegmentIncorrectContentType { msn: 10, content_type: Some(t), .. } if t == "text/plain") );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::SegmentNotFound { msn: 11, attempts: 4, .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::SlowSegmentDownload { msn: 12, duration_millis: 200, .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::SegmentAvailableLate { msn: 13, .. }) );
+    assert!(evt.is_some());
+}
+
+#[tokio::test]
+async fn ts_segment_checks() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.path("/video.m3u8")
+            .query_param("_HLS_msn", "11");
+        then.status(200)
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/ts_segment_checks/video_blocking.m3u8");
+    });
+    mock_playlists(&server, "ts_segment_checks", &["main.m3u8", "video.m3u8"]);
+    for msn in 10..14 {
+        server.mock(|when, then| {
+            when.path(format!("/{}.ts", msn));
+            then.status(200)
+                .header("Content-Type", "video/mp2t")
+                .body_from_file(format!("tests/negative_tests/ts_segment_checks/{}.ts", msn));
+        });
+    }
+    let logger = TestLog::default();
+    let mut proc = create_test_client(&server, &logger);
+    proc.fetch_segments(true);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::TsStreamTypeNotInCodecs { pid: 0x102, stream_type: 0x0f, .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::TsContinuityError { msn: 11, pid: 0x101, expected: 5, actual: 9, .. }) );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::TsTimestampDiscontinuity { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::TsTimestampDiscontinuity { msn: 12, jump_millis: 1000, .. }));
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::TsMissingPatPmt { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::TsMissingPatPmt { msn: 13, .. }));
+}
+
+#[tokio::test]
+async fn video_parameter_checks() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.path("/video.m3u8")
+            .query_param("_HLS_msn", "11");
+        then.status(200)
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/video_parameter_checks/video_blocking.m3u8");
+    });
+    mock_playlists(&server, "video_parameter_checks", &["main.m3u8", "video.m3u8"]);
+    for msn in 10..14 {
+        server.mock(|when, then| {
+            when.path(format!("/{}.ts", msn));
+            then.status(200)
+                .header("Content-Type", "video/mp2t")
+                .body_from_file(format!("tests/negative_tests/video_parameter_checks/{}.ts", msn));
+        });
+    }
+    let logger = TestLog::default();
+    let mut proc = create_test_client(&server, &logger);
+    proc.fetch_segments(true);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    // reported once, though all segments have the same profile and level
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::VideoCodecsMismatch { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::VideoCodecsMismatch { codec, .. } if codec == "avc1.64001F"));
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::VideoResolutionMismatch { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::VideoResolutionMismatch { resolution, .. } if resolution == "1280x720"));
+    // the change back at segment 13 follows EXT-X-DISCONTINUITY
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::VideoParametersChanged { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::VideoParametersChanged { msn: 12, this_resolution, .. } if this_resolution == "1280x720"));
+    // segment 12 also carries a different picture parameter set with the same ID
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::VideoPictureParametersChanged { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::VideoPictureParametersChanged { msn: 12, pps_id: 0, .. }));
+}
+
+#[tokio::test]
+async fn independent_segments() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.path("/video.m3u8")
+            .query_param("_HLS_msn", "11");
+        then.status(200)
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/independent_segments/video_blocking.m3u8");
+    });
+    mock_playlists(&server, "independent_segments", &["main.m3u8", "video.m3u8"]);
+    for msn in 10..14 {
+        server.mock(|when, then| {
+            when.path(format!("/{}.ts", msn));
+            then.status(200)
+                .header("Content-Type", "video/mp2t")
+                .body_from_file(format!("tests/negative_tests/independent_segments/{}.ts", msn));
+        });
+    }
+    let logger = TestLog::default();
+    let mut proc = create_test_client(&server, &logger);
+    proc.fetch_segments(true);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::SegmentNotIndependent { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::SegmentNotIndependent { msn: 12, uri, .. } if uri.ends_with("/12.ts")));
+}
+
+#[tokio::test]
+async fn segment_bitrate() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.path("/audio.m3u8")
+            .query_param("_HLS_msn", "11");
+        then.status(200)
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/segment_bitrate/audio_blocking.m3u8");
+    });
+    mock_playlists(&server, "segment_bitrate", &["main.m3u8", "audio.m3u8"]);
+    for msn in 10..14 {
+        server.mock(|when, then| {
+            when.path(format!("/{}.aac", msn));
+            then.status(200)
+                .header("Content-Type", "audio/aac")
+                .body_from_file(format!("tests/negative_tests/segment_bitrate/{}.aac", msn));
+        });
+    }
+    let logger = TestLog::default();
+    let bitrates = TestMetric::default();
+    let mut proc = create_test_client(&server, &logger);
+    proc.fetch_segments(true);
+    let metric = bitrates.clone();
+    proc.variant_bitrate_metric(move |uri| {
+        assert_eq!(uri, "audio.m3u8");
+        metric.clone()
+    });
+    proc.start().await.unwrap();
+
+    let mut values = bitrates.values.borrow().clone();
+    values.sort();
+    assert_eq!(values, vec![5000, 5000, 5000, 20000]);
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::PeakBitrateExceedsBandwidth { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::PeakBitrateExceedsBandwidth { first_msn: 12, last_msn: 12, bitrate: 20000, bandwidth: 10000, .. }));
+    // reported once, though the average remains too high after the first report
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::AverageBitrateMismatch { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::AverageBitrateMismatch { average_bandwidth: 5000, .. }));
+}
+
+#[tokio::test]
+async fn segment_keys() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.path("/video.m3u8")
+            .query_param("_HLS_msn", "11");
+        then.status(200)
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/segment_keys/video_blocking.m3u8");
+    });
+    mock_playlists(&server, "segment_keys", &["main.m3u8", "video.m3u8"]);
+    for msn in 10..14 {
+        server.mock(|when, then| {
+            when.path(format!("/{}.ts", msn));
+            then.status(200)
+                .header("Content-Type", "video/mp2t")
+                .body_from_file(format!("tests/negative_tests/segment_keys/{}.ts", msn));
+        });
+    }
+    let mut key_mocks = vec![];
+    for key in &["k1.key", "k2.key", "bad.key"] {
+        key_mocks.push(server.mock(|when, then| {
+            when.path(format!("/{}", key));
+            then.status(200)
+                .header("Content-Type", "application/octet-stream")
+                .body_from_file(format!("tests/negative_tests/segment_keys/{}", key));
+        }));
+    }
+    let logger = TestLog::default();
+    let mut proc = create_test_client(&server, &logger);
+    proc.fetch_segments(true);
+    proc.start().await.unwrap();
+
+    // each key is fetched once, though used for several segments
+    for mock in &key_mocks {
+        mock.assert_hits(1);
+    }
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::KeyWrongLength { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::KeyWrongLength { length: 17, .. }));
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::SegmentDecryptionFailed { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::SegmentDecryptionFailed { msn: 11, .. }));
+    // segments 10 and 12 are decrypted and checked, 12 not starting with a key frame, while 13
+    // can't be decrypted without a valid key
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::SegmentNotIndependent { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::SegmentNotIndependent { msn: 12, .. }));
+    let rotated = events
+        .iter()
+        .filter_map(|e| match e {
+            strck::hls::HlsEvent::KeyRotated { msn, .. } => Some(*msn),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(rotated, vec![12, 13]);
+    // adding an explicit IV to the tag for segment 10 changes the key already seen for it
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::ManifestHistoryChangedKey { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::ManifestHistoryChangedKey { msn: 10, .. }));
+}
+
+#[tokio::test]
+async fn drm_keys() {
+    let server = MockServer::start();
+    mock_playlists(&server, "drm_keys", &["main.m3u8", "video_lo.m3u8", "video_hi.m3u8"]);
+    let logger = TestLog::default();
+    let proc = create_test_client(&server, &logger);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let count = |pred: &dyn Fn(&strck::hls::HlsEvent) -> bool| events.iter().filter(|e| pred(e) ).count();
+    // the FairPlay key of video_lo.m3u8 has a different METHOD to the EXT-X-SESSION-KEY with its URI
+    assert_eq!(count(&|e| matches!(e, strck::hls::HlsEvent::SessionKeyMismatch { .. })), 1);
+    assert_eq!(count(&|e| matches!(e, strck::hls::HlsEvent::KeyUriSchemeMismatch { expected_scheme: "skd", .. })), 1);
+    assert_eq!(count(&|e| matches!(e, strck::hls::HlsEvent::KeyPsshSystemIdMismatch { keyformat, .. } if keyformat == "com.microsoft.playready")), 1);
+    assert_eq!(count(&|e| matches!(e, strck::hls::HlsEvent::KeyIdNotInPssh { keyid, .. } if keyid == "0x00000000000000000000000000000002")), 1);
+    assert_eq!(count(&|e| matches!(e, strck::hls::HlsEvent::KeyPsshInvalid { .. })), 1);
+    // the variants differ in both segments, but each difference is reported once
+    assert_eq!(count(&|e| matches!(e, strck::hls::HlsEvent::VariantKeyFormatVersionsMismatch { msn: 10, .. })), 1);
+    assert_eq!(count(&|e| matches!(e, strck::hls::HlsEvent::VariantKeyIdMismatch { msn: 10, .. })), 1);
+}
+
+#[tokio::test]
+async fn scte35_checks() {
+    let server = MockServer::start();
+    mock_playlists(&server, "scte35_checks", &["main.m3u8", "video.m3u8"]);
+    let logger = TestLog::default();
+    let proc = create_test_client(&server, &logger);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let find = |pred: &dyn Fn(&strck::hls::HlsEvent) -> bool| events.iter().filter(|e| pred(e) ).collect::<Vec<_>>();
+    let evt = find(&|e| matches!(e, strck::hls::HlsEvent::Scte35DurationMismatch { .. }));
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::Scte35DurationMismatch { daterange_id, duration_attr: "PLANNED-DURATION", duration_millis: 20000, scte35_duration_millis: 30000, .. } if daterange_id == "ad1"));
+    let evt = find(&|e| matches!(e, strck::hls::HlsEvent::Scte35CrcMismatch { .. }));
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::Scte35CrcMismatch { daterange_id, .. } if daterange_id == "ad2"));
+    let evt = find(&|e| matches!(e, strck::hls::HlsEvent::Scte35EventIdReused { .. }));
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::Scte35EventIdReused { daterange_id, other_daterange_id, event_id: 1, .. } if daterange_id == "ad2" && other_daterange_id == "ad1"));
+    let evt = find(&|e| matches!(e, strck::hls::HlsEvent::Scte35InWithoutOut { .. }));
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::Scte35InWithoutOut { daterange_id, .. } if daterange_id == "ad3"));
+    let evt = find(&|e| matches!(e, strck::hls::HlsEvent::Scte35OutOfNetworkMismatch { .. }));
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::Scte35OutOfNetworkMismatch { daterange_id, attr_name: "SCTE35-IN", .. } if daterange_id == "ad3"));
+    let evt = find(&|e| matches!(e, strck::hls::HlsEvent::Scte35Invalid { .. }));
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::Scte35Invalid { daterange_id, attr_name: "SCTE35-CMD", .. } if daterange_id == "ad4"));
+    let evt = find(&|e| matches!(e, strck::hls::HlsEvent::Scte35InEventIdMismatch { .. }));
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::Scte35InEventIdMismatch { out_event_id: 5, in_event_id: 6, .. }));
+}
+
+#[tokio::test]
+async fn scte35_window() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.path("/video.m3u8")
+            .query_param("_HLS_msn", "12");
+        then.status(200)
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/scte35_window/video_blocking.m3u8");
+    });
+    mock_playlists(&server, "scte35_window", &["main.m3u8", "video.m3u8"]);
+    let logger = TestLog::default();
+    let proc = create_test_client(&server, &logger);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    // event ID 1 was last used by "ad1", which is no longer in the playlist, while "ad2" is
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::Scte35EventIdReused { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::Scte35EventIdReused { daterange_id, other_daterange_id, event_id: 2, .. } if daterange_id == "ad4" && other_daterange_id == "ad2"));
+}
+
+#[tokio::test]
+async fn daterange_rules() {
+    let server = MockServer::start();
+    mock_playlists(&server, "daterange_rules", &["main.m3u8", "video.m3u8"]);
+    let logger = TestLog::default();
+    let proc = create_test_client(&server, &logger);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let find = |pred: &dyn Fn(&strck::hls::HlsEvent) -> bool| events.iter().filter(|e| pred(e) ).collect::<Vec<_>>();
+    let evt = find(&|e| matches!(e, strck::hls::HlsEvent::DaterangeEndBeforeStart { .. }));
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::DaterangeEndBeforeStart { daterange_id, .. } if daterange_id == "r1"));
+    let evt = find(&|e| matches!(e, strck::hls::HlsEvent::DaterangeDurationMismatch { .. }));
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::DaterangeDurationMismatch { duration_millis: 12000, end_minus_start_millis: 10000, .. }));
+    let evt = find(&|e| matches!(e, strck::hls::HlsEvent::DaterangeEndOnNextWithoutClass { .. }));
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::DaterangeEndOnNextWithoutClass { daterange_id, .. } if daterange_id == "r3"));
+    let evt = find(&|e| matches!(e, strck::hls::HlsEvent::DaterangeEndOnNextWithEnd { .. }));
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::DaterangeEndOnNextWithEnd { daterange_id, duration: true, end_date: false, .. } if daterange_id == "r4"));
+    // a2 overlaps a1 once its DURATION is given, but a3 follows a1 and a2
+    let evt = find(&|e| matches!(e, strck::hls::HlsEvent::DaterangeOverlap { .. }));
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::DaterangeOverlap { daterange_id, other_daterange_id, .. } if daterange_id == "a2" && other_daterange_id == "a1"));
+    let evt = find(&|e| matches!(e, strck::hls::HlsEvent::DaterangeWithoutProgramDateTime { .. }));
+    assert_eq!(evt.len(), 1);
+}
+
+#[tokio::test]
+async fn fmp4_segment_checks() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.path("/video.m3u8")
+            .query_param("_HLS_msn", "11");
+        then.status(200)
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/fmp4_segment_checks/video_blocking.m3u8");
+    });
+    mock_playlists(&server, "fmp4_segment_checks", &["main.m3u8", "video.m3u8"]);
+    let init = server.mock(|when, then| {
+        when.path("/init.mp4");
+        then.status(200)
+            .header("Content-Type", "video/mp4")
+            .body_from_file("tests/negative_tests/fmp4_segment_checks/init.mp4");
+    });
+    for msn in 10..13 {
+        server.mock(|when, then| {
+            when.path(format!("/{}.m4s", msn));
+            then.status(200)
+                .header("Content-Type", "video/iso.segment")
+                .body_from_file(format!("tests/negative_tests/fmp4_segment_checks/{}.m4s", msn));
+        });
+    }
+    let logger = TestLog::default();
+    let mut proc = create_test_client(&server, &logger);
+    proc.fetch_segments(true);
+    proc.start().await.unwrap();
+
+    // the init segment is only fetched again when EXT-X-MAP changes
+    init.assert_hits(1);
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::Mp4DecodeTimeDiscontinuity { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::Mp4DecodeTimeDiscontinuity { msn: 11, track_id: 1, expected_decode_time: 200, decode_time: 250, .. }));
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::Mp4DurationMismatch { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::Mp4DurationMismatch { msn: 12, duration_millis: 400, extinf_millis: 200, .. }));
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::InitSegmentCodecsMismatch { track_id: 1, codec, .. } if codec == "avc1.64001F") );
+    assert!(evt.is_some());
+}
+
+#[tokio::test]
+async fn map_changes() {
+    let server = MockServer::start();
+    server.mock(|when, then| {
+        when.path("/video.m3u8")
+            .query_param("_HLS_msn", "11");
+        then.status(200)
+            .header("Content-Type", "application/vnd.apple.mpegurl")
+            .body_from_file("tests/negative_tests/map_changes/video_blocking.m3u8");
+    });
+    mock_playlists(&server, "map_changes", &["main.m3u8", "video.m3u8"]);
+    let logger = TestLog::default();
+    let proc = create_test_client(&server, &logger);
+    proc.start().await.unwrap();
+
+    let events = logger.events.borrow();
+    let evt = events
+        .iter()
+        .find(|e| matches!(e, strck::hls::HlsEvent::ManifestHistoryChangedMap { msn: 10, this_map: Some(m), .. } if m == "URI=\"init2.mp4\"") );
+    assert!(evt.is_some());
+    let evt = events
+        .iter()
+        .filter(|e| matches!(e, strck::hls::HlsEvent::MapChangedWithoutDiscontinuity { .. }) )
+        .collect::<Vec<_>>();
+    assert_eq!(evt.len(), 1);
+    assert!(matches!(evt[0], strck::hls::HlsEvent::MapChangedWithoutDiscontinuity { msn: 11, .. }));
+}
+
+/// serve each of the given files from the named test directory
+fn mock_playlists(server: &MockServer, test_name: &str, files: &[&str]) {
+    for file in files {
+        server.mock(|when, then| {
+            when.path(format!("/{}", file));
+            then.status(200)
+                .header("Content-Type", "application/vnd.apple.mpegurl")
+                .body_from_file(format!("tests/negative_tests/{}/{}", test_name, file));
+        });
+    }
+}
+
 fn create_test_client(server: &MockServer, logger: &TestLog) -> HlsProcessor<NullSnoop, TestLog, TestMetric> {
     let client = create_client();
     let proc = strck::hls::HlsProcessor::new(
         client,
         reqwest::Url::parse(&server.url("/main.m3u8")).unwrap(),
         logger.clone(),
-        TestMetric,
-        TestMetric,
-        TestMetric,
+        TestMetric::default(),
+        TestMetric::default(),
+        TestMetric::default(),
     );
     proc
 }
 
-#[derive(Clone)]
-struct TestMetric;
+#[derive(Clone, Default)]
+struct TestMetric {
+    values: Rc<RefCell<Vec<u64>>>,
+}
 impl strck::metric::Metric for TestMetric {
-    fn put(&mut self, _value: u64) { }
+    fn put(&mut self, value: u64) {
+        self.values.borrow_mut().push(value);
+    }
     fn close(self) { }
 }
 